@@ -0,0 +1,269 @@
+use core::hash::Hash;
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AnalogCurve {
+    #[default]
+    Linear,
+    Power(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnalogPolicy {
+    pub dead_zone: f64,
+    pub saturation: f64,
+    pub inverted: bool,
+    pub curve: AnalogCurve,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalogThreshold<Sw> {
+    pub switch: Sw,
+    pub press: f64,
+    pub release: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalogAxis<Sw> {
+    pub policy: AnalogPolicy,
+    pub thresholds: Vec<AnalogThreshold<Sw>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AnalogState<Ax, Sw> {
+    axes: HashMap<Ax, AnalogAxis<Sw>>,
+    values: HashMap<Ax, f64>,
+    pressed: HashSet<Sw>,
+}
+
+// Axis configuration is not a part of the snapshot.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AnalogStateSnapshot<Ax, Sw> {
+    pub values: Vec<(Ax, f64)>,
+    pub pressed: Vec<Sw>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalogEventData<Sw> {
+    pub value: f64,
+    pub pressed: Vec<Sw>,
+    pub released: Vec<Sw>,
+}
+
+impl AnalogCurve {
+    #[must_use]
+    pub fn apply(self, value: f64) -> f64 {
+        match self {
+            Self::Linear => value,
+            Self::Power(exponent) => value.powf(exponent),
+        }
+    }
+}
+
+impl Default for AnalogPolicy {
+    fn default() -> Self {
+        Self {
+            dead_zone: 0.0,
+            saturation: 1.0,
+            inverted: false,
+            curve: AnalogCurve::Linear,
+        }
+    }
+}
+
+impl AnalogPolicy {
+    #[must_use]
+    pub const fn with_dead_zone(mut self, dead_zone: f64) -> Self {
+        self.dead_zone = dead_zone;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_saturation(mut self, saturation: f64) -> Self {
+        self.saturation = saturation;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_curve(mut self, curve: AnalogCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    // Maps a raw value in `-1.0..=1.0` so that the dead zone reads as zero
+    // and the saturation as full deflection, keeping the sign.
+    #[must_use]
+    pub fn apply(&self, raw: f64) -> f64 {
+        let magnitude = raw.abs();
+        if magnitude <= self.dead_zone {
+            return 0.0;
+        }
+        let range = self.saturation - self.dead_zone;
+        let magnitude = if range > 0.0 {
+            ((magnitude - self.dead_zone) / range).min(1.0)
+        } else {
+            1.0
+        };
+        let value = self.curve.apply(magnitude).copysign(raw);
+        if self.inverted {
+            -value
+        } else {
+            value
+        }
+    }
+}
+
+impl<Sw> AnalogThreshold<Sw> {
+    // A negative `press` makes the switch fire on the negative half of the axis.
+    // `release` is expected to be closer to zero to avoid chattering.
+    pub const fn new(switch: Sw, press: f64, release: f64) -> Self {
+        Self {
+            switch,
+            press,
+            release,
+        }
+    }
+
+    pub fn is_pressed(&self, value: f64, was_pressed: bool) -> bool {
+        match (self.press >= 0.0, was_pressed) {
+            (true, false) => value >= self.press,
+            (true, true) => value > self.release,
+            (false, false) => value <= self.press,
+            (false, true) => value < self.release,
+        }
+    }
+}
+
+impl<Sw> AnalogAxis<Sw> {
+    #[must_use]
+    pub const fn new(policy: AnalogPolicy) -> Self {
+        Self {
+            policy,
+            thresholds: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_threshold(mut self, threshold: AnalogThreshold<Sw>) -> Self {
+        self.thresholds.push(threshold);
+        self
+    }
+}
+
+impl<Ax, Sw> Default for AnalogState<Ax, Sw> {
+    fn default() -> Self {
+        Self {
+            axes: HashMap::new(),
+            values: HashMap::new(),
+            pressed: HashSet::new(),
+        }
+    }
+}
+
+impl<Ax, Sw> AnalogState<Ax, Sw> {
+    #[must_use]
+    pub fn with_axis(mut self, axis: Ax, config: AnalogAxis<Sw>) -> Self
+    where
+        Ax: Eq + Hash,
+    {
+        let _ = self.axes.insert(axis, config);
+        self
+    }
+
+    pub fn axis(&self, axis: &Ax) -> Option<&AnalogAxis<Sw>>
+    where
+        Ax: Eq + Hash,
+    {
+        self.axes.get(axis)
+    }
+
+    pub fn value(&self, axis: &Ax) -> f64
+    where
+        Ax: Eq + Hash,
+    {
+        self.values.get(axis).copied().unwrap_or_default()
+    }
+
+    pub fn is_pressed(&self, switch: &Sw) -> bool
+    where
+        Sw: Eq + Hash,
+    {
+        self.pressed.contains(switch)
+    }
+
+    #[must_use]
+    pub fn snapshot(&self) -> AnalogStateSnapshot<Ax, Sw>
+    where
+        Ax: Clone,
+        Sw: Clone,
+    {
+        AnalogStateSnapshot {
+            values: self
+                .values
+                .iter()
+                .map(|(axis, value)| (axis.clone(), *value))
+                .collect(),
+            pressed: self.pressed.iter().cloned().collect(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: AnalogStateSnapshot<Ax, Sw>)
+    where
+        Ax: Eq + Hash,
+        Sw: Eq + Hash,
+    {
+        self.values = snapshot.values.into_iter().collect();
+        self.pressed = snapshot.pressed.into_iter().collect();
+    }
+
+    // Axes without a configuration use the default policy and have no thresholds.
+    pub fn on_value_event(&mut self, axis: Ax, raw: f64) -> AnalogEventData<Sw>
+    where
+        Ax: Eq + Hash,
+        Sw: Clone + Eq + Hash,
+    {
+        let mut pressed = Vec::new();
+        let mut released = Vec::new();
+        let value = match self.axes.get(&axis) {
+            Some(config) => {
+                let value = config.policy.apply(raw);
+                for threshold in &config.thresholds {
+                    let was_pressed = self.pressed.contains(&threshold.switch);
+                    match (was_pressed, threshold.is_pressed(value, was_pressed)) {
+                        (false, true) => {
+                            let _ = self.pressed.insert(threshold.switch.clone());
+                            pressed.push(threshold.switch.clone());
+                        }
+                        (true, false) => {
+                            let _ = self.pressed.remove(&threshold.switch);
+                            released.push(threshold.switch.clone());
+                        }
+                        _ => {}
+                    }
+                }
+                value
+            }
+            None => AnalogPolicy::default().apply(raw),
+        };
+        let _ = self.values.insert(axis, value);
+        AnalogEventData {
+            value,
+            pressed,
+            released,
+        }
+    }
+
+    // Returns the switches that were held by the thresholds.
+    pub fn reset(&mut self) -> Vec<Sw> {
+        self.values.clear();
+        self.pressed.drain().collect()
+    }
+}
@@ -0,0 +1,101 @@
+use core::hash::Hash;
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug)]
+pub struct AxisState<Ax> {
+    accumulated: HashMap<Ax, (f64, f64)>,
+    step: f64,
+}
+
+// Deltas not yet turned into steps.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AxisStateSnapshot<Ax> {
+    pub accumulated: Vec<(Ax, (f64, f64))>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, PartialOrd, Serialize)]
+pub struct AxisEventData {
+    pub delta: (f64, f64),
+    pub steps: (i32, i32),
+}
+
+impl<Ax> AxisState<Ax> {
+    // `step` is the delta of one wheel notch, touchpads report fractions of it.
+    #[must_use]
+    pub fn new(step: f64) -> Self {
+        Self {
+            accumulated: HashMap::new(),
+            step,
+        }
+    }
+
+    #[must_use]
+    pub const fn step(&self) -> f64 {
+        self.step
+    }
+
+    pub const fn set_step(&mut self, step: f64) {
+        self.step = step;
+    }
+
+    pub fn accumulated(&self, axis: &Ax) -> (f64, f64)
+    where
+        Ax: Eq + Hash,
+    {
+        self.accumulated.get(axis).copied().unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn snapshot(&self) -> AxisStateSnapshot<Ax>
+    where
+        Ax: Clone,
+    {
+        let accumulated = self
+            .accumulated
+            .iter()
+            .map(|(axis, accumulated)| (axis.clone(), *accumulated))
+            .collect();
+        AxisStateSnapshot { accumulated }
+    }
+
+    pub fn restore(&mut self, snapshot: AxisStateSnapshot<Ax>)
+    where
+        Ax: Eq + Hash,
+    {
+        self.accumulated = snapshot.accumulated.into_iter().collect();
+    }
+
+    pub fn on_axis_event(&mut self, axis: Ax, delta: (f64, f64)) -> AxisEventData
+    where
+        Ax: Eq + Hash,
+    {
+        let step = self.step;
+        let accumulated = self.accumulated.entry(axis).or_default();
+        let steps = (
+            accumulate(&mut accumulated.0, delta.0, step),
+            accumulate(&mut accumulated.1, delta.1, step),
+        );
+        AxisEventData { delta, steps }
+    }
+
+    pub fn reset(&mut self) {
+        self.accumulated.clear();
+    }
+}
+
+// Reversing the direction drops what is left of the previous one.
+#[allow(clippy::cast_possible_truncation)]
+fn accumulate(accumulated: &mut f64, delta: f64, step: f64) -> i32 {
+    if *accumulated * delta < 0.0 {
+        *accumulated = 0.0;
+    }
+    *accumulated += delta;
+    if step <= 0.0 {
+        return 0;
+    }
+    let steps = (*accumulated / step).trunc();
+    *accumulated = steps.mul_add(-step, *accumulated);
+    steps as i32
+}
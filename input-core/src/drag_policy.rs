@@ -0,0 +1,66 @@
+use core::ops::Add;
+
+use crate::Coords2d;
+
+pub trait CoordsDistance {
+    fn distance(&self, other: &Self) -> f64;
+
+    // Only coords with axes have a direction to estimate velocities from.
+    fn displacement(&self, _other: &Self) -> Option<(f64, f64)> {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DragPolicy<Du> {
+    pub distance: f64,
+    pub hysteresis: f64,
+    pub duration: Option<Du>,
+}
+
+impl<Co> CoordsDistance for Co
+where
+    Co: Coords2d,
+{
+    fn distance(&self, other: &Self) -> f64 {
+        (other.x() - self.x()).hypot(other.y() - self.y())
+    }
+
+    fn displacement(&self, other: &Self) -> Option<(f64, f64)> {
+        Some((other.x() - self.x(), other.y() - self.y()))
+    }
+}
+
+impl<Du> DragPolicy<Du> {
+    pub const fn new(distance: f64, hysteresis: f64, duration: Option<Du>) -> Self {
+        Self {
+            distance,
+            hysteresis,
+            duration,
+        }
+    }
+
+    // Once beyond the threshold, the pointer has to come back by `hysteresis`
+    // to be considered inside again. Matters only while `duration` holds the drag back.
+    pub fn is_beyond(&self, distance: f64, was_beyond: bool) -> bool {
+        if was_beyond {
+            distance >= self.distance - self.hysteresis
+        } else {
+            distance >= self.distance
+        }
+    }
+
+    pub fn drag_deadline<Ti>(&self, time: Ti) -> Option<Ti>
+    where
+        Ti: Add<Du, Output = Ti>,
+        Du: Clone,
+    {
+        self.duration.clone().map(|duration| time + duration)
+    }
+}
+
+impl<Du> Default for DragPolicy<Du> {
+    fn default() -> Self {
+        Self::new(0.0, 0.0, None)
+    }
+}
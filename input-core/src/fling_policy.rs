@@ -0,0 +1,80 @@
+// Time points and durations as a number of units, velocities are measured
+// in coords per unit.
+pub trait TimeValue {
+    fn as_f64(&self) -> f64;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlingPolicy<Du> {
+    // Only the samples this close to the release count for the velocity.
+    pub window: Du,
+    pub min_speed: f64,
+    pub kinetic: Option<KineticPolicy<Du>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KineticPolicy<Du> {
+    pub interval: Du,
+    // Share of the velocity kept after each interval.
+    pub friction: f64,
+    pub min_speed: f64,
+}
+
+// Timestamps in milliseconds are far below the precision limit of `f64`.
+#[allow(clippy::cast_precision_loss)]
+impl TimeValue for i64 {
+    fn as_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+impl TimeValue for u64 {
+    fn as_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+
+impl TimeValue for i32 {
+    fn as_f64(&self) -> f64 {
+        f64::from(*self)
+    }
+}
+
+impl TimeValue for u32 {
+    fn as_f64(&self) -> f64 {
+        f64::from(*self)
+    }
+}
+
+impl TimeValue for f64 {
+    fn as_f64(&self) -> f64 {
+        *self
+    }
+}
+
+impl<Du> FlingPolicy<Du> {
+    pub const fn new(window: Du, min_speed: f64) -> Self {
+        Self {
+            window,
+            min_speed,
+            kinetic: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_kinetic(mut self, kinetic: KineticPolicy<Du>) -> Self {
+        self.kinetic = Some(kinetic);
+        self
+    }
+}
+
+impl<Du> KineticPolicy<Du> {
+    pub const fn new(interval: Du, friction: f64, min_speed: f64) -> Self {
+        Self {
+            interval,
+            friction,
+            min_speed,
+        }
+    }
+}
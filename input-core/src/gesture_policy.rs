@@ -0,0 +1,42 @@
+// A gesture kind starts once its change since the pointers went down passes
+// the threshold, and then follows every change until the gesture ends.
+// `scale` is relative, e.g. `0.1` for the span changing by 10%, and `angle`
+// is in radians.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GesturePolicy {
+    pub distance: f64,
+    pub scale: f64,
+    pub angle: f64,
+}
+
+impl GesturePolicy {
+    #[must_use]
+    pub const fn new(distance: f64, scale: f64, angle: f64) -> Self {
+        Self {
+            distance,
+            scale,
+            angle,
+        }
+    }
+
+    #[must_use]
+    pub fn is_pinch(&self, scale: f64) -> bool {
+        (scale - 1.0).abs() >= self.scale
+    }
+
+    #[must_use]
+    pub fn is_pan(&self, translation: (f64, f64)) -> bool {
+        translation.0.hypot(translation.1) >= self.distance
+    }
+
+    #[must_use]
+    pub fn is_rotate(&self, angle: f64) -> bool {
+        angle.abs() >= self.angle
+    }
+}
+
+impl Default for GesturePolicy {
+    fn default() -> Self {
+        Self::new(0.0, 0.0, 0.0)
+    }
+}
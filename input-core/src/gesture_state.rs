@@ -0,0 +1,383 @@
+use core::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::GesturePolicy;
+
+pub trait Coords2d {
+    fn x(&self) -> f64;
+    fn y(&self) -> f64;
+}
+
+#[derive(Clone, Debug)]
+pub struct GestureState<Sw, Co> {
+    pointers: Vec<(Sw, Co)>,
+    // Whether the first pointer went down alone and is still held.
+    has_primary: bool,
+    primary_switch: Option<Sw>,
+    policy: GesturePolicy,
+    pinch: GestureBase,
+    pan: GestureBase,
+    rotate: GestureBase,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct GestureStateSnapshot<Sw, Co> {
+    pub pointers: Vec<(Sw, Co)>,
+    #[serde(default)]
+    pub has_primary: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum GestureEventKind {
+    PinchStart,
+    Pinch,
+    PinchEnd,
+    Pan,
+    Rotate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct GestureEventData {
+    pub kind: GestureEventKind,
+    pub scale: f64,
+    pub translation: (f64, f64),
+    pub angle: f64,
+}
+
+// All pointers count: the center is their centroid, the distance is their mean
+// distance to it and the angles are theirs around it.
+#[derive(Clone, Debug, PartialEq)]
+struct GestureSpan {
+    center: (f64, f64),
+    distance: f64,
+    angles: Vec<f64>,
+}
+
+// The span a kind is measured from, taken again when the pointers change.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct GestureBase {
+    span: Option<GestureSpan>,
+    is_recognized: bool,
+}
+
+impl GestureEventData {
+    #[must_use]
+    pub const fn new(kind: GestureEventKind) -> Self {
+        Self {
+            kind,
+            scale: 1.0,
+            translation: (0.0, 0.0),
+            angle: 0.0,
+        }
+    }
+}
+
+impl<Sw, Co> GestureState<Sw, Co> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub const fn with_policy(mut self, policy: GesturePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub const fn policy(&self) -> &GesturePolicy {
+        &self.policy
+    }
+
+    pub const fn set_policy(&mut self, policy: GesturePolicy) {
+        self.policy = policy;
+    }
+
+    // Bindings on the primary switch match the touch that started a gesture,
+    // whatever its id, until it lifts.
+    #[must_use]
+    pub fn with_primary_switch(mut self, switch: Sw) -> Self {
+        self.primary_switch = Some(switch);
+        self
+    }
+
+    pub const fn primary_switch(&self) -> Option<&Sw> {
+        self.primary_switch.as_ref()
+    }
+
+    pub fn set_primary_switch(&mut self, switch: Option<Sw>) {
+        self.primary_switch = switch;
+    }
+
+    pub fn primary(&self) -> Option<&(Sw, Co)> {
+        self.pointers.first().filter(|_| self.has_primary)
+    }
+
+    // The switch a touch is handled as: the primary switch for the primary
+    // touch, its own id otherwise.
+    pub fn touch_switch(&self, switch: &Sw) -> Sw
+    where
+        Sw: Clone + Eq,
+    {
+        match (self.primary(), &self.primary_switch) {
+            (Some((primary, _)), Some(primary_switch)) if primary == switch => {
+                primary_switch.clone()
+            }
+            _ => switch.clone(),
+        }
+    }
+
+    pub fn coords(&self, switch: &Sw) -> Option<&Co>
+    where
+        Sw: Eq,
+    {
+        self.pointers
+            .iter()
+            .find(|(pointer, _)| pointer == switch)
+            .map(|(_, coords)| coords)
+    }
+
+    pub fn snapshot(&self) -> GestureStateSnapshot<Sw, Co>
+    where
+        Sw: Clone,
+        Co: Clone,
+    {
+        GestureStateSnapshot {
+            pointers: self.pointers.clone(),
+            has_primary: self.has_primary,
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: GestureStateSnapshot<Sw, Co>) {
+        self.pointers = snapshot.pointers;
+        self.has_primary = snapshot.has_primary;
+        self.pinch = GestureBase::default();
+        self.pan = GestureBase::default();
+        self.rotate = GestureBase::default();
+    }
+
+    pub fn pointers(&self) -> &[(Sw, Co)] {
+        &self.pointers
+    }
+
+    pub const fn num_pointers(&self) -> usize {
+        self.pointers.len()
+    }
+
+    pub const fn is_active(&self) -> bool {
+        self.pointers.len() >= 2
+    }
+
+    /// # Errors
+    ///
+    /// Returns [`GesturePressError::AlreadyPressed`] if the switch is already pressed.
+    pub fn on_press_event(
+        &mut self,
+        switch: Sw,
+        coords: Co,
+    ) -> Result<Option<GestureEventData>, GesturePressError>
+    where
+        Sw: Eq,
+    {
+        if self.pointers.iter().any(|(pointer, _)| *pointer == switch) {
+            return Err(GesturePressError::AlreadyPressed);
+        }
+
+        self.has_primary |= self.pointers.is_empty();
+        self.pointers.push((switch, coords));
+        self.rebase();
+        if self.pointers.len() == 2 {
+            Ok(Some(GestureEventData::new(GestureEventKind::PinchStart)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Returns [`GestureReleaseError::AlreadyReleased`] if the switch is not pressed.
+    pub fn on_release_event(
+        &mut self,
+        switch: &Sw,
+    ) -> Result<Option<GestureEventData>, GestureReleaseError>
+    where
+        Sw: Eq,
+    {
+        let idx = self
+            .pointers
+            .iter()
+            .position(|(pointer, _)| pointer == switch)
+            .ok_or(GestureReleaseError::AlreadyReleased)?;
+
+        let _ = self.pointers.remove(idx);
+        self.has_primary &= idx != 0;
+        self.rebase();
+        if self.pointers.len() == 1 {
+            Ok(Some(GestureEventData::new(GestureEventKind::PinchEnd)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn on_move_event(&mut self, switch: &Sw, coords: Co) -> Vec<GestureEventData>
+    where
+        Sw: Eq,
+        Co: Coords2d,
+    {
+        let idx = self
+            .pointers
+            .iter()
+            .position(|(pointer, _)| pointer == switch);
+        let Some(idx) = idx else {
+            return vec![];
+        };
+
+        let prev = self.span();
+        self.pointers[idx].1 = coords;
+        let next = self.span();
+
+        let (Some(prev), Some(next)) = (prev, next) else {
+            return vec![];
+        };
+
+        let mut events = vec![];
+        let base = self.pinch.span.get_or_insert_with(|| prev.clone());
+        if base.distance > 0.0 {
+            let scale = next.distance / base.distance;
+            if (scale - 1.0).abs() > f64::EPSILON
+                && (self.pinch.is_recognized || self.policy.is_pinch(scale))
+            {
+                self.pinch.recognize(&next);
+                events.push(GestureEventData {
+                    scale,
+                    ..GestureEventData::new(GestureEventKind::Pinch)
+                });
+            }
+        }
+        let base = self.pan.span.get_or_insert_with(|| prev.clone());
+        let translation = (next.center.0 - base.center.0, next.center.1 - base.center.1);
+        if translation != (0.0, 0.0) && (self.pan.is_recognized || self.policy.is_pan(translation))
+        {
+            self.pan.recognize(&next);
+            events.push(GestureEventData {
+                translation,
+                ..GestureEventData::new(GestureEventKind::Pan)
+            });
+        }
+        let base = self.rotate.span.get_or_insert(prev);
+        let angle = next.angle_from(base);
+        if angle != 0.0 && (self.rotate.is_recognized || self.policy.is_rotate(angle)) {
+            self.rotate.recognize(&next);
+            events.push(GestureEventData {
+                angle,
+                ..GestureEventData::new(GestureEventKind::Rotate)
+            });
+        }
+        events
+    }
+
+    // Kinds stay recognized while the gesture goes on with other pointers.
+    fn rebase(&mut self) {
+        let is_active = self.is_active();
+        for base in [&mut self.pinch, &mut self.pan, &mut self.rotate] {
+            base.span = None;
+            base.is_recognized &= is_active;
+        }
+    }
+
+    fn span(&self) -> Option<GestureSpan>
+    where
+        Co: Coords2d,
+    {
+        if self.pointers.len() < 2 {
+            return None;
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let len = self.pointers.len() as f64;
+        let (x, y) = self
+            .pointers
+            .iter()
+            .fold((0.0, 0.0), |(x, y), (_, coords)| {
+                (x + coords.x(), y + coords.y())
+            });
+        let center = (x / len, y / len);
+        let offsets = self
+            .pointers
+            .iter()
+            .map(|(_, coords)| (coords.x() - center.0, coords.y() - center.1));
+        Some(GestureSpan {
+            center,
+            distance: offsets.clone().map(|(dx, dy)| dx.hypot(dy)).sum::<f64>() / len,
+            angles: offsets.map(|(dx, dy)| dy.atan2(dx)).collect(),
+        })
+    }
+}
+
+impl GestureSpan {
+    // The mean turn of the pointers around the center.
+    fn angle_from(&self, base: &Self) -> f64 {
+        #[allow(clippy::cast_precision_loss)]
+        let len = self.angles.len() as f64;
+        self.angles
+            .iter()
+            .zip(&base.angles)
+            .map(|(angle, base)| normalize_angle(angle - base))
+            .sum::<f64>()
+            / len
+    }
+}
+
+impl GestureBase {
+    fn recognize(&mut self, span: &GestureSpan) {
+        self.span = Some(span.clone());
+        self.is_recognized = true;
+    }
+}
+
+impl<Sw, Co> Default for GestureState<Sw, Co> {
+    fn default() -> Self {
+        Self {
+            pointers: Vec::new(),
+            has_primary: false,
+            primary_switch: None,
+            policy: GesturePolicy::default(),
+            pinch: GestureBase::default(),
+            pan: GestureBase::default(),
+            rotate: GestureBase::default(),
+        }
+    }
+}
+
+impl Coords2d for (f64, f64) {
+    fn x(&self) -> f64 {
+        self.0
+    }
+
+    fn y(&self) -> f64 {
+        self.1
+    }
+}
+
+fn normalize_angle(angle: f64) -> f64 {
+    if angle > PI {
+        2.0_f64.mul_add(-PI, angle)
+    } else if angle <= -PI {
+        2.0_f64.mul_add(PI, angle)
+    } else {
+        angle
+    }
+}
+
+#[derive(Clone, Copy, Debug, Error)]
+pub enum GesturePressError {
+    #[error("Pointer is pressed while in Pressed state")]
+    AlreadyPressed,
+}
+
+#[derive(Clone, Copy, Debug, Error)]
+pub enum GestureReleaseError {
+    #[error("Pointer is released while in Released state")]
+    AlreadyReleased,
+}
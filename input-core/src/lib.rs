@@ -23,20 +23,42 @@
 )]
 #![allow(clippy::module_name_repetitions)]
 
+mod analog_state;
+mod axis_state;
 mod coords_state;
+mod drag_policy;
+mod fling_policy;
+mod gesture_policy;
+mod gesture_state;
 mod modifiers;
+mod pen_state;
 mod pointer_state;
+mod repeat_state;
 //mod result_with_context;
 mod scheduler;
+mod sequence_state;
+mod text_input_state;
 //mod timed_event_ext;
+mod timed_policy;
 mod timed_state;
 
+pub use analog_state::*;
+pub use axis_state::*;
 pub use coords_state::*;
+pub use drag_policy::*;
+pub use fling_policy::*;
+pub use gesture_policy::*;
+pub use gesture_state::*;
 pub use modifiers::*;
+pub use pen_state::*;
 pub use pointer_state::*;
+pub use repeat_state::*;
 //pub use result_with_context::*;
 pub use scheduler::*;
+pub use sequence_state::*;
+pub use text_input_state::*;
 //pub use timed_event_ext::*;
+pub use timed_policy::*;
 pub use timed_state::*;
 
 /*
@@ -1,28 +1,86 @@
 use core::borrow::Borrow;
-use core::hash::Hash;
-use std::collections::BTreeSet;
+use core::cmp::Ordering;
+use core::hash::{Hash, Hasher};
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use thiserror::Error;
 
-#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Clone, Debug)]
 pub struct Modifiers<Sw> {
     switches: Arc<BTreeSet<Sw>>,
+    latches: Arc<BTreeMap<Sw, LatchState>>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ModifierKind {
+    #[default]
+    Momentary,
+    Lock,
+    Latch,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+enum LatchState {
+    Held { is_used: bool },
+    Latched { is_used: bool },
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ModifiersSnapshot<Sw> {
+    switches: Vec<Sw>,
+    latches: Vec<(Sw, LatchState)>,
+}
+
+// Groups of switches that can stand in for an alias, e.g. Ctrl = LCtrl | RCtrl.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ModifierAliases<Sw> {
+    aliases: BTreeMap<Sw, BTreeSet<Sw>>,
 }
 
 impl<Sw> Modifiers<Sw> {
+    #[must_use]
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn switches(&self) -> &Arc<BTreeSet<Sw>> {
+    #[must_use]
+    pub const fn switches(&self) -> &Arc<BTreeSet<Sw>> {
         &self.switches
     }
 
+    #[must_use]
     pub fn into_switches(self) -> Arc<BTreeSet<Sw>> {
         self.switches
     }
 
+    #[must_use]
+    pub fn snapshot(&self) -> ModifiersSnapshot<Sw>
+    where
+        Sw: Clone,
+    {
+        ModifiersSnapshot {
+            switches: self.switches.iter().cloned().collect(),
+            latches: self
+                .latches
+                .iter()
+                .map(|(switch, state)| (switch.clone(), *state))
+                .collect(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: ModifiersSnapshot<Sw>)
+    where
+        Sw: Ord,
+    {
+        self.switches = Arc::new(snapshot.switches.into_iter().collect());
+        self.latches = Arc::new(snapshot.latches.into_iter().collect());
+    }
+
+    /// # Errors
+    ///
+    /// Returns [`ModifiersPressError::AlreadyPressed`] if the switch is already pressed.
     pub fn on_press_event(&mut self, switch: Sw) -> Result<(), ModifiersPressError>
     where
         Sw: Clone + Eq + Hash + Ord,
@@ -37,6 +95,9 @@ impl<Sw> Modifiers<Sw> {
         }
     }
 
+    /// # Errors
+    ///
+    /// Returns [`ModifiersReleaseError::AlreadyReleased`] if the switch is not pressed.
     pub fn on_release_event(&mut self, switch: &Sw) -> Result<(), ModifiersReleaseError>
     where
         Sw: Clone + Eq + Hash + Ord,
@@ -49,19 +110,337 @@ impl<Sw> Modifiers<Sw> {
             Err(ModifiersReleaseError::AlreadyReleased)
         }
     }
+
+    pub fn on_lock_press_event(&mut self, switch: Sw) -> bool
+    where
+        Sw: Clone + Eq + Hash + Ord,
+    {
+        let is_locked = !self.switches.contains(&switch);
+        self.set_locked(switch, is_locked);
+        is_locked
+    }
+
+    // Lets the host sync lock state on startup and after focus changes.
+    pub fn set_locked(&mut self, switch: Sw, is_locked: bool)
+    where
+        Sw: Clone + Eq + Hash + Ord,
+    {
+        let switches = Arc::make_mut(&mut self.switches);
+        if is_locked {
+            let _ = switches.insert(switch);
+        } else {
+            let _ = switches.remove(&switch);
+        }
+    }
+
+    // A latch released without being used stays pressed for the next switch.
+    // Pressing it again while latched cancels it on release.
+    pub fn on_latch_press_event(&mut self, switch: Sw)
+    where
+        Sw: Clone + Eq + Hash + Ord,
+    {
+        let latches = Arc::make_mut(&mut self.latches);
+        let is_used = matches!(latches.get(&switch), Some(LatchState::Latched { .. }));
+        let _ = latches.insert(switch.clone(), LatchState::Held { is_used });
+        let _ = Arc::make_mut(&mut self.switches).insert(switch);
+    }
+
+    /// # Errors
+    ///
+    /// Returns [`ModifiersReleaseError::AlreadyReleased`] if the switch is not held.
+    pub fn on_latch_release_event(&mut self, switch: &Sw) -> Result<(), ModifiersReleaseError>
+    where
+        Sw: Clone + Eq + Hash + Ord,
+    {
+        let latches = Arc::make_mut(&mut self.latches);
+        match latches.get_mut(switch) {
+            Some(state @ LatchState::Held { is_used: false }) => {
+                *state = LatchState::Latched { is_used: false };
+                Ok(())
+            }
+            Some(LatchState::Held { is_used: true }) => {
+                let _ = latches.remove(switch);
+                let _ = Arc::make_mut(&mut self.switches).remove(switch);
+                Ok(())
+            }
+            Some(LatchState::Latched { .. }) | None => Err(ModifiersReleaseError::AlreadyReleased),
+        }
+    }
+
+    // Called once a non-modifier switch is pressed with the latches applied.
+    pub fn use_latches(&mut self)
+    where
+        Sw: Clone + Eq + Hash + Ord,
+    {
+        if self.latches.is_empty() {
+            return;
+        }
+        for state in Arc::make_mut(&mut self.latches).values_mut() {
+            match state {
+                LatchState::Held { is_used } | LatchState::Latched { is_used } => *is_used = true,
+            }
+        }
+    }
+
+    // Called once a non-modifier switch is released, so that its release
+    // and clicks still see the used latches.
+    pub fn consume_latches(&mut self)
+    where
+        Sw: Clone + Eq + Hash + Ord,
+    {
+        if self.latches.is_empty() {
+            return;
+        }
+        let latches = Arc::make_mut(&mut self.latches);
+        let switches = Arc::make_mut(&mut self.switches);
+        latches.retain(|switch, state| match state {
+            LatchState::Latched { is_used: true } => {
+                let _ = switches.remove(switch);
+                false
+            }
+            LatchState::Held { .. } | LatchState::Latched { is_used: false } => true,
+        });
+    }
+
+    pub fn is_latched(&self, switch: &Sw) -> bool
+    where
+        Sw: Ord,
+    {
+        matches!(self.latches.get(switch), Some(LatchState::Latched { .. }))
+    }
+
+    // Releases everything but the kept switches, e.g. locks, and returns
+    // the released ones. Latches are dropped as well.
+    pub fn release_all(&mut self, mut keep: impl FnMut(&Sw) -> bool) -> Vec<Sw>
+    where
+        Sw: Clone + Ord,
+    {
+        let switches = Arc::make_mut(&mut self.switches);
+        let released: Vec<_> = switches
+            .iter()
+            .filter(|switch| !keep(switch))
+            .cloned()
+            .collect();
+        for switch in &released {
+            let _ = switches.remove(switch);
+        }
+        self.latches = Arc::new(BTreeMap::new());
+        released
+    }
+
+    /// Aliases stay pressed while any switch of their group is pressed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ModifiersPressError::AlreadyPressed`] if the switch is already pressed.
+    pub fn on_aliased_press_event(
+        &mut self,
+        switch: Sw,
+        aliases: &ModifierAliases<Sw>,
+    ) -> Result<(), ModifiersPressError>
+    where
+        Sw: Clone + Eq + Hash + Ord,
+    {
+        let implied: Vec<_> = aliases.aliases_of(&switch).cloned().collect();
+        self.on_press_event(switch)?;
+        let switches = Arc::make_mut(&mut self.switches);
+        switches.extend(implied);
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// Returns [`ModifiersReleaseError::AlreadyReleased`] if the switch is not pressed.
+    pub fn on_aliased_release_event(
+        &mut self,
+        switch: &Sw,
+        aliases: &ModifierAliases<Sw>,
+    ) -> Result<(), ModifiersReleaseError>
+    where
+        Sw: Clone + Eq + Hash + Ord,
+    {
+        self.on_release_event(switch)?;
+        let switches = Arc::make_mut(&mut self.switches);
+        for (alias, group) in &aliases.aliases {
+            if group.contains(switch) && group.is_disjoint(switches) {
+                let _ = switches.remove(alias);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<Sw> ModifierAliases<Sw> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_alias(mut self, alias: Sw, switches: impl IntoIterator<Item = Sw>) -> Self
+    where
+        Sw: Ord,
+    {
+        self.aliases.entry(alias).or_default().extend(switches);
+        self
+    }
+
+    pub fn extend(&mut self, other: &Self)
+    where
+        Sw: Clone + Ord,
+    {
+        for (alias, group) in &other.aliases {
+            self.aliases
+                .entry(alias.clone())
+                .or_default()
+                .extend(group.iter().cloned());
+        }
+    }
+
+    #[must_use]
+    pub const fn aliases(&self) -> &BTreeMap<Sw, BTreeSet<Sw>> {
+        &self.aliases
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty()
+    }
+
+    pub fn aliases_of<'a>(&'a self, switch: &'a Sw) -> impl Iterator<Item = &'a Sw>
+    where
+        Sw: Ord,
+    {
+        self.aliases
+            .iter()
+            .filter(move |(_, group)| group.contains(switch))
+            .map(|(alias, _)| alias)
+    }
+
+    // Adds the aliases implied by the switches, so that a binding
+    // on LCtrl ranks above a binding on Ctrl.
+    #[must_use]
+    pub fn expand(&self, modifiers: &Modifiers<Sw>) -> Modifiers<Sw>
+    where
+        Sw: Clone + Ord,
+    {
+        let mut switches = modifiers.switches().as_ref().clone();
+        for switch in modifiers.switches().iter() {
+            switches.extend(self.aliases_of(switch).cloned());
+        }
+        Modifiers::from(Arc::new(switches))
+    }
+
+    // Replaces switches by their aliases, dropping the side of the modifier.
+    #[must_use]
+    pub fn generalize(&self, modifiers: &Modifiers<Sw>) -> Modifiers<Sw>
+    where
+        Sw: Clone + Ord,
+    {
+        let switches = modifiers
+            .switches()
+            .iter()
+            .flat_map(|switch| {
+                let mut aliases = self.aliases_of(switch).peekable();
+                if aliases.peek().is_some() {
+                    aliases.cloned().collect()
+                } else {
+                    vec![switch.clone()]
+                }
+            })
+            .collect();
+        Modifiers::from(Arc::new(switches))
+    }
+}
+
+impl<Sw> Default for ModifierAliases<Sw> {
+    fn default() -> Self {
+        Self {
+            aliases: BTreeMap::new(),
+        }
+    }
 }
 
 impl<Sw> Default for Modifiers<Sw> {
     fn default() -> Self {
         Self {
             switches: Arc::new(BTreeSet::new()),
+            latches: Arc::new(BTreeMap::new()),
         }
     }
 }
 
 impl<Sw> From<Arc<BTreeSet<Sw>>> for Modifiers<Sw> {
     fn from(switches: Arc<BTreeSet<Sw>>) -> Self {
-        Self { switches }
+        Self {
+            switches,
+            latches: Arc::new(BTreeMap::new()),
+        }
+    }
+}
+
+// Latch bookkeeping is not a part of the modifier set.
+impl<Sw> PartialEq for Modifiers<Sw>
+where
+    Sw: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.switches == other.switches
+    }
+}
+
+impl<Sw> Eq for Modifiers<Sw> where Sw: Eq {}
+
+impl<Sw> Hash for Modifiers<Sw>
+where
+    Sw: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.switches.hash(state);
+    }
+}
+
+impl<Sw> PartialOrd for Modifiers<Sw>
+where
+    Sw: PartialOrd,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.switches.partial_cmp(&other.switches)
+    }
+}
+
+impl<Sw> Ord for Modifiers<Sw>
+where
+    Sw: Ord,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.switches.cmp(&other.switches)
+    }
+}
+
+// Serialized as the modifier set, e.g. in scheduled binding lookups.
+impl<Sw> Serialize for Modifiers<Sw>
+where
+    Sw: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.switches.iter())
+    }
+}
+
+impl<'de, Sw> Deserialize<'de> for Modifiers<Sw>
+where
+    Sw: Deserialize<'de> + Ord,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let switches = BTreeSet::deserialize(deserializer)?;
+        Ok(Self::from(Arc::new(switches)))
     }
 }
 
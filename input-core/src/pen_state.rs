@@ -0,0 +1,242 @@
+use core::hash::Hash;
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AnalogThreshold, Coords2d};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PenTool {
+    #[default]
+    Pen,
+    Eraser,
+}
+
+// `pressure` is normalised to `0.0..=1.0` and is zero while the pen hovers,
+// `tilt` holds the angles from the normal along the x and y axes in degrees.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct PenCoords<Co> {
+    pub coords: Co,
+    pub pressure: f64,
+    pub tilt: (f64, f64),
+    pub tool: PenTool,
+    pub is_barrel_pressed: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PenToolSwitches<Sw> {
+    pub contact: Sw,
+    pub thresholds: Vec<AnalogThreshold<Sw>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PenState<Sw> {
+    tools: HashMap<PenTool, PenToolSwitches<Sw>>,
+    barrel: Option<Sw>,
+    sample: PenCoords<()>,
+    pressed: Vec<Sw>,
+}
+
+// Tool configuration is not a part of the snapshot.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PenStateSnapshot<Sw> {
+    pub sample: PenCoords<()>,
+    pub pressed: Vec<Sw>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PenEventData<Sw> {
+    pub pressed: Vec<Sw>,
+    pub released: Vec<Sw>,
+}
+
+impl<Co> PenCoords<Co> {
+    pub const fn new(coords: Co) -> Self {
+        Self {
+            coords,
+            pressure: 0.0,
+            tilt: (0.0, 0.0),
+            tool: PenTool::Pen,
+            is_barrel_pressed: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_pressure(mut self, pressure: f64) -> Self {
+        self.pressure = pressure;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_tilt(mut self, tilt: (f64, f64)) -> Self {
+        self.tilt = tilt;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_tool(mut self, tool: PenTool) -> Self {
+        self.tool = tool;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_barrel_pressed(mut self, is_barrel_pressed: bool) -> Self {
+        self.is_barrel_pressed = is_barrel_pressed;
+        self
+    }
+
+    pub fn is_in_contact(&self) -> bool {
+        self.pressure > 0.0
+    }
+
+    // The same sample at other coords.
+    pub const fn with_coords<CoCo>(&self, coords: CoCo) -> PenCoords<CoCo> {
+        PenCoords {
+            coords,
+            pressure: self.pressure,
+            tilt: self.tilt,
+            tool: self.tool,
+            is_barrel_pressed: self.is_barrel_pressed,
+        }
+    }
+}
+
+impl<Co> Coords2d for PenCoords<Co>
+where
+    Co: Coords2d,
+{
+    fn x(&self) -> f64 {
+        self.coords.x()
+    }
+
+    fn y(&self) -> f64 {
+        self.coords.y()
+    }
+}
+
+impl<Sw> PenToolSwitches<Sw> {
+    pub const fn new(contact: Sw) -> Self {
+        Self {
+            contact,
+            thresholds: Vec::new(),
+        }
+    }
+
+    // Threshold switches are held on top of the contact switch, e.g. to
+    // start a drag only when the pen is pressed harder.
+    #[must_use]
+    pub fn with_threshold(mut self, threshold: AnalogThreshold<Sw>) -> Self {
+        self.thresholds.push(threshold);
+        self
+    }
+}
+
+impl<Sw> Default for PenState<Sw> {
+    fn default() -> Self {
+        Self {
+            tools: HashMap::new(),
+            barrel: None,
+            sample: PenCoords::new(()),
+            pressed: Vec::new(),
+        }
+    }
+}
+
+impl<Sw> PenState<Sw> {
+    #[must_use]
+    pub fn with_tool(mut self, tool: PenTool, switches: PenToolSwitches<Sw>) -> Self {
+        let _ = self.tools.insert(tool, switches);
+        self
+    }
+
+    #[must_use]
+    pub fn with_barrel(mut self, switch: Sw) -> Self {
+        self.barrel = Some(switch);
+        self
+    }
+
+    pub fn tool(&self, tool: PenTool) -> Option<&PenToolSwitches<Sw>> {
+        self.tools.get(&tool)
+    }
+
+    // The last sample, whatever its coords were.
+    pub const fn sample(&self) -> &PenCoords<()> {
+        &self.sample
+    }
+
+    // The held switches in the order they were pressed.
+    pub fn pressed(&self) -> &[Sw] {
+        &self.pressed
+    }
+
+    pub fn is_pressed(&self, switch: &Sw) -> bool
+    where
+        Sw: PartialEq,
+    {
+        self.pressed.contains(switch)
+    }
+
+    pub fn snapshot(&self) -> PenStateSnapshot<Sw>
+    where
+        Sw: Clone,
+    {
+        PenStateSnapshot {
+            sample: self.sample,
+            pressed: self.pressed.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: PenStateSnapshot<Sw>) {
+        self.sample = snapshot.sample;
+        self.pressed = snapshot.pressed;
+    }
+
+    // The barrel switch goes first, so that it can be used as a modifier of
+    // the contact. Releases come in the reverse order of presses.
+    pub fn on_pen_event<Co>(&mut self, coords: &PenCoords<Co>) -> PenEventData<Sw>
+    where
+        Sw: Clone + PartialEq,
+    {
+        let mut next = Vec::new();
+        if let Some(barrel) = self.barrel.as_ref().filter(|_| coords.is_barrel_pressed) {
+            next.push(barrel.clone());
+        }
+        let switches = self
+            .tools
+            .get(&coords.tool)
+            .filter(|_| coords.is_in_contact());
+        if let Some(switches) = switches {
+            next.push(switches.contact.clone());
+            for threshold in &switches.thresholds {
+                let was_pressed = self.pressed.contains(&threshold.switch);
+                if threshold.is_pressed(coords.pressure, was_pressed) {
+                    next.push(threshold.switch.clone());
+                }
+            }
+        }
+
+        let released: Vec<_> = self
+            .pressed
+            .iter()
+            .rev()
+            .filter(|switch| !next.contains(switch))
+            .cloned()
+            .collect();
+        let pressed: Vec<_> = next
+            .into_iter()
+            .filter(|switch| !self.pressed.contains(switch))
+            .collect();
+        // Held switches keep their place, so that releases mirror the presses.
+        self.pressed.retain(|switch| !released.contains(switch));
+        self.pressed.extend(pressed.iter().cloned());
+        self.sample = coords.with_coords(());
+        PenEventData { pressed, released }
+    }
+
+    // Returns the held switches in the order they are to be released.
+    pub fn reset(&mut self) -> Vec<Sw> {
+        self.sample = PenCoords::new(());
+        self.pressed.drain(..).rev().collect()
+    }
+}
@@ -1,29 +1,71 @@
 use core::fmt::Debug;
 use core::hash::Hash;
-use std::collections::HashMap;
+use core::ops::Add;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Weak};
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::{CoordsDistance, DragPolicy, FlingPolicy, HandleRequest, TimeValue};
+
 #[derive(Clone, Debug)]
-pub struct PointerState<Sw, Co> {
-    switches: HashMap<Sw, SwitchState<Co>>,
+pub struct PointerState<Sw, Co, Ti, Du> {
+    policy: DragPolicy<Du>,
+    fling_policy: Option<FlingPolicy<Du>>,
+    switches: HashMap<Sw, PressedSwitch<Co, Ti>>,
+    kinetics: HashMap<Sw, KineticSwitchState>,
 }
 
-#[derive(Clone, Debug)]
-enum SwitchState<Co> {
-    Pressed(Co),
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PointerStateSnapshot<Sw, Co, Ti> {
+    switches: Vec<(Sw, PressedSwitch<Co, Ti>)>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct PressedSwitch<Co, Ti> {
+    coords: Co,
+    last_coords: Co,
+    state: SwitchState<Ti>,
+    // Recent coords, kept only with a fling policy and not persisted.
+    #[serde(skip, default = "VecDeque::new")]
+    samples: VecDeque<(Ti, Co)>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+enum SwitchState<Ti> {
+    Pressed {
+        deadline: Option<Ti>,
+        is_beyond: bool,
+        is_click_cancelled: bool,
+    },
     Moving,
+    Cancelled,
+}
+
+#[derive(Clone, Debug)]
+struct KineticSwitchState {
+    tag: Arc<()>,
+    velocity: (f64, f64),
+    is_flung: bool,
 }
 
+#[derive(Clone, Debug)]
+pub struct KineticHandleRequest(Weak<()>);
+
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum PointerMoveEventKind {
     DragStart,
     DragMove,
+    DragCancel,
+    Fling,
+    Kinetic,
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum PointerChangeEventData {
     DragEnd,
+    DragCancel,
 }
 
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -32,26 +74,227 @@ pub struct PointerMoveEventData<Sw> {
     pub kind: PointerMoveEventKind,
 }
 
-impl<Sw, Co> PointerState<Sw, Co> {
-    pub fn new() -> Self {
-        Self::default()
+// Velocity in coords per time unit. `delta` is the distance covered by a
+// kinetic update and is zero for the fling itself.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct KineticEventData {
+    pub kind: PointerMoveEventKind,
+    pub velocity: (f64, f64),
+    pub delta: (f64, f64),
+}
+
+impl<Sw, Co, Ti, Du> PointerState<Sw, Co, Ti, Du> {
+    pub fn new(policy: DragPolicy<Du>) -> Self {
+        Self {
+            policy,
+            fling_policy: None,
+            switches: HashMap::new(),
+            kinetics: HashMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_fling_policy(mut self, policy: FlingPolicy<Du>) -> Self {
+        self.fling_policy = Some(policy);
+        self
+    }
+
+    pub const fn policy(&self) -> &DragPolicy<Du> {
+        &self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: DragPolicy<Du>) {
+        self.policy = policy;
+    }
+
+    pub const fn fling_policy(&self) -> Option<&FlingPolicy<Du>> {
+        self.fling_policy.as_ref()
+    }
+
+    pub fn set_fling_policy(&mut self, policy: Option<FlingPolicy<Du>>) {
+        self.fling_policy = policy;
+    }
+
+    pub fn snapshot(&self) -> PointerStateSnapshot<Sw, Co, Ti>
+    where
+        Sw: Clone,
+        Co: Clone,
+        Ti: Clone,
+    {
+        let switches = self
+            .switches
+            .iter()
+            .map(|(switch, pressed)| (switch.clone(), pressed.clone()))
+            .collect();
+        PointerStateSnapshot { switches }
+    }
+
+    pub fn restore(&mut self, snapshot: PointerStateSnapshot<Sw, Co, Ti>)
+    where
+        Sw: Eq + Hash,
+    {
+        self.switches = snapshot.switches.into_iter().collect();
+        // Kinetics are not persisted, their scheduled requests are gone too.
+        self.kinetics.clear();
+    }
+
+    pub fn iter_switches(&self) -> impl Iterator<Item = &Sw> {
+        self.switches.keys()
     }
 
-    pub fn on_press_event(&mut self, switch: Sw, coords: Co) -> Result<(), PointerPressError>
+    /// # Errors
+    ///
+    /// Returns [`PointerPressError::AlreadyPressed`] if the switch is already pressed.
+    pub fn on_press_event(
+        &mut self,
+        switch: Sw,
+        coords: Co,
+        time: Ti,
+    ) -> Result<(), PointerPressError>
     where
         Sw: Eq + Hash,
+        Co: Clone,
+        Ti: Clone + Add<Du, Output = Ti>,
+        Du: Clone,
     {
         use std::collections::hash_map::Entry;
 
+        // Touching the pointer again stops whatever is still sliding.
+        self.kinetics.clear();
         match self.switches.entry(switch) {
             Entry::Occupied(_) => Err(PointerPressError::AlreadyPressed),
             Entry::Vacant(entry) => {
-                let _ = entry.insert(SwitchState::Pressed(coords));
+                let mut samples = VecDeque::new();
+                if self.fling_policy.is_some() {
+                    samples.push_back((time.clone(), coords.clone()));
+                }
+                let _ = entry.insert(PressedSwitch {
+                    last_coords: coords.clone(),
+                    coords,
+                    state: SwitchState::Pressed {
+                        deadline: self.policy.drag_deadline(time),
+                        is_beyond: false,
+                        is_click_cancelled: false,
+                    },
+                    samples,
+                });
                 Ok(())
             }
         }
     }
 
+    // Velocity over the samples within the window before `time`.
+    pub fn velocity(&self, switch: &Sw, time: &Ti) -> Option<(f64, f64)>
+    where
+        Sw: Eq + Hash,
+        Co: CoordsDistance,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + TimeValue,
+        Du: Clone,
+    {
+        let policy = self.fling_policy.as_ref()?;
+        let pressed = self.switches.get(switch)?;
+        let mut samples = pressed
+            .samples
+            .iter()
+            .filter(|(sample_time, _)| sample_time.clone() + policy.window.clone() >= *time);
+        let (first_time, first) = samples.next()?;
+        let (last_time, last) = samples.last()?;
+        let duration = last_time.as_f64() - first_time.as_f64();
+        if duration <= 0.0 {
+            return None;
+        }
+        let (dx, dy) = first.displacement(last)?;
+        Some((dx / duration, dy / duration))
+    }
+
+    // Called before the release of a dragged switch. Returns the request for
+    // the fling, which is expected to be scheduled at the release time.
+    pub fn on_fling_event(&mut self, switch: &Sw, time: &Ti) -> Option<KineticHandleRequest>
+    where
+        Sw: Clone + Eq + Hash,
+        Co: CoordsDistance,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + TimeValue,
+        Du: Clone,
+    {
+        let pressed = self.switches.get(switch)?;
+        if !matches!(pressed.state, SwitchState::Moving) {
+            return None;
+        }
+        let velocity = self.velocity(switch, time)?;
+        let policy = self.fling_policy.as_ref()?;
+        if velocity.0.hypot(velocity.1) < policy.min_speed {
+            return None;
+        }
+        let tag = Arc::new(());
+        let request = KineticHandleRequest(Arc::downgrade(&tag));
+        let _ = self.kinetics.insert(
+            switch.clone(),
+            KineticSwitchState {
+                tag,
+                velocity,
+                is_flung: false,
+            },
+        );
+        Some(request)
+    }
+
+    // Returns the fling first, then the decaying kinetic updates with the
+    // next deadline, counted from the handled deadline.
+    pub fn on_kinetic_timeout_event(
+        &mut self,
+        switch: &Sw,
+        request: KineticHandleRequest,
+        time: Ti,
+    ) -> Option<(KineticEventData, Option<(Ti, KineticHandleRequest)>)>
+    where
+        Sw: Eq + Hash,
+        Ti: Add<Du, Output = Ti>,
+        Du: Clone + TimeValue,
+    {
+        let tag = request.0.upgrade()?;
+        let state = self.kinetics.get_mut(switch)?;
+        if !Arc::ptr_eq(&tag, &state.tag) {
+            return None;
+        }
+        let kinetic = self
+            .fling_policy
+            .as_ref()
+            .and_then(|policy| policy.kinetic.as_ref());
+        let (vx, vy) = state.velocity;
+        let data = if state.is_flung {
+            let Some(kinetic) = kinetic else {
+                let _ = self.kinetics.remove(switch);
+                return None;
+            };
+            let interval = kinetic.interval.as_f64();
+            state.velocity = (vx * kinetic.friction, vy * kinetic.friction);
+            KineticEventData {
+                kind: PointerMoveEventKind::Kinetic,
+                velocity: (vx, vy),
+                delta: (vx * interval, vy * interval),
+            }
+        } else {
+            state.is_flung = true;
+            KineticEventData {
+                kind: PointerMoveEventKind::Fling,
+                velocity: (vx, vy),
+                delta: (0.0, 0.0),
+            }
+        };
+        let (vx, vy) = state.velocity;
+        let next = kinetic
+            .filter(|kinetic| vx.hypot(vy) >= kinetic.min_speed)
+            .map(|kinetic| (time + kinetic.interval.clone(), request));
+        if next.is_none() {
+            let _ = self.kinetics.remove(switch);
+        }
+        Some((data, next))
+    }
+
+    pub fn stop_kinetics(&mut self) {
+        self.kinetics.clear();
+    }
+
     pub fn on_release_event(
         &mut self,
         switch: &Sw,
@@ -60,10 +303,11 @@ impl<Sw, Co> PointerState<Sw, Co> {
         Sw: Eq + Hash,
     {
         match self.switches.remove(switch) {
-            Some(state) => {
-                let data = match state {
-                    SwitchState::Pressed(_) => None,
+            Some(pressed) => {
+                let data = match pressed.state {
+                    SwitchState::Pressed { .. } => None,
                     SwitchState::Moving => Some(PointerChangeEventData::DragEnd),
+                    SwitchState::Cancelled => Some(PointerChangeEventData::DragCancel),
                 };
                 Ok(data)
             }
@@ -71,42 +315,191 @@ impl<Sw, Co> PointerState<Sw, Co> {
         }
     }
 
-    pub fn on_move_event<F>(&mut self, mut is_dragged_fn: F) -> Vec<PointerMoveEventData<Sw>>
+    // A press that moved beyond the drag distance without starting a drag
+    // or whose drag was cancelled should not be reported as a click.
+    pub fn is_click_cancelled(&self, switch: &Sw) -> bool
     where
-        Sw: Clone + Eq + Hash,
-        F: FnMut(&Co) -> bool,
+        Sw: Eq + Hash,
     {
-        let events = self
-            .switches
+        matches!(
+            self.switches.get(switch).map(|pressed| &pressed.state),
+            Some(
+                SwitchState::Pressed {
+                    is_click_cancelled: true,
+                    ..
+                } | SwitchState::Cancelled
+            )
+        )
+    }
+
+    pub fn on_cancel_event(&mut self) -> Vec<PointerMoveEventData<Sw>>
+    where
+        Sw: Clone,
+    {
+        self.switches
             .iter_mut()
-            .filter_map(|(switch, state)| match state {
-                SwitchState::Pressed(coords) => {
-                    if is_dragged_fn(coords) {
-                        *state = SwitchState::Moving;
-                        Some(PointerMoveEventData {
-                            switch: switch.clone(),
-                            kind: PointerMoveEventKind::DragStart,
-                        })
-                    } else {
-                        None
-                    }
+            .filter_map(|(switch, pressed)| match pressed.state {
+                SwitchState::Moving => {
+                    pressed.state = SwitchState::Cancelled;
+                    Some(PointerMoveEventData {
+                        switch: switch.clone(),
+                        kind: PointerMoveEventKind::DragCancel,
+                    })
                 }
-                SwitchState::Moving => Some(PointerMoveEventData {
-                    switch: switch.clone(),
-                    kind: PointerMoveEventKind::DragMove,
-                }),
+                SwitchState::Pressed { .. } | SwitchState::Cancelled => None,
             })
-            .collect();
+            .collect()
+    }
+
+    pub fn cancel_click(&mut self, switch: &Sw)
+    where
+        Sw: Eq + Hash,
+    {
+        if let Some(SwitchState::Pressed {
+            is_click_cancelled, ..
+        }) = self
+            .switches
+            .get_mut(switch)
+            .map(|pressed| &mut pressed.state)
+        {
+            *is_click_cancelled = true;
+        }
+    }
+
+    pub fn cancel_clicks(&mut self) {
+        for pressed in self.switches.values_mut() {
+            if let SwitchState::Pressed {
+                is_click_cancelled, ..
+            } = &mut pressed.state
+            {
+                *is_click_cancelled = true;
+            }
+        }
+    }
+
+    pub fn on_move_event(&mut self, coords: &Co, time: &Ti) -> Vec<PointerMoveEventData<Sw>>
+    where
+        Sw: Clone + Eq + Hash,
+        Co: Clone + CoordsDistance,
+        Ti: Clone + Ord + Add<Du, Output = Ti>,
+        Du: Clone,
+    {
+        let policy = &self.policy;
+        let fling_policy = self.fling_policy.as_ref();
+        self.switches
+            .iter_mut()
+            .filter_map(|(switch, pressed)| {
+                push_sample(fling_policy, pressed, coords, time);
+                pressed.last_coords = coords.clone();
+                on_switch_move(policy, switch, pressed, coords, time)
+            })
+            .collect()
+    }
+
+    pub fn on_switch_move_event(
+        &mut self,
+        switch: &Sw,
+        coords: &Co,
+        time: &Ti,
+    ) -> Option<PointerMoveEventData<Sw>>
+    where
+        Sw: Clone + Eq + Hash,
+        Co: Clone + CoordsDistance,
+        Ti: Clone + Ord + Add<Du, Output = Ti>,
+        Du: Clone,
+    {
+        let pressed = self.switches.get_mut(switch)?;
+        push_sample(self.fling_policy.as_ref(), pressed, coords, time);
+        pressed.last_coords = coords.clone();
+        on_switch_move(&self.policy, switch, pressed, coords, time)
+    }
 
-        events
+    // Coords at press time stay available for the whole press, drags included.
+    pub fn press_coords(&self, switch: &Sw) -> Option<&Co>
+    where
+        Sw: Eq + Hash,
+    {
+        self.switches.get(switch).map(|pressed| &pressed.coords)
+    }
+
+    // Coords of the last move of this switch, so that every touch keeps its own.
+    pub fn last_coords(&self, switch: &Sw) -> Option<&Co>
+    where
+        Sw: Eq + Hash,
+    {
+        self.switches
+            .get(switch)
+            .map(|pressed| &pressed.last_coords)
     }
 }
 
-impl<Sw, Co> Default for PointerState<Sw, Co> {
-    fn default() -> Self {
-        Self {
-            switches: HashMap::new(),
+fn push_sample<Co, Ti, Du>(
+    policy: Option<&FlingPolicy<Du>>,
+    pressed: &mut PressedSwitch<Co, Ti>,
+    coords: &Co,
+    time: &Ti,
+) where
+    Co: Clone,
+    Ti: Clone + Ord + Add<Du, Output = Ti>,
+    Du: Clone,
+{
+    let Some(policy) = policy else {
+        return;
+    };
+    pressed.samples.push_back((time.clone(), coords.clone()));
+    while let Some((sample_time, _)) = pressed.samples.front() {
+        if sample_time.clone() + policy.window.clone() >= *time {
+            break;
         }
+        let _ = pressed.samples.pop_front();
+    }
+}
+
+fn on_switch_move<Sw, Co, Ti, Du>(
+    policy: &DragPolicy<Du>,
+    switch: &Sw,
+    pressed: &mut PressedSwitch<Co, Ti>,
+    next: &Co,
+    time: &Ti,
+) -> Option<PointerMoveEventData<Sw>>
+where
+    Sw: Clone,
+    Co: CoordsDistance,
+    Ti: Ord,
+{
+    let kind = match &mut pressed.state {
+        SwitchState::Pressed {
+            deadline,
+            is_beyond,
+            is_click_cancelled,
+        } => {
+            *is_beyond = policy.is_beyond(pressed.coords.distance(next), *is_beyond);
+            *is_click_cancelled |= *is_beyond;
+            let is_ready = deadline.as_ref().is_none_or(|deadline| deadline <= time);
+            if !(*is_beyond && is_ready) {
+                return None;
+            }
+            pressed.state = SwitchState::Moving;
+            PointerMoveEventKind::DragStart
+        }
+        SwitchState::Moving => PointerMoveEventKind::DragMove,
+        SwitchState::Cancelled => return None,
+    };
+    Some(PointerMoveEventData {
+        switch: switch.clone(),
+        kind,
+    })
+}
+
+impl<Sw, Co, Ti, Du> Default for PointerState<Sw, Co, Ti, Du> {
+    fn default() -> Self {
+        Self::new(DragPolicy::default())
+    }
+}
+
+impl HandleRequest for KineticHandleRequest {
+    fn is_alive(&self) -> bool {
+        self.0.strong_count() > 0
     }
 }
 
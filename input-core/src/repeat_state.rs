@@ -0,0 +1,200 @@
+use core::hash::Hash;
+use core::ops::Add;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+use serde::{Deserialize, Serialize};
+
+use crate::HandleRequest;
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RepeatPolicy<Du> {
+    pub delay: Du,
+    pub interval: Du,
+    pub acceleration: Vec<Du>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RepeatState<Sw, Du> {
+    switches: HashMap<Sw, RepeatSwitchState>,
+    policy: Option<RepeatPolicy<Du>>,
+}
+
+#[derive(Clone, Debug)]
+struct RepeatSwitchState {
+    tag: Arc<()>,
+    num_repeats: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct RepeatHandleRequest(Weak<()>);
+
+// Held switches with the number of repeats so far.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RepeatStateSnapshot<Sw> {
+    pub switches: Vec<(Sw, usize)>,
+}
+
+impl<Du> RepeatPolicy<Du> {
+    pub const fn new(delay: Du, interval: Du) -> Self {
+        Self {
+            delay,
+            interval,
+            acceleration: Vec::new(),
+        }
+    }
+
+    // Intervals of the first repeats, `interval` is used once they run out.
+    #[must_use]
+    pub fn with_acceleration(mut self, intervals: impl IntoIterator<Item = Du>) -> Self {
+        self.acceleration = intervals.into_iter().collect();
+        self
+    }
+
+    pub fn interval(&self, num_repeats: usize) -> &Du {
+        self.acceleration.get(num_repeats).unwrap_or(&self.interval)
+    }
+}
+
+impl<Sw, Du> RepeatState<Sw, Du> {
+    // Without a policy repeats are expected to come from the host.
+    pub fn new(policy: Option<RepeatPolicy<Du>>) -> Self {
+        Self {
+            switches: HashMap::new(),
+            policy,
+        }
+    }
+
+    pub const fn policy(&self) -> Option<&RepeatPolicy<Du>> {
+        self.policy.as_ref()
+    }
+
+    pub fn set_policy(&mut self, policy: Option<RepeatPolicy<Du>>) {
+        self.policy = policy;
+    }
+
+    pub fn is_pressed(&self, switch: &Sw) -> bool
+    where
+        Sw: Eq + Hash,
+    {
+        self.switches.contains_key(switch)
+    }
+
+    pub fn iter_switches(&self) -> impl Iterator<Item = &Sw> {
+        self.switches.keys()
+    }
+
+    pub fn num_repeats(&self, switch: &Sw) -> Option<usize>
+    where
+        Sw: Eq + Hash,
+    {
+        self.switches.get(switch).map(|state| state.num_repeats)
+    }
+
+    pub fn snapshot(&self) -> RepeatStateSnapshot<Sw>
+    where
+        Sw: Clone,
+    {
+        let switches = self
+            .switches
+            .iter()
+            .map(|(switch, state)| (switch.clone(), state.num_repeats))
+            .collect();
+        RepeatStateSnapshot { switches }
+    }
+
+    pub fn restore(&mut self, snapshot: RepeatStateSnapshot<Sw>)
+    where
+        Sw: Eq + Hash,
+    {
+        self.switches = snapshot
+            .switches
+            .into_iter()
+            .map(|(switch, num_repeats)| {
+                let tag = Arc::new(());
+                (switch, RepeatSwitchState { tag, num_repeats })
+            })
+            .collect();
+    }
+
+    pub fn request(&self, switch: &Sw) -> Option<RepeatHandleRequest>
+    where
+        Sw: Eq + Hash,
+    {
+        let state = self.switches.get(switch)?;
+        Some(RepeatHandleRequest(Arc::downgrade(&state.tag)))
+    }
+
+    pub fn on_press_event<Ti>(&mut self, switch: Sw, time: Ti) -> Option<(Ti, RepeatHandleRequest)>
+    where
+        Sw: Eq + Hash,
+        Ti: Add<Du, Output = Ti>,
+        Du: Clone,
+    {
+        let tag = Arc::new(());
+        let request = RepeatHandleRequest(Arc::downgrade(&tag));
+        let _ = self.switches.insert(
+            switch,
+            RepeatSwitchState {
+                tag,
+                num_repeats: 0,
+            },
+        );
+        let policy = self.policy.as_ref()?;
+        Some((time + policy.delay.clone(), request))
+    }
+
+    pub fn on_release_event(&mut self, switch: &Sw) -> bool
+    where
+        Sw: Eq + Hash,
+    {
+        self.switches.remove(switch).is_some()
+    }
+
+    pub fn on_repeat_event(&mut self, switch: &Sw) -> Option<usize>
+    where
+        Sw: Eq + Hash,
+    {
+        // Repeats are either synthesised or come from the host, never both.
+        if self.policy.is_some() {
+            return None;
+        }
+        let state = self.switches.get_mut(switch)?;
+        state.num_repeats += 1;
+        Some(state.num_repeats)
+    }
+
+    // Returns the number of repeats and the next deadline, counted from the
+    // handled deadline so that late timeouts do not slow the repeat down.
+    pub fn on_timeout_event<Ti>(
+        &mut self,
+        switch: &Sw,
+        request: RepeatHandleRequest,
+        time: Ti,
+    ) -> Option<(usize, Ti, RepeatHandleRequest)>
+    where
+        Sw: Eq + Hash,
+        Ti: Add<Du, Output = Ti>,
+        Du: Clone,
+    {
+        let tag = request.0.upgrade()?;
+        let policy = self.policy.as_ref()?;
+        let state = self.switches.get_mut(switch)?;
+        if !Arc::ptr_eq(&tag, &state.tag) {
+            return None;
+        }
+        let deadline = time + policy.interval(state.num_repeats).clone();
+        state.num_repeats += 1;
+        Some((state.num_repeats, deadline, request))
+    }
+
+    pub fn reset(&mut self) {
+        self.switches.clear();
+    }
+}
+
+impl HandleRequest for RepeatHandleRequest {
+    fn is_alive(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+}
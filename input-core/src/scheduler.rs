@@ -1,15 +1,33 @@
 use core::mem::take;
 use std::collections::BTreeMap;
 
-use crate::{ClickExactHandleRequest, LongPressHandleRequest};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ClickExactHandleRequest, KineticHandleRequest, LongPressHandleRequest, RepeatHandleRequest,
+    SequenceHandleRequest,
+};
 
 #[derive(Clone, Debug)]
 pub struct SchedulerState<Ti, Da, Rq> {
     requests: BTreeMap<Ti, Vec<(Da, Rq)>>,
 }
 
+// Pending deadlines without their handle requests, which cannot be persisted.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SchedulerSnapshot<Ti, Da> {
+    pub requests: Vec<(Ti, Da)>,
+}
+
+pub trait HandleRequest {
+    fn is_alive(&self) -> bool;
+}
+
 pub type LongPressSchedulerState<Ti, Da> = SchedulerState<Ti, Da, LongPressHandleRequest>;
 pub type ClickExactSchedulerState<Ti, Da> = SchedulerState<Ti, Da, ClickExactHandleRequest>;
+pub type SequenceSchedulerState<Ti, Da> = SchedulerState<Ti, Da, SequenceHandleRequest>;
+pub type RepeatSchedulerState<Ti, Da> = SchedulerState<Ti, Da, RepeatHandleRequest>;
+pub type KineticSchedulerState<Ti, Da> = SchedulerState<Ti, Da, KineticHandleRequest>;
 
 impl<Ti, Da, Rq> SchedulerState<Ti, Da, Rq> {
     pub fn new() -> Self {
@@ -31,6 +49,10 @@ impl<Ti, Da, Rq> SchedulerState<Ti, Da, Rq> {
         self.requests.entry(time).or_default().push((data, request));
     }
 
+    pub fn clear(&mut self) {
+        self.requests.clear();
+    }
+
     pub fn take_scheduled(&mut self, time: &Ti) -> impl Iterator<Item = (Ti, Vec<(Da, Rq)>)>
     where
         Ti: Ord,
@@ -43,6 +65,44 @@ impl<Ti, Da, Rq> SchedulerState<Ti, Da, Rq> {
         }
         scheduled.into_iter()
     }
+
+    // Requests that can no longer be handled are left out.
+    #[must_use]
+    pub fn snapshot(&self) -> SchedulerSnapshot<Ti, Da>
+    where
+        Ti: Clone,
+        Da: Clone,
+        Rq: HandleRequest,
+    {
+        let requests = self
+            .requests
+            .iter()
+            .flat_map(|(time, requests)| {
+                requests
+                    .iter()
+                    .filter(|(_, request)| request.is_alive())
+                    .map(move |(data, _)| (time.clone(), data.clone()))
+            })
+            .collect();
+        SchedulerSnapshot { requests }
+    }
+
+    // Requests are taken from the already restored states,
+    // entries they no longer expect are dropped.
+    pub fn restore(
+        &mut self,
+        snapshot: SchedulerSnapshot<Ti, Da>,
+        mut request_fn: impl FnMut(&Da) -> Option<Rq>,
+    ) where
+        Ti: Ord,
+    {
+        self.requests.clear();
+        for (time, data) in snapshot.requests {
+            if let Some(request) = request_fn(&data) {
+                self.schedule(time, data, request);
+            }
+        }
+    }
 }
 
 impl<Ti, Da, Rq> From<BTreeMap<Ti, Vec<(Da, Rq)>>> for SchedulerState<Ti, Da, Rq> {
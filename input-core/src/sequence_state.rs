@@ -0,0 +1,136 @@
+use core::mem::take;
+use core::ops::Add;
+use std::sync::{Arc, Weak};
+
+use serde::{Deserialize, Serialize};
+
+use crate::HandleRequest;
+
+#[derive(Clone, Debug)]
+pub struct SequenceState<St, Du> {
+    pending: Vec<St>,
+    tag: Option<Arc<()>>,
+    timeout: Du,
+}
+
+#[derive(Clone, Debug)]
+pub struct SequenceHandleRequest(Weak<()>);
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SequenceStateSnapshot<St> {
+    pub pending: Vec<St>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum SequenceMatchKind {
+    Prefix,
+    Complete,
+    // Complete, but a longer sequence starts with it, so it completes on the
+    // timeout or on a step that does not extend it.
+    Extendable,
+}
+
+#[derive(Clone, Debug)]
+pub enum SequenceEventData<St> {
+    Pending(SequenceHandleRequest),
+    Complete(Vec<St>),
+}
+
+impl<St, Du> SequenceState<St, Du> {
+    pub const fn new(timeout: Du) -> Self {
+        Self {
+            pending: Vec::new(),
+            tag: None,
+            timeout,
+        }
+    }
+
+    pub const fn timeout(&self) -> &Du {
+        &self.timeout
+    }
+
+    pub fn set_timeout(&mut self, timeout: Du) {
+        self.timeout = timeout;
+    }
+
+    pub fn deadline<Ti>(&self, time: Ti) -> Ti
+    where
+        Ti: Add<Du, Output = Ti>,
+        Du: Clone,
+    {
+        time + self.timeout.clone()
+    }
+
+    pub fn pending(&self) -> &[St] {
+        &self.pending
+    }
+
+    pub const fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn snapshot(&self) -> SequenceStateSnapshot<St>
+    where
+        St: Clone,
+    {
+        SequenceStateSnapshot {
+            pending: self.pending.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: SequenceStateSnapshot<St>) {
+        self.tag = (!snapshot.pending.is_empty()).then(|| Arc::new(()));
+        self.pending = snapshot.pending;
+    }
+
+    pub fn request(&self) -> Option<SequenceHandleRequest> {
+        self.tag
+            .as_ref()
+            .map(|tag| SequenceHandleRequest(Arc::downgrade(tag)))
+    }
+
+    pub fn reset(&mut self) -> Vec<St> {
+        self.tag = None;
+        take(&mut self.pending)
+    }
+
+    pub fn on_step_event<F>(&mut self, step: St, mut match_fn: F) -> Option<SequenceEventData<St>>
+    where
+        F: FnMut(&[St]) -> Option<SequenceMatchKind>,
+    {
+        let mut steps = self.reset();
+        steps.push(step);
+        loop {
+            match match_fn(&steps) {
+                Some(SequenceMatchKind::Complete) => {
+                    return Some(SequenceEventData::Complete(steps))
+                }
+                Some(SequenceMatchKind::Prefix | SequenceMatchKind::Extendable) => {
+                    let tag = Arc::new(());
+                    let request = SequenceHandleRequest(Arc::downgrade(&tag));
+                    self.tag = Some(tag);
+                    self.pending = steps;
+                    return Some(SequenceEventData::Pending(request));
+                }
+                None if steps.len() > 1 => {
+                    let _ = steps.drain(..steps.len() - 1);
+                }
+                None => return None,
+            }
+        }
+    }
+
+    pub fn on_timeout_event(&mut self, request: &SequenceHandleRequest) -> Option<Vec<St>> {
+        if request.0.upgrade().is_some() {
+            Some(self.reset())
+        } else {
+            None
+        }
+    }
+}
+
+impl HandleRequest for SequenceHandleRequest {
+    fn is_alive(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+}
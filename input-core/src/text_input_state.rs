@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// Text comes from the host's layout or input method, separately from the
+// key switches that produced it.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextInput {
+    Text(String),
+    CompositionStart,
+    // Holds the whole preedit text, not a delta.
+    CompositionUpdate(String),
+    CompositionCommit(String),
+    CompositionCancel,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TextInputState {
+    composition: Option<String>,
+}
+
+// `inserted` is the text to insert at the caret and `preedit` is the text to
+// show in place of the composition, `None` when there is no composition.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TextInputEventData {
+    pub inserted: Option<String>,
+    pub preedit: Option<String>,
+}
+
+impl TextInputState {
+    #[must_use]
+    pub const fn is_composing(&self) -> bool {
+        self.composition.is_some()
+    }
+
+    #[must_use]
+    pub fn composition(&self) -> Option<&str> {
+        self.composition.as_deref()
+    }
+
+    // Commits and cancels are accepted without a composition, as some input
+    // methods commit text right away.
+    /// # Errors
+    ///
+    /// Returns [`TextInputError::AlreadyComposing`] if a composition starts during another one,
+    /// and [`TextInputError::NotComposing`] if there is no composition to update.
+    pub fn on_input_event(
+        &mut self,
+        input: TextInput,
+    ) -> Result<TextInputEventData, TextInputError> {
+        match input {
+            TextInput::Text(text) => Ok(TextInputEventData {
+                inserted: Some(text),
+                preedit: self.composition.clone(),
+            }),
+            TextInput::CompositionStart => {
+                if self.composition.is_some() {
+                    return Err(TextInputError::AlreadyComposing);
+                }
+                self.composition = Some(String::new());
+                Ok(TextInputEventData {
+                    inserted: None,
+                    preedit: self.composition.clone(),
+                })
+            }
+            TextInput::CompositionUpdate(text) => {
+                if self.composition.is_none() {
+                    return Err(TextInputError::NotComposing);
+                }
+                self.composition = Some(text);
+                Ok(TextInputEventData {
+                    inserted: None,
+                    preedit: self.composition.clone(),
+                })
+            }
+            TextInput::CompositionCommit(text) => {
+                self.composition = None;
+                Ok(TextInputEventData {
+                    inserted: Some(text),
+                    preedit: None,
+                })
+            }
+            TextInput::CompositionCancel => {
+                self.composition = None;
+                Ok(TextInputEventData::default())
+            }
+        }
+    }
+
+    // Makes the input that failed with `error` valid.
+    pub fn resync(&mut self, error: TextInputError) {
+        self.composition = match error {
+            TextInputError::AlreadyComposing => None,
+            TextInputError::NotComposing => Some(String::new()),
+        };
+    }
+
+    // Returns the preedit text of the cancelled composition.
+    pub const fn reset(&mut self) -> Option<String> {
+        self.composition.take()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+pub enum TextInputError {
+    #[error("Composition is started while composing")]
+    AlreadyComposing,
+    #[error("Composition is updated while not composing")]
+    NotComposing,
+}
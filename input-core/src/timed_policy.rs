@@ -0,0 +1,121 @@
+use core::hash::Hash;
+use core::ops::Add;
+use std::collections::HashMap;
+
+use crate::{NumPossibleClicks, TimedReleaseEventData};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct TimedPolicy<Du> {
+    pub long_press_duration: Du,
+    pub click_exact_duration: Du,
+    pub max_clicks: NumPossibleClicks,
+}
+
+#[derive(Clone, Debug)]
+pub struct TimedPolicies<Sw, Du> {
+    default: TimedPolicy<Du>,
+    switches: HashMap<Sw, TimedPolicy<Du>>,
+}
+
+impl<Du> TimedPolicy<Du> {
+    pub const fn new(
+        long_press_duration: Du,
+        click_exact_duration: Du,
+        max_clicks: NumPossibleClicks,
+    ) -> Self {
+        Self {
+            long_press_duration,
+            click_exact_duration,
+            max_clicks,
+        }
+    }
+
+    pub fn long_press_deadline<Ti>(&self, time: Ti) -> Ti
+    where
+        Ti: Add<Du, Output = Ti>,
+        Du: Clone,
+    {
+        time + self.long_press_duration.clone()
+    }
+
+    // No more clicks are possible after `max_clicks`, so there is no reason to wait.
+    pub fn click_exact_deadline<Ti>(&self, time: Ti, data: &TimedReleaseEventData) -> Ti
+    where
+        Ti: Add<Du, Output = Ti>,
+        Du: Clone,
+    {
+        if data.num_possible_clicks >= self.max_clicks {
+            time
+        } else {
+            time + self.click_exact_duration.clone()
+        }
+    }
+}
+
+// Deadlines are at the event time and the click count is unbounded, as before
+// durations were configurable.
+impl<Du> Default for TimedPolicy<Du>
+where
+    Du: Default,
+{
+    fn default() -> Self {
+        Self::new(Du::default(), Du::default(), NumPossibleClicks::MAX)
+    }
+}
+
+impl<Sw, Du> TimedPolicies<Sw, Du> {
+    pub fn new(default: TimedPolicy<Du>) -> Self {
+        Self {
+            default,
+            switches: HashMap::new(),
+        }
+    }
+
+    pub const fn default_policy(&self) -> &TimedPolicy<Du> {
+        &self.default
+    }
+
+    pub fn set_default_policy(&mut self, policy: TimedPolicy<Du>) {
+        self.default = policy;
+    }
+
+    pub fn policy(&self, switch: &Sw) -> &TimedPolicy<Du>
+    where
+        Sw: Eq + Hash,
+    {
+        self.switches.get(switch).unwrap_or(&self.default)
+    }
+
+    pub fn set_switch_policy(
+        &mut self,
+        switch: Sw,
+        policy: TimedPolicy<Du>,
+    ) -> Option<TimedPolicy<Du>>
+    where
+        Sw: Eq + Hash,
+    {
+        self.switches.insert(switch, policy)
+    }
+
+    pub fn remove_switch_policy(&mut self, switch: &Sw) -> Option<TimedPolicy<Du>>
+    where
+        Sw: Eq + Hash,
+    {
+        self.switches.remove(switch)
+    }
+}
+
+impl<Sw, Du> From<TimedPolicy<Du>> for TimedPolicies<Sw, Du> {
+    fn from(default: TimedPolicy<Du>) -> Self {
+        Self::new(default)
+    }
+}
+
+impl<Sw, Du> Default for TimedPolicies<Sw, Du>
+where
+    Du: Default,
+{
+    fn default() -> Self {
+        Self::new(TimedPolicy::default())
+    }
+}
@@ -1,10 +1,14 @@
 use core::hash::Hash;
+use core::ops::Add;
 use std::collections::hash_map::OccupiedEntry;
 use std::collections::HashMap;
 use std::sync::{Arc, Weak};
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::{HandleRequest, TimedPolicies, TimedPolicy};
+
 pub type NumPossibleClicks = u32;
 
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -35,14 +39,16 @@ pub enum TimedClickExactEventKind {
 }
 
 #[derive(Clone, Debug)]
-pub struct TimedState<Sw> {
+pub struct TimedState<Sw, Du> {
     switches: HashMap<Sw, SwitchState>,
+    policies: TimedPolicies<Sw, Du>,
 }
 
 #[derive(Clone, Debug)]
 struct SwitchState {
     kind: SwitchStateKind,
     num_possible_clicks: NumPossibleClicks,
+    is_suppressed: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -59,6 +65,27 @@ pub struct LongPressHandleRequest(Weak<()>);
 #[derive(Clone, Debug)]
 pub struct ClickExactHandleRequest(Weak<()>);
 
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TimedStateSnapshot<Sw> {
+    switches: Vec<(Sw, SwitchSnapshot)>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+struct SwitchSnapshot {
+    kind: SwitchSnapshotKind,
+    num_possible_clicks: NumPossibleClicks,
+    #[serde(default)]
+    is_suppressed: bool,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+enum SwitchSnapshotKind {
+    Pressed,
+    LongPressed,
+    Released,
+    LongReleased,
+}
+
 impl<Ki> TimedEventData<Ki> {
     #[must_use]
     pub fn new(kind: Ki, num_clicks: NumPossibleClicks) -> Self {
@@ -69,15 +96,62 @@ impl<Ki> TimedEventData<Ki> {
     }
 }
 
-impl<Sw> From<HashMap<Sw, SwitchState>> for TimedState<Sw> {
+impl<Sw, Du> From<HashMap<Sw, SwitchState>> for TimedState<Sw, Du>
+where
+    Du: Default,
+{
     fn from(switches: HashMap<Sw, SwitchState>) -> Self {
-        Self { switches }
+        Self {
+            switches,
+            policies: TimedPolicies::default(),
+        }
     }
 }
 
-impl<Sw> TimedState<Sw> {
-    pub fn new() -> Self {
-        Self::default()
+impl<Sw, Du> TimedState<Sw, Du> {
+    pub fn new(policies: TimedPolicies<Sw, Du>) -> Self {
+        Self {
+            switches: HashMap::new(),
+            policies,
+        }
+    }
+
+    pub const fn policies(&self) -> &TimedPolicies<Sw, Du> {
+        &self.policies
+    }
+
+    pub const fn policies_mut(&mut self) -> &mut TimedPolicies<Sw, Du> {
+        &mut self.policies
+    }
+
+    pub fn policy(&self, switch: &Sw) -> &TimedPolicy<Du>
+    where
+        Sw: Eq + Hash,
+    {
+        self.policies.policy(switch)
+    }
+
+    pub fn long_press_deadline<Ti>(&self, switch: &Sw, time: Ti) -> Ti
+    where
+        Sw: Eq + Hash,
+        Ti: Add<Du, Output = Ti>,
+        Du: Clone,
+    {
+        self.policy(switch).long_press_deadline(time)
+    }
+
+    pub fn click_exact_deadline<Ti>(
+        &self,
+        switch: &Sw,
+        time: Ti,
+        data: &TimedReleaseEventData,
+    ) -> Ti
+    where
+        Sw: Eq + Hash,
+        Ti: Add<Du, Output = Ti>,
+        Du: Clone,
+    {
+        self.policy(switch).click_exact_deadline(time, data)
     }
 
     pub fn iter_switches(&self) -> impl Iterator<Item = &Sw>
@@ -87,15 +161,115 @@ impl<Sw> TimedState<Sw> {
         self.switches.keys()
     }
 
+    pub fn snapshot(&self) -> TimedStateSnapshot<Sw>
+    where
+        Sw: Clone,
+    {
+        let switches = self
+            .switches
+            .iter()
+            .map(|(switch, state)| (switch.clone(), state.snapshot()))
+            .collect();
+        TimedStateSnapshot { switches }
+    }
+
+    // Handle requests issued before the restore are no longer handled.
+    pub fn restore(&mut self, snapshot: TimedStateSnapshot<Sw>)
+    where
+        Sw: Eq + Hash,
+    {
+        self.switches = snapshot
+            .switches
+            .into_iter()
+            .map(|(switch, snapshot)| (switch, SwitchState::restore(snapshot)))
+            .collect();
+    }
+
+    pub fn long_press_request(&self, switch: &Sw) -> Option<LongPressHandleRequest>
+    where
+        Sw: Eq + Hash,
+    {
+        match &self.switches.get(switch)?.kind {
+            SwitchStateKind::Pressed(tag) => Some(LongPressHandleRequest(Arc::downgrade(tag))),
+            _ => None,
+        }
+    }
+
+    pub fn click_exact_request(&self, switch: &Sw) -> Option<ClickExactHandleRequest>
+    where
+        Sw: Eq + Hash,
+    {
+        match &self.switches.get(switch)?.kind {
+            SwitchStateKind::Released(tag) | SwitchStateKind::LongReleased(tag) => {
+                Some(ClickExactHandleRequest(Arc::downgrade(tag)))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn iter_pressed(&self) -> impl Iterator<Item = &Sw> {
+        self.switches
+            .iter()
+            .filter(|(_, state)| {
+                matches!(
+                    state.kind,
+                    SwitchStateKind::Pressed(_) | SwitchStateKind::LongPressed
+                )
+            })
+            .map(|(switch, _)| switch)
+    }
+
+    // The switch stays pressed, but its scheduled long press is dropped.
+    pub fn cancel_long_press(&mut self, switch: &Sw)
+    where
+        Sw: Eq + Hash,
+    {
+        if let Some(state) = self.switches.get_mut(switch) {
+            if let SwitchStateKind::Pressed(tag) = &mut state.kind {
+                *tag = Arc::new(());
+            }
+        }
+    }
+
+    // Until its release, the press no longer counts: its long press is dropped
+    // and the release neither clicks nor schedules an exact click.
+    pub fn suppress(&mut self, switch: &Sw)
+    where
+        Sw: Eq + Hash,
+    {
+        self.cancel_long_press(switch);
+        if let Some(state) = self.switches.get_mut(switch) {
+            state.is_suppressed = matches!(
+                state.kind,
+                SwitchStateKind::Pressed(_) | SwitchStateKind::LongPressed
+            );
+        }
+    }
+
+    pub fn is_suppressed(&self, switch: &Sw) -> bool
+    where
+        Sw: Eq + Hash,
+    {
+        self.switches
+            .get(switch)
+            .is_some_and(|state| state.is_suppressed)
+    }
+
+    // Drops click counts along with pending long presses and exact clicks.
+    pub fn reset(&mut self) {
+        self.switches.clear();
+    }
+
     pub fn on_press_event(&mut self, switch: Sw) -> Result<LongPressHandleRequest, TimedPressError>
     where
         Sw: Eq + Hash,
     {
         use std::collections::hash_map::Entry;
 
+        let max_clicks = self.policies.policy(&switch).max_clicks;
         let entry = self.switches.entry(switch);
         match entry {
-            Entry::Occupied(mut entry) => entry.get_mut().on_press_event(),
+            Entry::Occupied(mut entry) => entry.get_mut().on_press_event(max_clicks),
             Entry::Vacant(entry) => {
                 let (state, request) = SwitchState::from_pressed();
                 let _: &mut _ = entry.insert(state);
@@ -115,6 +289,11 @@ impl<Sw> TimedState<Sw> {
 
         let entry = self.switches.entry(switch);
         match entry {
+            // The clicks of a suppressed press start over.
+            Entry::Occupied(entry) if entry.get().is_suppressed => {
+                let _ = entry.remove();
+                Ok(None)
+            }
             Entry::Occupied(mut entry) => entry.get_mut().on_release_event().map(Some),
             Entry::Vacant(_) => Ok(None),
         }
@@ -193,11 +372,12 @@ impl<Sw> TimedState<Sw> {
     }
 }
 
-impl<Sw> Default for TimedState<Sw> {
+impl<Sw, Du> Default for TimedState<Sw, Du>
+where
+    Du: Default,
+{
     fn default() -> Self {
-        Self {
-            switches: HashMap::default(),
-        }
+        Self::new(TimedPolicies::default())
     }
 }
 
@@ -206,6 +386,34 @@ impl SwitchState {
         Self {
             kind,
             num_possible_clicks: num_clicks,
+            is_suppressed: false,
+        }
+    }
+
+    const fn snapshot(&self) -> SwitchSnapshot {
+        let kind = match self.kind {
+            SwitchStateKind::Pressed(_) => SwitchSnapshotKind::Pressed,
+            SwitchStateKind::LongPressed => SwitchSnapshotKind::LongPressed,
+            SwitchStateKind::Released(_) => SwitchSnapshotKind::Released,
+            SwitchStateKind::LongReleased(_) => SwitchSnapshotKind::LongReleased,
+        };
+        SwitchSnapshot {
+            kind,
+            num_possible_clicks: self.num_possible_clicks,
+            is_suppressed: self.is_suppressed,
+        }
+    }
+
+    fn restore(snapshot: SwitchSnapshot) -> Self {
+        let kind = match snapshot.kind {
+            SwitchSnapshotKind::Pressed => SwitchStateKind::Pressed(Arc::new(())),
+            SwitchSnapshotKind::LongPressed => SwitchStateKind::LongPressed,
+            SwitchSnapshotKind::Released => SwitchStateKind::Released(Arc::new(())),
+            SwitchSnapshotKind::LongReleased => SwitchStateKind::LongReleased(Arc::new(())),
+        };
+        Self {
+            is_suppressed: snapshot.is_suppressed,
+            ..Self::new(kind, snapshot.num_possible_clicks)
         }
     }
 
@@ -216,7 +424,10 @@ impl SwitchState {
         (state, request)
     }
 
-    fn on_press_event(&mut self) -> Result<LongPressHandleRequest, TimedPressError> {
+    fn on_press_event(
+        &mut self,
+        max_clicks: NumPossibleClicks,
+    ) -> Result<LongPressHandleRequest, TimedPressError> {
         use SwitchStateKind::{LongPressed, LongReleased, Pressed, Released};
 
         match self.kind {
@@ -226,7 +437,11 @@ impl SwitchState {
                 let tag = Arc::new(());
                 let request = LongPressHandleRequest(Arc::downgrade(&tag));
                 self.kind = Pressed(tag);
-                self.num_possible_clicks += 1;
+                if self.num_possible_clicks >= max_clicks {
+                    self.num_possible_clicks = 1;
+                } else {
+                    self.num_possible_clicks += 1;
+                }
                 Ok(request)
             }
         }
@@ -310,6 +525,18 @@ impl SwitchState {
     }
 }
 
+impl HandleRequest for LongPressHandleRequest {
+    fn is_alive(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+}
+
+impl HandleRequest for ClickExactHandleRequest {
+    fn is_alive(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+}
+
 #[derive(Clone, Copy, Debug, Error)]
 pub enum TimedPressError {
     #[error("Button is pressed while in Pressed state")]
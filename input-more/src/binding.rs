@@ -1,7 +1,10 @@
 use input_core::{
-    Modifiers, PointerChangeEventData, PointerMoveEventData, TimedClickExactEventData,
-    TimedLongPressEventData, TimedReleaseEventData,
+    GestureEventKind, ModifierAliases, Modifiers, PointerChangeEventData, PointerMoveEventData,
+    TimedClickExactEventData, TimedLongPressEventData, TimedReleaseEventData,
 };
+use serde::{Deserialize, Serialize};
+
+use crate::ContextExpr;
 
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum Binding<Sw, Tr, Mo, Ev> {
@@ -11,8 +14,12 @@ pub enum Binding<Sw, Tr, Mo, Ev> {
     ),
     LongPress(SwitchBinding<Sw, Mo, TimedLongPressEventData, (), Ev>),
     ClickExact(SwitchBinding<Sw, Mo, TimedClickExactEventData, (), Ev>),
+    Repeat(SwitchBinding<Sw, Mo, (), (), Ev>),
     Trigger(TriggerBinding<Tr, Mo, Ev>),
     Coords(CoordsBinding<PointerMoveEventData<Sw>, Mo, Ev>),
+    Gesture(CoordsBinding<GestureEventKind, Mo, Ev>),
+    Axis(CoordsBinding<Tr, Mo, Ev>),
+    Sequence(SequenceBinding<Sw, Mo, Ev>),
 }
 
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -21,6 +28,10 @@ pub struct SwitchBinding<Sw, Mo, Td, Pd, Ev> {
     pub modifiers: Modifiers<Mo>,
     pub timed_data: Td,
     pub pointer_data: Pd,
+    pub when: Option<ContextExpr>,
+    pub target: Option<ContextExpr>,
+    pub priority: i32,
+    pub propagation: BindingPropagation,
     pub event: Ev,
 }
 
@@ -28,6 +39,9 @@ pub struct SwitchBinding<Sw, Mo, Td, Pd, Ev> {
 pub struct TriggerBinding<Tr, Mo, Ev> {
     pub trigger: Tr,
     pub modifiers: Modifiers<Mo>,
+    pub when: Option<ContextExpr>,
+    pub priority: i32,
+    pub propagation: BindingPropagation,
     pub event: Ev,
 }
 
@@ -35,18 +49,264 @@ pub struct TriggerBinding<Tr, Mo, Ev> {
 pub struct CoordsBinding<Pd, Mo, Ev> {
     pub pointer_data: Pd,
     pub modifiers: Modifiers<Mo>,
+    pub when: Option<ContextExpr>,
+    pub target: Option<ContextExpr>,
+    pub priority: i32,
+    pub propagation: BindingPropagation,
+    pub event: Ev,
+}
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SequenceBinding<Sw, Mo, Ev> {
+    pub prefix: Vec<SequenceStep<Sw, Mo>>,
+    pub switch: Sw,
+    pub modifiers: Modifiers<Mo>,
+    pub when: Option<ContextExpr>,
+    pub priority: i32,
+    pub propagation: BindingPropagation,
+    pub event: Ev,
+}
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct BindingEvent<Ev> {
+    pub when: Option<ContextExpr>,
+    pub target: Option<ContextExpr>,
+    pub priority: i32,
+    pub propagation: BindingPropagation,
     pub event: Ev,
 }
 
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum BindingPropagation {
+    #[default]
+    Consume,
+    Passthrough,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(bound(deserialize = "Sw: Deserialize<'de>, Mo: Deserialize<'de> + Ord"))]
+pub struct SequenceStep<Sw, Mo> {
+    pub switch: Sw,
+    pub modifiers: Modifiers<Mo>,
+}
+
 impl<Sw, Tr, Mo, Ev> Binding<Sw, Tr, Mo, Ev> {
+    pub const fn when(&self) -> Option<&ContextExpr> {
+        match self {
+            Self::Press(binding) | Self::Repeat(binding) => binding.when.as_ref(),
+            Self::Release(binding) => binding.when.as_ref(),
+            Self::LongPress(binding) => binding.when.as_ref(),
+            Self::ClickExact(binding) => binding.when.as_ref(),
+            Self::Trigger(binding) => binding.when.as_ref(),
+            Self::Coords(binding) => binding.when.as_ref(),
+            Self::Gesture(binding) => binding.when.as_ref(),
+            Self::Axis(binding) => binding.when.as_ref(),
+            Self::Sequence(binding) => binding.when.as_ref(),
+        }
+    }
+
+    pub const fn target(&self) -> Option<&ContextExpr> {
+        match self {
+            Self::Press(binding) | Self::Repeat(binding) => binding.target.as_ref(),
+            Self::Release(binding) => binding.target.as_ref(),
+            Self::LongPress(binding) => binding.target.as_ref(),
+            Self::ClickExact(binding) => binding.target.as_ref(),
+            Self::Coords(binding) => binding.target.as_ref(),
+            Self::Gesture(binding) => binding.target.as_ref(),
+            Self::Axis(binding) => binding.target.as_ref(),
+            Self::Trigger(_) | Self::Sequence(_) => None,
+        }
+    }
+
+    // Sequence steps are matched exactly, so they drop the side of the modifiers.
+    #[must_use]
+    pub fn with_modifier_aliases(&self, aliases: &ModifierAliases<Mo>) -> Self
+    where
+        Sw: Clone,
+        Tr: Clone,
+        Mo: Clone + Ord,
+        Ev: Clone,
+    {
+        let mut binding = self.clone();
+        match &mut binding {
+            Self::Press(binding) | Self::Repeat(binding) => {
+                binding.modifiers = aliases.expand(&binding.modifiers);
+            }
+            Self::Release(binding) => binding.modifiers = aliases.expand(&binding.modifiers),
+            Self::LongPress(binding) => binding.modifiers = aliases.expand(&binding.modifiers),
+            Self::ClickExact(binding) => binding.modifiers = aliases.expand(&binding.modifiers),
+            Self::Trigger(binding) => binding.modifiers = aliases.expand(&binding.modifiers),
+            Self::Coords(binding) => binding.modifiers = aliases.expand(&binding.modifiers),
+            Self::Gesture(binding) => binding.modifiers = aliases.expand(&binding.modifiers),
+            Self::Axis(binding) => binding.modifiers = aliases.expand(&binding.modifiers),
+            Self::Sequence(binding) => {
+                for step in &mut binding.prefix {
+                    step.modifiers = aliases.generalize(&step.modifiers);
+                }
+                binding.modifiers = aliases.generalize(&binding.modifiers);
+            }
+        }
+        binding
+    }
+
+    pub const fn priority(&self) -> i32 {
+        match self {
+            Self::Press(binding) | Self::Repeat(binding) => binding.priority,
+            Self::Release(binding) => binding.priority,
+            Self::LongPress(binding) => binding.priority,
+            Self::ClickExact(binding) => binding.priority,
+            Self::Trigger(binding) => binding.priority,
+            Self::Coords(binding) => binding.priority,
+            Self::Gesture(binding) => binding.priority,
+            Self::Axis(binding) => binding.priority,
+            Self::Sequence(binding) => binding.priority,
+        }
+    }
+
+    pub const fn propagation(&self) -> BindingPropagation {
+        match self {
+            Self::Press(binding) | Self::Repeat(binding) => binding.propagation,
+            Self::Release(binding) => binding.propagation,
+            Self::LongPress(binding) => binding.propagation,
+            Self::ClickExact(binding) => binding.propagation,
+            Self::Trigger(binding) => binding.propagation,
+            Self::Coords(binding) => binding.propagation,
+            Self::Gesture(binding) => binding.propagation,
+            Self::Axis(binding) => binding.propagation,
+            Self::Sequence(binding) => binding.propagation,
+        }
+    }
+
     pub fn modifiers(&self) -> &Modifiers<Mo> {
         match self {
-            Self::Press(binding) => &binding.modifiers,
+            Self::Press(binding) | Self::Repeat(binding) => &binding.modifiers,
             Self::Release(binding) => &binding.modifiers,
             Self::LongPress(binding) => &binding.modifiers,
             Self::ClickExact(binding) => &binding.modifiers,
             Self::Trigger(binding) => &binding.modifiers,
             Self::Coords(binding) => &binding.modifiers,
+            Self::Gesture(binding) => &binding.modifiers,
+            Self::Axis(binding) => &binding.modifiers,
+            Self::Sequence(binding) => &binding.modifiers,
+        }
+    }
+
+    pub fn iter_modifiers(&self) -> impl Iterator<Item = &Modifiers<Mo>> {
+        let prefix = match self {
+            Self::Sequence(binding) => binding.prefix.as_slice(),
+            _ => &[],
+        };
+        prefix
+            .iter()
+            .map(|step| &step.modifiers)
+            .chain(core::iter::once(self.modifiers()))
+    }
+}
+
+impl<Sw, Mo, Td, Pd, Ev> SwitchBinding<Sw, Mo, Td, Pd, Ev> {
+    pub fn new(
+        switch: Sw,
+        modifiers: Modifiers<Mo>,
+        timed_data: Td,
+        pointer_data: Pd,
+        event: Ev,
+    ) -> Self {
+        Self {
+            switch,
+            modifiers,
+            timed_data,
+            pointer_data,
+            when: None,
+            target: None,
+            priority: 0,
+            propagation: BindingPropagation::default(),
+            event,
+        }
+    }
+
+    #[must_use]
+    pub fn with_when(mut self, when: ContextExpr) -> Self {
+        self.when = Some(when);
+        self
+    }
+
+    #[must_use]
+    pub fn with_target(mut self, target: ContextExpr) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    #[must_use]
+    pub const fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_propagation(mut self, propagation: BindingPropagation) -> Self {
+        self.propagation = propagation;
+        self
+    }
+}
+
+impl<Pd, Mo, Ev> CoordsBinding<Pd, Mo, Ev> {
+    pub fn new(pointer_data: Pd, modifiers: Modifiers<Mo>, event: Ev) -> Self {
+        Self {
+            pointer_data,
+            modifiers,
+            when: None,
+            target: None,
+            priority: 0,
+            propagation: BindingPropagation::default(),
+            event,
         }
     }
+
+    #[must_use]
+    pub fn with_when(mut self, when: ContextExpr) -> Self {
+        self.when = Some(when);
+        self
+    }
+
+    #[must_use]
+    pub fn with_target(mut self, target: ContextExpr) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    #[must_use]
+    pub const fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_propagation(mut self, propagation: BindingPropagation) -> Self {
+        self.propagation = propagation;
+        self
+    }
+}
+
+impl<Ev> BindingEvent<Ev> {
+    pub const fn new(
+        when: Option<ContextExpr>,
+        target: Option<ContextExpr>,
+        priority: i32,
+        propagation: BindingPropagation,
+        event: Ev,
+    ) -> Self {
+        Self {
+            when,
+            target,
+            priority,
+            propagation,
+            event,
+        }
+    }
+}
+
+impl<Sw, Mo> SequenceStep<Sw, Mo> {
+    pub const fn new(switch: Sw, modifiers: Modifiers<Mo>) -> Self {
+        Self { switch, modifiers }
+    }
 }
@@ -0,0 +1,68 @@
+use input_core::Modifiers;
+
+use crate::BindingEvent;
+
+#[derive(Clone, Debug)]
+pub struct BuildReport<'a, Mo, Bu> {
+    entries: Vec<BuildReportEntry<'a, Mo, Bu>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BuildReportEntry<'a, Mo, Bu> {
+    pub modifiers: &'a Modifiers<Mo>,
+    pub binding: &'a BindingEvent<Bu>,
+    pub outcome: BindingOutcome,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum BindingOutcome {
+    Applied,
+    Disabled,
+    Shadowed,
+    Conflicted,
+}
+
+impl<'a, Mo, Bu> BuildReport<'a, Mo, Bu> {
+    #[must_use]
+    pub const fn new(entries: Vec<BuildReportEntry<'a, Mo, Bu>>) -> Self {
+        Self { entries }
+    }
+
+    #[must_use]
+    pub fn entries(&self) -> &[BuildReportEntry<'a, Mo, Bu>] {
+        &self.entries
+    }
+
+    #[must_use]
+    pub fn into_entries(self) -> Vec<BuildReportEntry<'a, Mo, Bu>> {
+        self.entries
+    }
+
+    pub fn by_outcome(
+        &self,
+        outcome: BindingOutcome,
+    ) -> impl Iterator<Item = &BuildReportEntry<'a, Mo, Bu>> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.outcome == outcome)
+    }
+
+    #[must_use]
+    pub fn has_conflicts(&self) -> bool {
+        self.by_outcome(BindingOutcome::Conflicted).next().is_some()
+    }
+}
+
+impl<'a, Mo, Bu> BuildReportEntry<'a, Mo, Bu> {
+    pub const fn new(
+        modifiers: &'a Modifiers<Mo>,
+        binding: &'a BindingEvent<Bu>,
+        outcome: BindingOutcome,
+    ) -> Self {
+        Self {
+            modifiers,
+            binding,
+            outcome,
+        }
+    }
+}
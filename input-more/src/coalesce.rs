@@ -0,0 +1,244 @@
+use core::mem::{replace, take};
+
+use input_core::CoordsDistance;
+
+use crate::{CoordsEvent, RawEvent, TouchEvent};
+
+// A run of consecutive coords events of one lane. Drags are detected from
+// the farthest and the last coords of the run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CoalescedCoords<Ti, Co> {
+    pub first: CoordsEvent<Ti, Co>,
+    pub last: CoordsEvent<Ti, Co>,
+    // The event farthest from `first` unless it is `last`, so that a drag
+    // beyond the slop is not lost when the pointer comes back within the run.
+    pub farthest: Option<CoordsEvent<Ti, Co>>,
+    // Events between `first` and `last`, kept only if the coalescer asks for it.
+    pub history: Vec<CoordsEvent<Ti, Co>>,
+    pub num_events: usize,
+}
+
+// A run of consecutive touch moves, coalesced per touch in the order the
+// touches first moved in the run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CoalescedTouchMoves<Ti, ToSw, Co> {
+    pub touches: Vec<(ToSw, CoalescedCoords<Ti, Co>)>,
+    // The touch that moved last, whose time ends the run.
+    last: usize,
+}
+
+// Pen events are kept as they are, as their pressure and tool press and
+// release switches and a coalesced run could skip a threshold crossing.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CoalescedEvent<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co> {
+    Raw(RawEvent<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co>),
+    KeyboardCoords(CoalescedCoords<Ti, Co>),
+    MouseCoords(CoalescedCoords<Ti, Co>),
+    TouchMove(CoalescedTouchMoves<Ti, ToSw, Co>),
+}
+
+// Buffers raw events until the next frame. Other events keep their place
+// between the runs, so no binding is reordered relative to the moves.
+#[derive(Clone, Debug)]
+pub struct CoordsCoalescer<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co> {
+    events: Vec<CoalescedEvent<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co>>,
+    keeps_history: bool,
+}
+
+impl<Ti, Co> CoalescedCoords<Ti, Co> {
+    pub fn new(event: CoordsEvent<Ti, Co>) -> Self
+    where
+        Ti: Clone,
+        Co: Clone,
+    {
+        Self {
+            first: event.clone(),
+            last: event,
+            farthest: None,
+            history: Vec::new(),
+            num_events: 1,
+        }
+    }
+
+    pub fn push(&mut self, event: CoordsEvent<Ti, Co>, keeps_history: bool)
+    where
+        Ti: Clone,
+        Co: Clone + CoordsDistance,
+    {
+        let prev = replace(&mut self.last, event);
+        let farthest = match self.farthest.take() {
+            Some(farthest) if self.distance(&farthest) > self.distance(&prev) => farthest,
+            _ => prev.clone(),
+        };
+        self.farthest = (self.distance(&farthest) > self.distance(&self.last)).then_some(farthest);
+        if keeps_history && self.num_events > 1 {
+            self.history.push(prev);
+        }
+        self.num_events += 1;
+    }
+
+    pub fn into_events(self) -> Vec<CoordsEvent<Ti, Co>> {
+        self.farthest.into_iter().chain([self.last]).collect()
+    }
+
+    fn distance(&self, event: &CoordsEvent<Ti, Co>) -> f64
+    where
+        Co: CoordsDistance,
+    {
+        self.first.coords.distance(&event.coords)
+    }
+}
+
+impl<Ti, ToSw, Co> CoalescedTouchMoves<Ti, ToSw, Co> {
+    pub fn new(event: TouchEvent<Ti, ToSw, Co>) -> Self
+    where
+        Ti: Clone,
+        Co: Clone,
+    {
+        let coords = CoalescedCoords::new(CoordsEvent::new(event.time, event.coords));
+        Self {
+            touches: vec![(event.id, coords)],
+            last: 0,
+        }
+    }
+
+    pub fn push(&mut self, event: TouchEvent<Ti, ToSw, Co>, keeps_history: bool)
+    where
+        Ti: Clone,
+        ToSw: PartialEq,
+        Co: Clone + CoordsDistance,
+    {
+        let coords = CoordsEvent::new(event.time, event.coords);
+        if let Some(j) = self.touches.iter().position(|(id, _)| *id == event.id) {
+            self.touches[j].1.push(coords, keeps_history);
+            self.last = j;
+        } else {
+            self.touches.push((event.id, CoalescedCoords::new(coords)));
+            self.last = self.touches.len() - 1;
+        }
+    }
+
+    #[must_use]
+    pub fn time(&self) -> &Ti {
+        &self.touches[self.last].1.last.time
+    }
+
+    // The moves of different touches are interleaved back by their time.
+    #[must_use]
+    pub fn into_events(self) -> Vec<TouchEvent<Ti, ToSw, Co>>
+    where
+        Ti: Ord,
+        ToSw: Clone,
+    {
+        let mut events: Vec<_> = self
+            .touches
+            .into_iter()
+            .flat_map(|(id, coords)| {
+                coords
+                    .into_events()
+                    .into_iter()
+                    .map(move |event| TouchEvent::new(event.time, id.clone(), event.coords))
+            })
+            .collect();
+        events.sort_by(|event, other| event.time.cmp(&other.time));
+        events
+    }
+}
+
+impl<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co> CoalescedEvent<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co> {
+    pub fn time(&self) -> &Ti {
+        match self {
+            Self::Raw(event) => event.time(),
+            Self::KeyboardCoords(coords) | Self::MouseCoords(coords) => &coords.last.time,
+            Self::TouchMove(moves) => moves.time(),
+        }
+    }
+
+    pub fn into_raw(self) -> Vec<RawEvent<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co>>
+    where
+        Ti: Ord,
+        ToSw: Clone,
+    {
+        match self {
+            Self::Raw(event) => vec![event],
+            Self::KeyboardCoords(coords) => coords
+                .into_events()
+                .into_iter()
+                .map(RawEvent::KeyboardCoords)
+                .collect(),
+            Self::MouseCoords(coords) => coords
+                .into_events()
+                .into_iter()
+                .map(RawEvent::MouseCoords)
+                .collect(),
+            Self::TouchMove(moves) => moves
+                .into_events()
+                .into_iter()
+                .map(RawEvent::TouchMove)
+                .collect(),
+        }
+    }
+}
+
+impl<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co> Default
+    for CoordsCoalescer<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co>
+{
+    fn default() -> Self {
+        Self {
+            events: Vec::new(),
+            keeps_history: false,
+        }
+    }
+}
+
+impl<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co> CoordsCoalescer<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub const fn with_history(mut self, keeps_history: bool) -> Self {
+        self.keeps_history = keeps_history;
+        self
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn push(&mut self, event: RawEvent<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co>)
+    where
+        Ti: Clone,
+        ToSw: PartialEq,
+        Co: Clone + CoordsDistance,
+    {
+        let keeps_history = self.keeps_history;
+        match (self.events.last_mut(), event) {
+            (Some(CoalescedEvent::KeyboardCoords(coords)), RawEvent::KeyboardCoords(event))
+            | (Some(CoalescedEvent::MouseCoords(coords)), RawEvent::MouseCoords(event)) => {
+                coords.push(event, keeps_history);
+            }
+            (Some(CoalescedEvent::TouchMove(moves)), RawEvent::TouchMove(event)) => {
+                moves.push(event, keeps_history);
+            }
+            (_, RawEvent::KeyboardCoords(event)) => self
+                .events
+                .push(CoalescedEvent::KeyboardCoords(CoalescedCoords::new(event))),
+            (_, RawEvent::MouseCoords(event)) => self
+                .events
+                .push(CoalescedEvent::MouseCoords(CoalescedCoords::new(event))),
+            (_, RawEvent::TouchMove(event)) => self
+                .events
+                .push(CoalescedEvent::TouchMove(CoalescedTouchMoves::new(event))),
+            (_, event) => self.events.push(CoalescedEvent::Raw(event)),
+        }
+    }
+
+    // Called once per frame, the events are expected to be passed to
+    // `GlobalState::with_coalesced_events` in order.
+    pub fn take(&mut self) -> Vec<CoalescedEvent<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co>> {
+        take(&mut self.events)
+    }
+}
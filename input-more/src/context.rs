@@ -0,0 +1,329 @@
+use core::fmt;
+use core::iter::Peekable;
+use core::ops::Not;
+use core::str::{CharIndices, FromStr};
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ContextExpr {
+    Flag(String),
+    Equals(String, String),
+    Not(Box<Self>),
+    And(Box<Self>, Box<Self>),
+    Or(Box<Self>, Box<Self>),
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ContextStack {
+    layers: Vec<ContextLayer>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ContextLayer {
+    name: String,
+    flags: HashSet<String>,
+    values: HashMap<String, String>,
+}
+
+impl ContextExpr {
+    pub fn flag(name: impl Into<String>) -> Self {
+        Self::Flag(name.into())
+    }
+
+    pub fn equals(key: impl Into<String>, value: impl Into<String>) -> Self {
+        Self::Equals(key.into(), value.into())
+    }
+
+    #[must_use]
+    pub fn and(self, other: Self) -> Self {
+        Self::And(Box::new(self), Box::new(other))
+    }
+
+    #[must_use]
+    pub fn or(self, other: Self) -> Self {
+        Self::Or(Box::new(self), Box::new(other))
+    }
+
+    #[must_use]
+    pub fn eval(&self, context: &ContextStack) -> bool {
+        match self {
+            Self::Flag(name) => context.is_set(name),
+            Self::Equals(key, value) => context.value(key) == Some(value.as_str()),
+            Self::Not(expr) => !expr.eval(context),
+            Self::And(lhs, rhs) => lhs.eval(context) && rhs.eval(context),
+            Self::Or(lhs, rhs) => lhs.eval(context) || rhs.eval(context),
+        }
+    }
+}
+
+impl ContextStack {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn layers(&self) -> &[ContextLayer] {
+        &self.layers
+    }
+
+    #[must_use]
+    pub fn top(&self) -> Option<&ContextLayer> {
+        self.layers.last()
+    }
+
+    pub fn top_mut(&mut self) -> Option<&mut ContextLayer> {
+        self.layers.last_mut()
+    }
+
+    pub fn push(&mut self, layer: ContextLayer) {
+        self.layers.push(layer);
+    }
+
+    pub fn pop(&mut self) -> Option<ContextLayer> {
+        self.layers.pop()
+    }
+
+    pub fn clear(&mut self) {
+        self.layers.clear();
+    }
+
+    #[must_use]
+    pub fn is_set(&self, flag: &str) -> bool {
+        self.layers
+            .iter()
+            .any(|layer| layer.name == flag || layer.flags.contains(flag))
+    }
+
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|layer| layer.values.get(key))
+            .map(String::as_str)
+    }
+
+    #[must_use]
+    pub fn matches(&self, when: Option<&ContextExpr>) -> bool {
+        when.is_none_or(|expr| expr.eval(self))
+    }
+}
+
+impl ContextLayer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            flags: HashSet::new(),
+            values: HashMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_flag(mut self, flag: impl Into<String>) -> Self {
+        let _ = self.flags.insert(flag.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_value(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        let _ = self.values.insert(key.into(), value.into());
+        self
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_flag(&mut self, flag: impl Into<String>, is_set: bool) {
+        let flag = flag.into();
+        if is_set {
+            let _ = self.flags.insert(flag);
+        } else {
+            let _ = self.flags.remove(&flag);
+        }
+    }
+
+    pub fn set_value(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let _ = self.values.insert(key.into(), value.into());
+    }
+
+    pub fn remove_value(&mut self, key: &str) -> Option<String> {
+        self.values.remove(key)
+    }
+}
+
+impl Not for ContextExpr {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self::Not(Box::new(self))
+    }
+}
+
+impl FromStr for ContextExpr {
+    type Err = ContextExprParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = ContextExprParser {
+            chars: s.char_indices().peekable(),
+            len: s.len(),
+        };
+        let expr = parser.parse_or()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            None => Ok(expr),
+            Some((pos, ch)) => Err(ContextExprParseError::UnexpectedChar(pos, ch)),
+        }
+    }
+}
+
+impl fmt::Display for ContextExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flag(name) => write!(f, "{name}"),
+            Self::Equals(key, value) => write!(f, "{key} == '{value}'"),
+            Self::Not(expr) => match **expr {
+                Self::Flag(_) | Self::Not(_) => write!(f, "!{expr}"),
+                _ => write!(f, "!({expr})"),
+            },
+            Self::And(lhs, rhs) => {
+                fmt_operand(f, lhs)?;
+                write!(f, " && ")?;
+                fmt_operand(f, rhs)
+            }
+            Self::Or(lhs, rhs) => write!(f, "{lhs} || {rhs}"),
+        }
+    }
+}
+
+fn fmt_operand(f: &mut fmt::Formatter<'_>, expr: &ContextExpr) -> fmt::Result {
+    match expr {
+        ContextExpr::Or(_, _) => write!(f, "({expr})"),
+        _ => write!(f, "{expr}"),
+    }
+}
+
+struct ContextExprParser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    len: usize,
+}
+
+impl ContextExprParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, ch)| ch.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ContextExprParseError> {
+        match self.chars.next() {
+            Some((_, ch)) if ch == expected => Ok(()),
+            Some((pos, ch)) => Err(ContextExprParseError::UnexpectedChar(pos, ch)),
+            None => Err(ContextExprParseError::UnexpectedEnd(self.len)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<ContextExpr, ContextExprParseError> {
+        let mut expr = self.parse_and()?;
+        loop {
+            self.skip_whitespace();
+            if self.chars.next_if(|&(_, ch)| ch == '|').is_none() {
+                return Ok(expr);
+            }
+            self.expect('|')?;
+            expr = expr.or(self.parse_and()?);
+        }
+    }
+
+    fn parse_and(&mut self) -> Result<ContextExpr, ContextExprParseError> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            self.skip_whitespace();
+            if self.chars.next_if(|&(_, ch)| ch == '&').is_none() {
+                return Ok(expr);
+            }
+            self.expect('&')?;
+            expr = expr.and(self.parse_unary()?);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<ContextExpr, ContextExprParseError> {
+        self.skip_whitespace();
+        if self.chars.next_if(|&(_, ch)| ch == '!').is_some() {
+            return Ok(!self.parse_unary()?);
+        }
+        if self.chars.next_if(|&(_, ch)| ch == '(').is_some() {
+            let expr = self.parse_or()?;
+            self.skip_whitespace();
+            self.expect(')')?;
+            return Ok(expr);
+        }
+
+        let name = self.parse_ident()?;
+        self.skip_whitespace();
+        let is_equals = match self.chars.peek() {
+            Some(&(_, '=')) => true,
+            Some(&(_, '!')) => {
+                let mut lookahead = self.chars.clone();
+                let _ = lookahead.next();
+                if !matches!(lookahead.peek(), Some(&(_, '='))) {
+                    return Ok(ContextExpr::Flag(name));
+                }
+                false
+            }
+            _ => return Ok(ContextExpr::Flag(name)),
+        };
+        let _ = self.chars.next();
+        self.expect('=')?;
+        self.skip_whitespace();
+        let value = self.parse_value()?;
+        let expr = ContextExpr::Equals(name, value);
+        Ok(if is_equals { expr } else { !expr })
+    }
+
+    fn parse_ident(&mut self) -> Result<String, ContextExprParseError> {
+        let mut ident = String::new();
+        while let Some((_, ch)) = self.chars.next_if(|&(_, ch)| is_ident_char(ch)) {
+            ident.push(ch);
+        }
+        if ident.is_empty() {
+            match self.chars.peek() {
+                Some(&(pos, ch)) => Err(ContextExprParseError::UnexpectedChar(pos, ch)),
+                None => Err(ContextExprParseError::UnexpectedEnd(self.len)),
+            }
+        } else {
+            Ok(ident)
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<String, ContextExprParseError> {
+        let Some(&(_, quote @ ('\'' | '"'))) = self.chars.peek() else {
+            return self.parse_ident();
+        };
+        let (start, _) = self.chars.next().unwrap_or_default();
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, ch)) if ch == quote => return Ok(value),
+                Some((_, ch)) => value.push(ch),
+                None => return Err(ContextExprParseError::UnterminatedString(start)),
+            }
+        }
+    }
+}
+
+fn is_ident_char(ch: char) -> bool {
+    ch.is_alphanumeric() || matches!(ch, '_' | '.' | ':' | '-')
+}
+
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+pub enum ContextExprParseError {
+    #[error("Unexpected character {1:?} at position {0}")]
+    UnexpectedChar(usize, char),
+    #[error("Unexpected end of expression at position {0}")]
+    UnexpectedEnd(usize),
+    #[error("Unterminated string starting at position {0}")]
+    UnterminatedString(usize),
+}
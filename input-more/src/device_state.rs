@@ -1,24 +1,29 @@
 use core::borrow::BorrowMut;
+use core::fmt::Debug;
 use core::hash::Hash;
+use core::ops::{Add, Deref};
 
 use input_core::{
-    ClickExactHandleRequest, CoordsState, LongPressHandleRequest, Modifiers, PointerState,
-    SchedulerState, TimedEventData, TimedState, PointerChangeEventData,
+    AxisEventData, ClickExactHandleRequest, CoordsDistance, CoordsState, HandleRequest,
+    LongPressHandleRequest, ModifierKind, Modifiers, PointerChangeEventData, PointerState,
+    SchedulerSnapshot, SchedulerState, TimedEventData, TimedState,
 };
 
 use crate::{
-    CoordsEvent, DeviceMappingCache, FilteredBindings, MappingModifiersCache, SwitchEvent,
-    SwitchMappingCache, TriggerEvent,
+    CoordsEvent, DeviceMappingCache, FilteredBindings, HitTester, MappingModifiersCache, Recovery,
+    SwitchEvent, SwitchMappingCache, TriggerEvent,
 };
 
 #[derive(Clone, Debug, Default)]
-pub struct DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
+pub struct DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po, Rv, Ht> {
     pub modifiers: Mo,
     pub coords_state: Cs,
     pub timed_state: Ts,
     pub long_press_scheduler: ShLo,
     pub click_exact_scheduler: ShCl,
     pub pointer_state: Po,
+    pub recovery: Rv,
+    pub hit_tester: Ht,
 }
 
 /*
@@ -41,7 +46,8 @@ define_struct_take_and_with_field!(DeviceState {
 });
 */
 
-impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
+impl<Mo, Cs, Ts, ShLo, ShCl, Po, Rv, Ht> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po, Rv, Ht> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         modifiers: Mo,
         coords_state: Cs,
@@ -49,6 +55,8 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
         long_press_scheduler: ShLo,
         click_exact_scheduler: ShCl,
         pointer_state: Po,
+        recovery: Rv,
+        hit_tester: Ht,
     ) -> Self {
         Self {
             modifiers,
@@ -57,6 +65,8 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
             long_press_scheduler,
             click_exact_scheduler,
             pointer_state,
+            recovery,
+            hit_tester,
         }
     }
 }
@@ -64,8 +74,11 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
 pub type DeviceSchedulerState<Ti, Sw, Mo, Co, Re> =
     SchedulerState<Ti, (SwitchEvent<Ti, Sw>, Modifiers<Mo>, Co), Re>;
 
-impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
-    pub fn with_press_event<'a, Sw, MoMo, Ti, Co, Tr, Ev>(
+pub type DeviceSchedulerSnapshot<Ti, Sw, Mo, Co> =
+    SchedulerSnapshot<Ti, (SwitchEvent<Ti, Sw>, Modifiers<Mo>, Co)>;
+
+impl<Mo, Cs, Ts, ShLo, ShCl, Po, Rv, Ht> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po, Rv, Ht> {
+    pub fn with_press_event<'a, Sw, MoMo, Ti, Du, Co, Tr, Ev>(
         &mut self,
         event: SwitchEvent<Ti, Sw>,
         mapping: &'a DeviceMappingCache<Sw, Tr, MoMo, Ev>,
@@ -74,21 +87,18 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
     where
         Mo: BorrowMut<Modifiers<MoMo>>,
         Cs: BorrowMut<CoordsState<Co>>,
-        Ts: BorrowMut<TimedState<Sw>>,
+        Ts: BorrowMut<TimedState<Sw, Du>>,
         ShLo: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, LongPressHandleRequest>>,
         ShCl: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, ClickExactHandleRequest>>,
-        Po: BorrowMut<PointerState<Sw, Co>>,
-        Sw: Clone + Eq + Hash,
+        Po: BorrowMut<PointerState<Sw, Co, Ti, Du>>,
+        Rv: BorrowMut<Recovery>,
+        Ht: Deref,
+        Ht::Target: HitTester<Co>,
+        Sw: Clone + Eq + Hash + Debug,
         MoMo: Clone + Eq + From<Sw> + Hash + Ord,
-        Ti: Clone + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + Debug,
+        Du: Clone,
         Co: Clone,
-        // TODO: Remove after debugging
-        Ev: std::fmt::Debug,
-        Ti: std::fmt::Debug,
-        Sw: std::fmt::Debug,
-        Tr: std::fmt::Debug,
-        MoMo: std::fmt::Debug,
-        Ev: std::fmt::Debug,
     {
         use crate::unwrap_or_return;
 
@@ -102,11 +112,26 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
         }
 
         if is_used_as_modifier {
-            let result = self.modifiers.borrow_mut().on_press_event(modifier);
+            let modifiers = self.modifiers.borrow_mut();
+            let result = match mapping_modifiers.kind(&modifier) {
+                ModifierKind::Momentary => {
+                    modifiers.on_aliased_press_event(modifier, mapping_modifiers.aliases())
+                }
+                ModifierKind::Lock => {
+                    let _ = modifiers.on_lock_press_event(modifier);
+                    Ok(())
+                }
+                ModifierKind::Latch => {
+                    modifiers.on_latch_press_event(modifier);
+                    Ok(())
+                }
+            };
+            // A pressed modifier is already in sync with the host.
             if let Err(err) = result {
-                eprintln!(
-                    "input_more::DeviceState::with_press_event: input_core::Modifiers::on_press_event returned an error: {:?} for event: {:?}",
-                    err, event
+                let _ = self.recovery.borrow_mut().on_error(
+                    "input_core::Modifiers::on_press_event",
+                    &err,
+                    &event,
                 );
             }
         }
@@ -121,21 +146,34 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
             .timed_state
             .borrow_mut()
             .on_press_event(event.switch.clone());
-        match result {
-            Ok(request) => self.long_press_scheduler.borrow_mut().schedule(
-                event.time.clone(),
+        let result = match result {
+            Err(err)
+                if self.recovery.borrow_mut().on_error(
+                    "input_core::TimedState::on_press_event",
+                    &err,
+                    &event,
+                ) =>
+            {
+                // The missed release starts the clicks over.
+                let timed_state = self.timed_state.borrow_mut();
+                let _ = timed_state.on_release_event(event.switch.clone());
+                let _ = timed_state.on_reset_click_count(&event.switch);
+                timed_state.on_press_event(event.switch.clone())
+            }
+            result => result,
+        };
+        if let Ok(request) = result {
+            self.long_press_scheduler.borrow_mut().schedule(
+                self.timed_state
+                    .borrow()
+                    .long_press_deadline(&event.switch, event.time.clone()),
                 (
                     event.clone(),
                     self.modifiers.borrow().clone(),
                     self.coords_state.borrow().coords().clone(),
                 ),
                 request,
-            ),
-            Err(err) => 
-            eprintln!(
-                "input_more::DeviceState::with_press_event: input_core::TimedState::on_press_event returned an error: {:?} for event: {:?}",
-                err, event
-            ),
+            );
         }
 
         let next_scheduled = self.long_press_scheduler.borrow().next_scheduled().cloned();
@@ -144,15 +182,25 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
             .press
             .and_then(|mapping| mapping.filter_by_timed_data(&()));
 
-        let result = self
-            .pointer_state
-            .borrow_mut()
-            .on_press_event(event.switch.clone(), self.coords_state.borrow().coords().clone());
+        let result = self.pointer_state.borrow_mut().on_press_event(
+            event.switch.clone(),
+            self.coords_state.borrow().coords().clone(),
+            event.time.clone(),
+        );
         if let Err(err) = result {
-            eprintln!(
-                "input_more::DeviceState::with_press_event: input_core::PointerState::on_press_event returned an error: {:?} for event: {:?}",
-                err, event
-            );
+            if self.recovery.borrow_mut().on_error(
+                "input_core::PointerState::on_press_event",
+                &err,
+                &event,
+            ) {
+                let pointer_state = self.pointer_state.borrow_mut();
+                let _ = pointer_state.on_release_event(&event.switch);
+                let _ = pointer_state.on_press_event(
+                    event.switch.clone(),
+                    self.coords_state.borrow().coords().clone(),
+                    event.time.clone(),
+                );
+            }
         }
         let mapping = unwrap_or_return!(mapping, (next_scheduled, None)); // FIXME
 
@@ -161,36 +209,32 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
 
         let coords = self.coords_state.borrow().coords().clone();
 
+        let mapping = mapping.filter_by_target(&*self.hit_tester, &coords);
+        let mapping = unwrap_or_return!(mapping, (next_scheduled, None));
+
         (next_scheduled, Some((mapping, coords)))
     }
 
-    pub fn with_press_timeout<'a, Sw, MoMo, Ti, Co, Tr, Ev>(
+    pub fn with_press_timeout<'a, Sw, MoMo, Ti, Du, Co, Tr, Ev>(
         &mut self,
-        time_minus_long_press_duration: Ti, // TODO: Time at Long press handling event already happend for time before that
+        time: Ti,
         mapping: &'a DeviceMappingCache<Sw, Tr, MoMo, Ev>,
     ) -> Vec<(FilteredBindings<'a, MoMo, Ev>, Co)>
     where
         Mo: BorrowMut<Modifiers<MoMo>>,
         Cs: BorrowMut<CoordsState<Co>>,
-        Ts: BorrowMut<TimedState<Sw>>,
+        Ts: BorrowMut<TimedState<Sw, Du>>,
         ShLo: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, LongPressHandleRequest>>,
         ShCl: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, ClickExactHandleRequest>>,
-        Po: BorrowMut<PointerState<Sw, Co>>,
-        Sw: Eq + Hash,
+        Po: BorrowMut<PointerState<Sw, Co, Ti, Du>>,
+        Rv: BorrowMut<Recovery>,
+        Ht: Deref,
+        Ht::Target: HitTester<Co>,
+        Sw: Clone + Eq + Hash + Debug,
         MoMo: Eq + Hash + Ord,
-        Ti: Ord,
-        // TODO: Remove after debugging
-        Ev: std::fmt::Debug,
-        Ti: std::fmt::Debug,
-        Sw: Clone + std::fmt::Debug,
-        Tr: std::fmt::Debug,
-        MoMo: std::fmt::Debug,
-        Ev: std::fmt::Debug,
+        Ti: Ord + Debug,
     {
-        let requests = self
-            .long_press_scheduler
-            .borrow_mut()
-            .take_scheduled(&time_minus_long_press_duration);
+        let requests = self.long_press_scheduler.borrow_mut().take_scheduled(&time);
 
         let mut delayed_bindings = Vec::new();
         for (_, requests) in requests {
@@ -208,15 +252,20 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
                         match result {
                             Ok(data) => data,
                             Err(err) => {
-                                eprintln!(
-                                    "input_more::DeviceState::with_press_timeout: input_core::TimedState::on_long_press_event returned an error: {:?} for event: {:?}",
-                                    err, event
+                                self.recovery.borrow_mut().on_internal_error(
+                                    "input_core::TimedState::on_long_press_event",
+                                    &err,
+                                    &event,
                                 );
                                 None
                             }
                         }
                     },
                 );
+                let result = result.and_then(|(bindings, coords)| {
+                    let bindings = bindings.filter_by_target(&*self.hit_tester, &coords)?;
+                    Some((bindings, coords))
+                });
                 if let Some((bindings, coords)) = result {
                     delayed_bindings.push((bindings, coords));
                 }
@@ -225,7 +274,7 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
         delayed_bindings
     }
 
-    pub fn with_release_event<'a, Sw, MoMo, Ti, Co, Tr, Ev>(
+    pub fn with_release_event<'a, Sw, MoMo, Ti, Du, Co, Tr, Ev>(
         &mut self,
         event: SwitchEvent<Ti, Sw>,
         mapping: &'a DeviceMappingCache<Sw, Tr, MoMo, Ev>,
@@ -234,21 +283,18 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
     where
         Mo: BorrowMut<Modifiers<MoMo>>,
         Cs: BorrowMut<CoordsState<Co>>,
-        Ts: BorrowMut<TimedState<Sw>>,
+        Ts: BorrowMut<TimedState<Sw, Du>>,
         ShLo: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, LongPressHandleRequest>>,
         ShCl: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, ClickExactHandleRequest>>,
-        Po: BorrowMut<PointerState<Sw, Co>>,
-        Sw: Clone + Eq + Hash,
+        Po: BorrowMut<PointerState<Sw, Co, Ti, Du>>,
+        Rv: BorrowMut<Recovery>,
+        Ht: Deref,
+        Ht::Target: HitTester<Co>,
+        Sw: Clone + Eq + Hash + Debug,
         MoMo: Clone + Eq + From<Sw> + Hash + Ord,
-        Ti: Clone + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + Debug,
+        Du: Clone,
         Co: Clone,
-        // TODO: Remove after debugging
-        Ev: std::fmt::Debug,
-        Ti: std::fmt::Debug,
-        Sw: std::fmt::Debug,
-        Tr: std::fmt::Debug,
-        MoMo: std::fmt::Debug,
-        Ev: std::fmt::Debug,
     {
         use crate::unwrap_or_return;
 
@@ -262,13 +308,7 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
         }
 
         if is_used_as_modifier {
-            let result = self.modifiers.borrow_mut().on_release_event(&modifier);
-            if let Err(err) = result {
-                eprintln!(
-                    "input_more::DeviceState::with_release_event: input_core::Modifiers::on_release_event returned an error: {:?} for event: {:?}",
-                    err, event
-                );
-            }
+            self.on_modifier_release(&event, &modifier, mapping_modifiers);
         }
 
         let mapping = unwrap_or_return!(mapping, (None, None));
@@ -277,25 +317,58 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
 
         let mapping = unwrap_or_return!(mapping, (None, None));
 
+        let is_click_cancelled = self
+            .pointer_state
+            .borrow()
+            .is_click_cancelled(&event.switch);
+        let hit_coords = self.hit_coords(&event.switch);
+
         let timed_data = self
             .timed_state
             .borrow_mut()
             .on_release_event(event.switch.clone());
         let timed_data = match timed_data {
-            Ok(ok) => ok,
-            Err(err) => {
-                eprintln!(
-                    "input_more::DeviceState::with_release_event: input_core::TimedState::on_release_event returned an error: {:?} for event: {:?}",
-                    err, event
-                );
+            Err(err)
+                if self.recovery.borrow_mut().on_error(
+                    "input_core::TimedState::on_release_event",
+                    &err,
+                    &event,
+                ) =>
+            {
+                // The switch is released already, so only the clicks start over.
+                let _ = self
+                    .timed_state
+                    .borrow_mut()
+                    .on_reset_click_count(&event.switch);
+                let _ = self
+                    .pointer_state
+                    .borrow_mut()
+                    .on_release_event(&event.switch);
+                return (None, None);
+            }
+            timed_data => timed_data,
+        };
+        let timed_data = match timed_data {
+            Ok(_) if is_click_cancelled => {
+                let _ = self
+                    .timed_state
+                    .borrow_mut()
+                    .on_reset_click_count(&event.switch);
                 None
             }
+            Ok(ok) => ok,
+            Err(_) => None,
         };
 
         let (timed_data, next_scheduled) = match timed_data {
             Some((timed_data, request)) => {
-                self.click_exact_scheduler.borrow_mut().schedule(
+                let deadline = self.timed_state.borrow().click_exact_deadline(
+                    &event.switch,
                     event.time.clone(),
+                    &timed_data,
+                );
+                self.click_exact_scheduler.borrow_mut().schedule(
+                    deadline,
                     (
                         event.clone(),
                         self.modifiers.borrow().clone(),
@@ -317,75 +390,61 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
             .release
             .and_then(|mapping| mapping.filter_by_timed_data(&timed_data));
 
-        let pointer_data = self
-            .pointer_state
-            .borrow_mut()
-            .on_release_event(&event.switch);
-        let pointer_data = match pointer_data {
-            Ok(ok) => ok,
-            Err(err) => {
-                eprintln!(
-                    "input_more::DeviceState::with_release_event: input_core::PointerState::on_release_event returned an error: {:?} for event: {:?}",
-                    err, event
-                );
-                None
-            }
-        };
-        if let Some(PointerChangeEventData::DragEnd) = pointer_data {
-            self
-            .timed_state
-            .borrow_mut().on_reset_click_count(&event.switch).unwrap();
-        }
+        let pointer_data = self.on_pointer_release(&event);
 
         let mapping = unwrap_or_return!(mapping, (next_scheduled, None));
 
         let mapping = mapping.filter_by_pointer_data(&pointer_data);
         let mapping = unwrap_or_return!(mapping, (next_scheduled, None));
 
+        let mapping = mapping.filter_by_target(&*self.hit_tester, &hit_coords);
+        let mapping = unwrap_or_return!(mapping, (next_scheduled, None));
+
         let coords = self.coords_state.borrow().coords().clone();
 
         (next_scheduled, Some((mapping, coords)))
     }
 
-    pub fn with_release_timeout<'a, Sw, MoMo, Ti, Co, Tr, Ev>(
+    pub fn with_release_timeout<'a, Sw, MoMo, Ti, Du, Co, Tr, Ev>(
         &mut self,
-        time_minus_click_exact_duration: Ti, // TODO: Time at Long press handling event already happend for time before that
+        time: Ti,
         mapping: &'a DeviceMappingCache<Sw, Tr, MoMo, Ev>,
     ) -> Vec<(FilteredBindings<'a, MoMo, Ev>, Co)>
     where
         Mo: BorrowMut<Modifiers<MoMo>>,
         Cs: BorrowMut<CoordsState<Co>>,
-        Ts: BorrowMut<TimedState<Sw>>,
+        Ts: BorrowMut<TimedState<Sw, Du>>,
         ShLo: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, LongPressHandleRequest>>,
         ShCl: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, ClickExactHandleRequest>>,
-        Po: BorrowMut<PointerState<Sw, Co>>,
-        Sw: Eq + Hash,
+        Po: BorrowMut<PointerState<Sw, Co, Ti, Du>>,
+        Rv: BorrowMut<Recovery>,
+        Ht: Deref,
+        Ht::Target: HitTester<Co>,
+        Sw: Clone + Eq + Hash + Debug,
         MoMo: Eq + Hash + Ord,
-        Ti: Ord,
-        // TODO: Remove after debugging
-        Ev: std::fmt::Debug,
-        Ti: std::fmt::Debug,
-        Sw: Clone + std::fmt::Debug,
-        Tr: std::fmt::Debug,
-        MoMo: std::fmt::Debug,
-        Ev: std::fmt::Debug,
+        Ti: Ord + Debug,
     {
         let requests = self
             .click_exact_scheduler
             .borrow_mut()
-            .take_scheduled(&time_minus_click_exact_duration);
+            .take_scheduled(&time);
 
         let mut delayed_bindings = Vec::new();
         for (_, requests) in requests {
             for ((event, modifiers, coords), request) in requests {
+                // A stale request must not reset the clicks of a newer press.
+                if !request.is_alive() {
+                    continue;
+                }
                 let result = self
                     .timed_state
                     .borrow_mut()
                     .on_reset_click_count(&event.switch);
                 if let Err(err) = result {
-                    eprintln!(
-                        "input_more::DeviceState::with_release_timeout: input_core::TimedState::on_reset_click_count returned an error: {:?} for event: {:?}",
-                        err, event
+                    self.recovery.borrow_mut().on_internal_error(
+                        "input_core::TimedState::on_reset_click_count",
+                        &err,
+                        &event,
                     );
                 }
 
@@ -402,15 +461,20 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
                         match result {
                             Ok(data) => data,
                             Err(err) => {
-                                eprintln!(
-                                    "input_more::DeviceState::with_release_timeout: input_core::TimedState::on_click_exact_event returned an error: {:?} for event: {:?}",
-                                    err, event
+                                self.recovery.borrow_mut().on_internal_error(
+                                    "input_core::TimedState::on_click_exact_event",
+                                    &err,
+                                    &event,
                                 );
                                 None
                             }
                         }
                     },
                 );
+                let result = result.and_then(|(bindings, coords)| {
+                    let bindings = bindings.filter_by_target(&*self.hit_tester, &coords)?;
+                    Some((bindings, coords))
+                });
                 if let Some((bindings, coords)) = result {
                     delayed_bindings.push((bindings, coords));
                 }
@@ -419,7 +483,7 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
         delayed_bindings
     }
 
-    pub fn with_trigger_event<'a, Sw, MoMo, Ti, Co, Tr, Ev>(
+    pub fn with_trigger_event<'a, Sw, MoMo, Ti, Du, Co, Tr, Ev>(
         &mut self,
         event: TriggerEvent<Ti, Tr>,
         mapping: &'a DeviceMappingCache<Sw, Tr, MoMo, Ev>,
@@ -427,10 +491,10 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
     where
         Mo: BorrowMut<Modifiers<MoMo>>,
         Cs: BorrowMut<CoordsState<Co>>,
-        Ts: BorrowMut<TimedState<Sw>>,
+        Ts: BorrowMut<TimedState<Sw, Du>>,
         ShLo: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, LongPressHandleRequest>>,
         ShCl: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, ClickExactHandleRequest>>,
-        Po: BorrowMut<PointerState<Sw, Co>>,
+        Po: BorrowMut<PointerState<Sw, Co, Ti, Du>>,
         Tr: Eq + Hash,
         MoMo: Clone + Hash + Ord,
         Co: Clone,
@@ -447,23 +511,25 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
         Some((bindings, coords))
     }
 
-    pub fn with_coords_event<'a, F, Sw, MoMo, Ti, Co, Tr, Ev>(
+    pub fn with_coords_event<'a, Sw, MoMo, Ti, Du, Co, Tr, Ev>(
         &mut self,
         event: CoordsEvent<Ti, Co>,
         mapping: &'a DeviceMappingCache<Sw, Tr, MoMo, Ev>,
-        mut is_dragged_fn: F,
     ) -> Vec<(FilteredBindings<'a, MoMo, Ev>, Co)>
     where
-        F: FnMut(&Co, &Co) -> bool,
         Mo: BorrowMut<Modifiers<MoMo>>,
         Cs: BorrowMut<CoordsState<Co>>,
-        Ts: BorrowMut<TimedState<Sw>>,
+        Ts: BorrowMut<TimedState<Sw, Du>>,
         ShLo: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, LongPressHandleRequest>>,
         ShCl: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, ClickExactHandleRequest>>,
-        Po: BorrowMut<PointerState<Sw, Co>>,
+        Po: BorrowMut<PointerState<Sw, Co, Ti, Du>>,
+        Ht: Deref,
+        Ht::Target: HitTester<Co>,
         Sw: Clone + Eq + Hash,
         MoMo: Clone + Hash + Ord,
-        Co: Clone,
+        Ti: Clone + Ord + Add<Du, Output = Ti>,
+        Du: Clone,
+        Co: Clone + CoordsDistance,
     {
         use crate::unwrap_or_continue;
 
@@ -474,7 +540,7 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
         let data = self
             .pointer_state
             .borrow_mut()
-            .on_move_event(|coords| is_dragged_fn(coords, &event.coords));
+            .on_move_event(&event.coords, &event.time);
 
         let mut all_bindings = vec![];
         let mapping = &mapping.coords;
@@ -482,6 +548,9 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
             let mapping = mapping.filter_by_pointer_data(&pointer_data);
             let mapping = unwrap_or_continue!(mapping);
             let mapping = mapping.filter_by_modifiers(self.modifiers.borrow());
+            let mapping = unwrap_or_continue!(mapping);
+            let hit_coords = self.hit_coords(&pointer_data.switch);
+            let mapping = mapping.filter_by_target(&*self.hit_tester, &hit_coords);
             let bindings = unwrap_or_continue!(mapping);
 
             let coords = event.coords.clone();
@@ -490,6 +559,220 @@ impl<Mo, Cs, Ts, ShLo, ShCl, Po> DeviceState<Mo, Cs, Ts, ShLo, ShCl, Po> {
 
         all_bindings
     }
+
+    pub fn with_switch_coords_event<'a, Sw, MoMo, Ti, Du, Co, Tr, Ev>(
+        &mut self,
+        switch: &Sw,
+        event: CoordsEvent<Ti, Co>,
+        mapping: &'a DeviceMappingCache<Sw, Tr, MoMo, Ev>,
+    ) -> Option<(FilteredBindings<'a, MoMo, Ev>, Co)>
+    where
+        Mo: BorrowMut<Modifiers<MoMo>>,
+        Cs: BorrowMut<CoordsState<Co>>,
+        Ts: BorrowMut<TimedState<Sw, Du>>,
+        ShLo: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, LongPressHandleRequest>>,
+        ShCl: BorrowMut<DeviceSchedulerState<Ti, Sw, MoMo, Co, ClickExactHandleRequest>>,
+        Po: BorrowMut<PointerState<Sw, Co, Ti, Du>>,
+        Ht: Deref,
+        Ht::Target: HitTester<Co>,
+        Sw: Clone + Eq + Hash,
+        MoMo: Clone + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti>,
+        Du: Clone,
+        Co: Clone + CoordsDistance,
+    {
+        self.coords_state
+            .borrow_mut()
+            .set_coords(event.coords.clone());
+
+        let pointer_data = self.pointer_state.borrow_mut().on_switch_move_event(
+            switch,
+            &event.coords,
+            &event.time,
+        )?;
+
+        let mapping = mapping.coords.filter_by_pointer_data(&pointer_data)?;
+        let mapping = mapping.filter_by_modifiers(self.modifiers.borrow())?;
+        let bindings = mapping.filter_by_target(&*self.hit_tester, &self.hit_coords(switch))?;
+
+        Some((bindings, event.coords))
+    }
+
+    pub fn with_repeat_event<'a, Sw, MoMo, Ti, Du, Co, Tr, Ev>(
+        &mut self,
+        switch: &Sw,
+        mapping: &'a DeviceMappingCache<Sw, Tr, MoMo, Ev>,
+    ) -> Option<(FilteredBindings<'a, MoMo, Ev>, Co)>
+    where
+        Mo: BorrowMut<Modifiers<MoMo>>,
+        Cs: BorrowMut<CoordsState<Co>>,
+        Po: BorrowMut<PointerState<Sw, Co, Ti, Du>>,
+        Ht: Deref,
+        Ht::Target: HitTester<Co>,
+        Sw: Eq + Hash,
+        MoMo: Clone + Hash + Ord,
+        Co: Clone,
+    {
+        let mapping = mapping.repeat.filter_by_switch(switch)?;
+        let mapping = mapping.filter_by_modifiers(self.modifiers.borrow())?;
+        let mapping = mapping.filter_by_timed_data(&())?;
+        let mapping = mapping.filter_by_pointer_data(&())?;
+        let hit_coords = self.hit_coords(switch);
+        let bindings = mapping.filter_by_target(&*self.hit_tester, &hit_coords)?;
+
+        let coords = self.coords_state.borrow().coords().clone();
+        Some((bindings, coords))
+    }
+
+    pub fn with_axis_event<'a, Sw, MoMo, Co, Tr, Ev>(
+        &mut self,
+        axis: &Tr,
+        data: AxisEventData,
+        mapping: &'a DeviceMappingCache<Sw, Tr, MoMo, Ev>,
+    ) -> Option<(FilteredBindings<'a, MoMo, Ev>, AxisEventData)>
+    where
+        Mo: BorrowMut<Modifiers<MoMo>>,
+        Cs: BorrowMut<CoordsState<Co>>,
+        Ht: Deref,
+        Ht::Target: HitTester<Co>,
+        Tr: Eq + Hash,
+        MoMo: Clone + Hash + Ord,
+    {
+        let mapping = mapping.axis.filter_by_pointer_data(axis)?;
+        let mapping = mapping.filter_by_modifiers(self.modifiers.borrow())?;
+        let coords = self.coords_state.borrow();
+        let bindings = mapping.filter_by_target(&*self.hit_tester, coords.coords())?;
+        Some((bindings, data))
+    }
+
+    pub fn with_drag_cancel_event<'a, Sw, MoMo, Ti, Du, Co, Tr, Ev>(
+        &mut self,
+        mapping: &'a DeviceMappingCache<Sw, Tr, MoMo, Ev>,
+    ) -> Vec<(FilteredBindings<'a, MoMo, Ev>, Co)>
+    where
+        Mo: BorrowMut<Modifiers<MoMo>>,
+        Cs: BorrowMut<CoordsState<Co>>,
+        Po: BorrowMut<PointerState<Sw, Co, Ti, Du>>,
+        Ht: Deref,
+        Ht::Target: HitTester<Co>,
+        Sw: Clone + Eq + Hash,
+        MoMo: Clone + Hash + Ord,
+        Co: Clone,
+    {
+        use crate::unwrap_or_continue;
+
+        let data = self.pointer_state.borrow_mut().on_cancel_event();
+
+        let mut all_bindings = vec![];
+        let mapping = &mapping.coords;
+        for pointer_data in data {
+            let mapping = mapping.filter_by_pointer_data(&pointer_data);
+            let mapping = unwrap_or_continue!(mapping);
+            let mapping = mapping.filter_by_modifiers(self.modifiers.borrow());
+            let mapping = unwrap_or_continue!(mapping);
+            let hit_coords = self.hit_coords(&pointer_data.switch);
+            let mapping = mapping.filter_by_target(&*self.hit_tester, &hit_coords);
+            let bindings = unwrap_or_continue!(mapping);
+
+            let pointer_state = self.pointer_state.borrow();
+            let coords = pointer_state
+                .last_coords(&pointer_data.switch)
+                .unwrap_or_else(|| self.coords_state.borrow().coords())
+                .clone();
+            all_bindings.push((bindings, coords));
+        }
+
+        all_bindings
+    }
+
+    // Targets are hit-tested against what was under the pointer at press time.
+    fn hit_coords<Sw, Co, Ti, Du>(&self, switch: &Sw) -> Co
+    where
+        Cs: BorrowMut<CoordsState<Co>>,
+        Po: BorrowMut<PointerState<Sw, Co, Ti, Du>>,
+        Sw: Eq + Hash,
+        Co: Clone,
+    {
+        self.pointer_state
+            .borrow()
+            .press_coords(switch)
+            .unwrap_or_else(|| self.coords_state.borrow().coords())
+            .clone()
+    }
+
+    fn on_modifier_release<Sw, MoMo, Ti>(
+        &mut self,
+        event: &SwitchEvent<Ti, Sw>,
+        modifier: &MoMo,
+        mapping_modifiers: &MappingModifiersCache<MoMo>,
+    ) where
+        Mo: BorrowMut<Modifiers<MoMo>>,
+        Rv: BorrowMut<Recovery>,
+        Sw: Debug,
+        MoMo: Clone + Eq + Hash + Ord,
+        Ti: Debug,
+    {
+        let modifiers = self.modifiers.borrow_mut();
+        let result = match mapping_modifiers.kind(modifier) {
+            ModifierKind::Momentary => {
+                modifiers.on_aliased_release_event(modifier, mapping_modifiers.aliases())
+            }
+            ModifierKind::Lock => Ok(()),
+            ModifierKind::Latch => modifiers.on_latch_release_event(modifier),
+        };
+        // A released modifier is already in sync with the host.
+        if let Err(err) = result {
+            let _ = self.recovery.borrow_mut().on_error(
+                "input_core::Modifiers::on_release_event",
+                &err,
+                event,
+            );
+        }
+    }
+
+    // A drag end releases the pointer without a click, so the clicks start over.
+    fn on_pointer_release<Sw, Co, Ti, Du>(
+        &mut self,
+        event: &SwitchEvent<Ti, Sw>,
+    ) -> Option<PointerChangeEventData>
+    where
+        Ts: BorrowMut<TimedState<Sw, Du>>,
+        Po: BorrowMut<PointerState<Sw, Co, Ti, Du>>,
+        Rv: BorrowMut<Recovery>,
+        Sw: Clone + Eq + Hash + Debug,
+        Ti: Debug,
+    {
+        let pointer_data = self
+            .pointer_state
+            .borrow_mut()
+            .on_release_event(&event.switch);
+        let pointer_data = match pointer_data {
+            Ok(ok) => ok,
+            // A released pointer is already in sync with the host.
+            Err(err) => {
+                let _ = self.recovery.borrow_mut().on_error(
+                    "input_core::PointerState::on_release_event",
+                    &err,
+                    event,
+                );
+                None
+            }
+        };
+        if let Some(PointerChangeEventData::DragEnd) = pointer_data {
+            let result = self
+                .timed_state
+                .borrow_mut()
+                .on_reset_click_count(&event.switch);
+            if let Err(err) = result {
+                self.recovery.borrow_mut().on_internal_error(
+                    "input_core::TimedState::on_reset_click_count",
+                    &err,
+                    event,
+                );
+            }
+        }
+        pointer_data
+    }
 }
 
 fn with_timeout_event<'a, Sw, Mo, Co, Td, Bi>(
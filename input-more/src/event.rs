@@ -1,16 +1,19 @@
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+use input_core::TextInput;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct SwitchEvent<Ti, Sw> {
     pub time: Ti,
     pub switch: Sw,
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct TriggerEvent<Ti, Tr> {
     pub time: Ti,
     pub trigger: Tr,
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct CoordsEvent<Ti, Co> {
     pub time: Ti,
     pub coords: Co,
@@ -33,3 +36,56 @@ impl<Ti, Co> CoordsEvent<Ti, Co> {
         Self { time, coords }
     }
 }
+
+// `value` is the raw position of the axis, normally in `-1.0..=1.0`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AnalogEvent<Ti, Ax> {
+    pub time: Ti,
+    pub axis: Ax,
+    pub value: f64,
+}
+
+impl<Ti, Ax> AnalogEvent<Ti, Ax> {
+    pub const fn new(time: Ti, axis: Ax, value: f64) -> Self {
+        Self { time, axis, value }
+    }
+}
+
+// `delta` is a vector in the units of the pointer coordinates.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct AxisEvent<Ti, Ax, Co> {
+    pub time: Ti,
+    pub axis: Ax,
+    pub delta: Co,
+}
+
+impl<Ti, Ax, Co> AxisEvent<Ti, Ax, Co> {
+    pub const fn new(time: Ti, axis: Ax, delta: Co) -> Self {
+        Self { time, axis, delta }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct TouchEvent<Ti, Id, Co> {
+    pub time: Ti,
+    pub id: Id,
+    pub coords: Co,
+}
+
+impl<Ti, Id, Co> TouchEvent<Ti, Id, Co> {
+    pub const fn new(time: Ti, id: Id, coords: Co) -> Self {
+        Self { time, id, coords }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct TextInputEvent<Ti> {
+    pub time: Ti,
+    pub input: TextInput,
+}
+
+impl<Ti> TextInputEvent<Ti> {
+    pub const fn new(time: Ti, input: TextInput) -> Self {
+        Self { time, input }
+    }
+}
@@ -1,5 +1,7 @@
 #[derive(Clone, Debug)]
-pub struct GlobalMapping<Ke, Mo> {
+pub struct GlobalMapping<Ke, Ms, To, Pe> {
     pub keyboard: Ke,
-    pub mouse: Mo,
+    pub mouse: Ms,
+    pub touch: To,
+    pub pen: Pe,
 }
@@ -1,16 +1,20 @@
 use core::hash::Hash;
 use std::sync::Arc;
 
-use crate::{DeviceMappingCache, GlobalMapping, Mapping, MappingModifiersCache};
+use input_core::{ModifierAliases, ModifierKind, Modifiers};
+
+use crate::{Binding, DeviceMappingCache, GlobalMapping, Mapping, MappingModifiersCache};
 
 #[derive(Clone, Debug)]
-pub struct GlobalMappingCache<Ke, Ms, Mo> {
+pub struct GlobalMappingCache<Ke, Ms, To, Pe, Mo> {
     keyboard: Ke,
     mouse: Ms,
+    touch: To,
+    pen: Pe,
     modifiers: Mo,
 }
 
-impl<Ke, Ms, Mo> GlobalMappingCache<Ke, Ms, Mo> {
+impl<Ke, Ms, To, Pe, Mo> GlobalMappingCache<Ke, Ms, To, Pe, Mo> {
     pub fn keyboard(&self) -> &Ke {
         &self.keyboard
     }
@@ -19,47 +23,124 @@ impl<Ke, Ms, Mo> GlobalMappingCache<Ke, Ms, Mo> {
         &self.mouse
     }
 
+    pub const fn touch(&self) -> &To {
+        &self.touch
+    }
+
+    pub const fn pen(&self) -> &Pe {
+        &self.pen
+    }
+
     pub fn modifiers(&self) -> &Mo {
         &self.modifiers
     }
 }
 
-impl<Mo, KeSw, KeTr, KeEv, MsSw, MsTr, MsEv>
+impl<Mo, KeSw, KeTr, KeEv, MsSw, MsTr, MsEv, ToSw, ToTr, ToEv, PeSw, PeTr, PeEv>
     GlobalMappingCache<
         DeviceMappingCache<KeSw, KeTr, Mo, KeEv>,
         DeviceMappingCache<MsSw, MsTr, Mo, MsEv>,
+        DeviceMappingCache<ToSw, ToTr, Mo, ToEv>,
+        DeviceMappingCache<PeSw, PeTr, Mo, PeEv>,
         MappingModifiersCache<Mo>,
     >
 where
-    Mo: Clone + Eq + Hash,
+    Mo: Clone + Eq + Hash + Ord,
     KeSw: Clone + Eq + Hash,
     MsSw: Clone + Eq + Hash,
+    ToSw: Clone + Eq + Hash,
+    PeSw: Clone + Eq + Hash,
     KeTr: Clone + Eq + Hash,
     MsTr: Clone + Eq + Hash,
+    ToTr: Clone + Eq + Hash,
+    PeTr: Clone + Eq + Hash,
     KeEv: Clone,
     MsEv: Clone,
+    ToEv: Clone,
+    PeEv: Clone,
 {
     pub fn from_mapping(
-        mapping: GlobalMapping<Mapping<KeSw, KeTr, Mo, KeEv>, Mapping<MsSw, MsTr, Mo, MsEv>>,
+        mapping: GlobalMapping<
+            Mapping<KeSw, KeTr, Mo, KeEv>,
+            Mapping<MsSw, MsTr, Mo, MsEv>,
+            Mapping<ToSw, ToTr, Mo, ToEv>,
+            Mapping<PeSw, PeTr, Mo, PeEv>,
+        >,
     ) -> Self {
         let keyboard_modifiers = mapping
             .keyboard
             .bindings()
             .iter()
-            .map(|binding| binding.modifiers().switches())
+            .flat_map(Binding::iter_modifiers)
+            .map(Modifiers::switches)
             .flat_map(|switch| switch.iter());
         let mouse_modifiers = mapping
             .mouse
             .bindings()
             .iter()
-            .map(|binding| binding.modifiers().switches())
+            .flat_map(Binding::iter_modifiers)
+            .map(Modifiers::switches)
+            .flat_map(|switch| switch.iter());
+        let touch_modifiers = mapping
+            .touch
+            .bindings()
+            .iter()
+            .flat_map(Binding::iter_modifiers)
+            .map(Modifiers::switches)
             .flat_map(|switch| switch.iter());
+        let pen_modifiers = mapping
+            .pen
+            .bindings()
+            .iter()
+            .flat_map(Binding::iter_modifiers)
+            .map(Modifiers::switches)
+            .flat_map(|switch| switch.iter());
+        let mut aliases = ModifierAliases::new();
+        aliases.extend(mapping.keyboard.modifier_aliases());
+        aliases.extend(mapping.mouse.modifier_aliases());
+        aliases.extend(mapping.touch.modifier_aliases());
+        aliases.extend(mapping.pen.modifier_aliases());
+        let mut kinds = Vec::new();
+        for (locks, latches) in [
+            (
+                mapping.keyboard.lock_modifiers(),
+                mapping.keyboard.latch_modifiers(),
+            ),
+            (
+                mapping.mouse.lock_modifiers(),
+                mapping.mouse.latch_modifiers(),
+            ),
+            (
+                mapping.touch.lock_modifiers(),
+                mapping.touch.latch_modifiers(),
+            ),
+            (mapping.pen.lock_modifiers(), mapping.pen.latch_modifiers()),
+        ] {
+            kinds.extend(
+                locks
+                    .iter()
+                    .map(|switch| (switch.clone(), ModifierKind::Lock)),
+            );
+            kinds.extend(
+                latches
+                    .iter()
+                    .map(|switch| (switch.clone(), ModifierKind::Latch)),
+            );
+        }
         Self {
-            keyboard: DeviceMappingCache::from_bindings(mapping.keyboard.bindings()),
-            mouse: DeviceMappingCache::from_bindings(mapping.mouse.bindings()),
+            keyboard: DeviceMappingCache::from_mapping_with_aliases(&mapping.keyboard, &aliases),
+            mouse: DeviceMappingCache::from_mapping_with_aliases(&mapping.mouse, &aliases),
+            touch: DeviceMappingCache::from_mapping_with_aliases(&mapping.touch, &aliases),
+            pen: DeviceMappingCache::from_mapping_with_aliases(&mapping.pen, &aliases),
             modifiers: MappingModifiersCache::from_switches(
-                keyboard_modifiers.chain(mouse_modifiers).cloned(),
-            ),
+                keyboard_modifiers
+                    .chain(mouse_modifiers)
+                    .chain(touch_modifiers)
+                    .chain(pen_modifiers)
+                    .cloned(),
+            )
+            .with_aliases(aliases)
+            .with_kinds(kinds),
         }
     }
 }
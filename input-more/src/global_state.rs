@@ -1,28 +1,176 @@
+use core::borrow::BorrowMut;
+use core::fmt::Debug;
 use core::hash::Hash;
+use core::ops::Add;
 
 use input_core::{
-    ClickExactHandleRequest, CoordsState, LongPressHandleRequest, Modifiers, PointerState,
-    SchedulerState, TimedState,
+    AnalogState, AxisEventData, AxisState, ClickExactHandleRequest, Coords2d, CoordsDistance,
+    CoordsState, GestureEventData, GestureEventKind, GestureState, KineticEventData,
+    KineticHandleRequest, LongPressHandleRequest, ModifierKind, Modifiers, PenCoords, PenState,
+    PointerMoveEventData, PointerState, RepeatSchedulerState, RepeatState, SchedulerState,
+    SequenceEventData, SequenceMatchKind, SequenceSchedulerState, SequenceState,
+    TextInputEventData, TextInputState, TimeValue, TimedState,
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    CoordsEvent, DeviceMappingCache, DeviceSchedulerState, DeviceState, FilteredBindings,
-    GlobalMappingCache, MappingModifiersCache, SwitchEvent, TriggerEvent,
+    AnalogEvent, AxisEvent, ContextStack, CoordsEvent, CoordsMappingCache, DeviceMappingCache,
+    DeviceSchedulerState, DeviceState, FilteredBindings, GlobalMappingCache, HitTester,
+    MappingModifiersCache, NoHitTester, Recovery, SequenceMappingCache, SequenceStep, SwitchEvent,
+    TextInputEvent, TouchEvent, TriggerEvent,
 };
 
 #[derive(Clone, Debug, Default)]
-pub struct GlobalState<Mo, CsKe, CsMs, TsKe, TsMs, ShKeLo, ShKeCl, ShMsLo, ShMsCl, PoKe, PoMs> {
+pub struct GlobalState<Mo, Ke, Ms, To, Pe, Ht = NoHitTester> {
     pub modifiers: Mo,
-    pub keyboard_coords_state: CsKe,
-    pub mouse_coords_state: CsMs,
-    pub keyboard_timed_state: TsKe,
-    pub mouse_timed_state: TsMs,
-    pub keyboard_long_press_scheduler: ShKeLo,
-    pub keyboard_click_exact_scheduler: ShKeCl,
-    pub mouse_long_press_scheduler: ShMsLo,
-    pub mouse_click_exact_scheduler: ShMsCl,
-    pub keyboard_pointer_state: PoKe,
-    pub mouse_pointer_state: PoMs,
+    pub keyboard: Ke,
+    pub mouse: Ms,
+    pub touch: To,
+    pub pen: Pe,
+    pub text_input_state: TextInputState,
+    pub context: ContextStack,
+    pub recovery: Recovery,
+    pub hit_tester: Ht,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct KeyboardLane<Cs, Ts, ShLo, ShCl, Po, Sq, ShSq, Rp, ShRp, An> {
+    pub coords_state: Cs,
+    pub timed_state: Ts,
+    pub long_press_scheduler: ShLo,
+    pub click_exact_scheduler: ShCl,
+    pub pointer_state: Po,
+    pub sequence_state: Sq,
+    pub sequence_scheduler: ShSq,
+    pub repeat_state: Rp,
+    pub repeat_scheduler: ShRp,
+    pub analog_state: An,
+}
+
+impl<Cs, Ts, ShLo, ShCl, Po, Sq, ShSq, Rp, ShRp, An>
+    KeyboardLane<Cs, Ts, ShLo, ShCl, Po, Sq, ShSq, Rp, ShRp, An>
+{
+    pub const fn new(
+        coords_state: Cs,
+        timed_state: Ts,
+        long_press_scheduler: ShLo,
+        click_exact_scheduler: ShCl,
+        pointer_state: Po,
+        sequence_state: Sq,
+        sequence_scheduler: ShSq,
+        repeat_state: Rp,
+        repeat_scheduler: ShRp,
+        analog_state: An,
+    ) -> Self {
+        Self {
+            coords_state,
+            timed_state,
+            long_press_scheduler,
+            click_exact_scheduler,
+            pointer_state,
+            sequence_state,
+            sequence_scheduler,
+            repeat_state,
+            repeat_scheduler,
+            analog_state,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MouseLane<Cs, Ts, ShLo, ShCl, Po, Ax, ShKn> {
+    pub coords_state: Cs,
+    pub timed_state: Ts,
+    pub long_press_scheduler: ShLo,
+    pub click_exact_scheduler: ShCl,
+    pub pointer_state: Po,
+    pub axis_state: Ax,
+    pub kinetic_scheduler: ShKn,
+}
+
+impl<Cs, Ts, ShLo, ShCl, Po, Ax, ShKn> MouseLane<Cs, Ts, ShLo, ShCl, Po, Ax, ShKn> {
+    pub const fn new(
+        coords_state: Cs,
+        timed_state: Ts,
+        long_press_scheduler: ShLo,
+        click_exact_scheduler: ShCl,
+        pointer_state: Po,
+        axis_state: Ax,
+        kinetic_scheduler: ShKn,
+    ) -> Self {
+        Self {
+            coords_state,
+            timed_state,
+            long_press_scheduler,
+            click_exact_scheduler,
+            pointer_state,
+            axis_state,
+            kinetic_scheduler,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TouchLane<Cs, Ts, ShLo, ShCl, Po, Ge, ShKn> {
+    pub coords_state: Cs,
+    pub timed_state: Ts,
+    pub long_press_scheduler: ShLo,
+    pub click_exact_scheduler: ShCl,
+    pub pointer_state: Po,
+    pub gesture_state: Ge,
+    pub kinetic_scheduler: ShKn,
+}
+
+impl<Cs, Ts, ShLo, ShCl, Po, Ge, ShKn> TouchLane<Cs, Ts, ShLo, ShCl, Po, Ge, ShKn> {
+    pub const fn new(
+        coords_state: Cs,
+        timed_state: Ts,
+        long_press_scheduler: ShLo,
+        click_exact_scheduler: ShCl,
+        pointer_state: Po,
+        gesture_state: Ge,
+        kinetic_scheduler: ShKn,
+    ) -> Self {
+        Self {
+            coords_state,
+            timed_state,
+            long_press_scheduler,
+            click_exact_scheduler,
+            pointer_state,
+            gesture_state,
+            kinetic_scheduler,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PenLane<Cs, Ts, ShLo, ShCl, Po, Pn> {
+    pub coords_state: Cs,
+    pub timed_state: Ts,
+    pub long_press_scheduler: ShLo,
+    pub click_exact_scheduler: ShCl,
+    pub pointer_state: Po,
+    pub pen_state: Pn,
+}
+
+impl<Cs, Ts, ShLo, ShCl, Po, Pn> PenLane<Cs, Ts, ShLo, ShCl, Po, Pn> {
+    pub const fn new(
+        coords_state: Cs,
+        timed_state: Ts,
+        long_press_scheduler: ShLo,
+        click_exact_scheduler: ShCl,
+        pointer_state: Po,
+        pen_state: Pn,
+    ) -> Self {
+        Self {
+            coords_state,
+            timed_state,
+            long_press_scheduler,
+            click_exact_scheduler,
+            pointer_state,
+            pen_state,
+        }
+    }
 }
 
 /*
@@ -55,34 +203,18 @@ define_struct_take_and_with_field!(GlobalState {
 });
 */
 
-impl<Mo, CsKe, CsMs, TsKe, TsMs, ShKeLo, ShKeCl, ShMsLo, ShMsCl, PoKe, PoMs>
-    GlobalState<Mo, CsKe, CsMs, TsKe, TsMs, ShKeLo, ShKeCl, ShMsLo, ShMsCl, PoKe, PoMs>
-{
-    pub fn new(
-        modifiers: Mo,
-        keyboard_coords_state: CsKe,
-        mouse_coords_state: CsMs,
-        keyboard_timed_state: TsKe,
-        mouse_timed_state: TsMs,
-        keyboard_long_press_scheduler: ShKeLo,
-        keyboard_click_exact_scheduler: ShKeCl,
-        mouse_long_press_scheduler: ShMsLo,
-        mouse_click_exact_scheduler: ShMsCl,
-        keyboard_pointer_state: PoKe,
-        mouse_pointer_state: PoMs,
-    ) -> Self {
+impl<Mo, Ke, Ms, To, Pe> GlobalState<Mo, Ke, Ms, To, Pe, NoHitTester> {
+    pub fn new(modifiers: Mo, keyboard: Ke, mouse: Ms, touch: To, pen: Pe) -> Self {
         Self {
             modifiers,
-            keyboard_coords_state,
-            mouse_coords_state,
-            keyboard_timed_state,
-            mouse_timed_state,
-            keyboard_long_press_scheduler,
-            keyboard_click_exact_scheduler,
-            mouse_long_press_scheduler,
-            mouse_click_exact_scheduler,
-            keyboard_pointer_state,
-            mouse_pointer_state,
+            keyboard,
+            mouse,
+            touch,
+            pen,
+            text_input_state: TextInputState::default(),
+            context: ContextStack::default(),
+            recovery: Recovery::default(),
+            hit_tester: NoHitTester,
         }
     }
 
@@ -179,159 +311,871 @@ impl<Mo, CsKe, CsMs, TsKe, TsMs, ShKeLo, ShKeCl, ShMsLo, ShMsCl, PoKe, PoMs>
         */
 }
 
-impl<Mo, Ti, KeSw, MsSw, KeCo, MsCo>
+impl<Mo, Ke, Ms, To, Pe, Ht> GlobalState<Mo, Ke, Ms, To, Pe, Ht> {
+    pub fn with_hit_tester<HtHt>(self, hit_tester: HtHt) -> GlobalState<Mo, Ke, Ms, To, Pe, HtHt> {
+        GlobalState {
+            modifiers: self.modifiers,
+            keyboard: self.keyboard,
+            mouse: self.mouse,
+            touch: self.touch,
+            pen: self.pen,
+            text_input_state: self.text_input_state,
+            context: self.context,
+            recovery: self.recovery,
+            hit_tester,
+        }
+    }
+
+    // While a composition is active the keys belong to the input method, so
+    // the keyboard lane keeps its state but drops the bindings.
+    pub fn with_text_input_event<Ti>(
+        &mut self,
+        event: TextInputEvent<Ti>,
+    ) -> GlobalStateWithEventResult<(), Option<TextInputEventData>>
+    where
+        Ti: Debug,
+    {
+        let data = match self.text_input_state.on_input_event(event.input.clone()) {
+            Ok(data) => Some(data),
+            Err(err) => {
+                if self.recovery.on_error(
+                    "input_core::TextInputState::on_input_event",
+                    &err,
+                    &event,
+                ) {
+                    self.text_input_state.resync(err);
+                    self.text_input_state.on_input_event(event.input).ok()
+                } else {
+                    None
+                }
+            }
+        };
+
+        GlobalStateWithEventResult {
+            scheduled: (),
+            bindings: data,
+        }
+    }
+}
+
+impl<Mo, Ti, Du, KeSw, MsSw, ToSw, PeSw, KeCo, MsCo, ToCo, PeCo, AxMs, AnKe, PnPe, Ht>
     GlobalState<
         Modifiers<Mo>,
-        CoordsState<KeCo>,
-        CoordsState<MsCo>,
-        TimedState<KeSw>,
-        TimedState<MsSw>,
-        DeviceSchedulerState<Ti, KeSw, Mo, KeCo, LongPressHandleRequest>,
-        DeviceSchedulerState<Ti, KeSw, Mo, KeCo, ClickExactHandleRequest>,
-        DeviceSchedulerState<Ti, MsSw, Mo, MsCo, LongPressHandleRequest>,
-        DeviceSchedulerState<Ti, MsSw, Mo, MsCo, ClickExactHandleRequest>,
-        PointerState<KeSw, KeCo>,
-        PointerState<MsSw, MsCo>,
+        KeyboardLane<
+            CoordsState<KeCo>,
+            TimedState<KeSw, Du>,
+            DeviceSchedulerState<Ti, KeSw, Mo, KeCo, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, KeSw, Mo, KeCo, ClickExactHandleRequest>,
+            PointerState<KeSw, KeCo, Ti, Du>,
+            SequenceState<SequenceStep<KeSw, Mo>, Du>,
+            SequenceSchedulerState<Ti, ()>,
+            RepeatState<KeSw, Du>,
+            RepeatSchedulerState<Ti, KeSw>,
+            AnKe,
+        >,
+        MouseLane<
+            CoordsState<MsCo>,
+            TimedState<MsSw, Du>,
+            DeviceSchedulerState<Ti, MsSw, Mo, MsCo, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, MsSw, Mo, MsCo, ClickExactHandleRequest>,
+            PointerState<MsSw, MsCo, Ti, Du>,
+            AxMs,
+            DeviceSchedulerState<Ti, MsSw, Mo, MsCo, KineticHandleRequest>,
+        >,
+        TouchLane<
+            CoordsState<ToCo>,
+            TimedState<ToSw, Du>,
+            DeviceSchedulerState<Ti, ToSw, Mo, ToCo, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, ToSw, Mo, ToCo, ClickExactHandleRequest>,
+            PointerState<ToSw, ToCo, Ti, Du>,
+            GestureState<ToSw, ToCo>,
+            DeviceSchedulerState<Ti, ToSw, Mo, ToCo, KineticHandleRequest>,
+        >,
+        PenLane<
+            CoordsState<PeCo>,
+            TimedState<PeSw, Du>,
+            DeviceSchedulerState<Ti, PeSw, Mo, PeCo, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, PeSw, Mo, PeCo, ClickExactHandleRequest>,
+            PointerState<PeSw, PeCo, Ti, Du>,
+            PnPe,
+        >,
+        Ht,
     >
+where
+    Ht: HitTester<KeCo> + HitTester<MsCo> + HitTester<ToCo> + HitTester<PeCo>,
 {
-    pub fn with_timeout<'a, KeTr, MsTr, KeEv, MsEv>(
+    pub fn with_timeout<'a, KeTr, MsTr, ToTr, PeTr, KeEv, MsEv, ToEv, PeEv>(
         &mut self,
-        time_minus_long_press_duration: Ti,
-        time_minus_click_exact_duration: Ti,
+        time: Ti,
         mapping: &'a GlobalMappingCache<
             DeviceMappingCache<KeSw, KeTr, Mo, KeEv>,
             DeviceMappingCache<MsSw, MsTr, Mo, MsEv>,
+            DeviceMappingCache<ToSw, ToTr, Mo, ToEv>,
+            DeviceMappingCache<PeSw, PeTr, Mo, PeEv>,
             MappingModifiersCache<Mo>,
         >,
-    ) -> GlobalStateWithTimeoutResult<'a, Mo, KeEv, KeCo, MsEv, MsCo>
+    ) -> GlobalStateWithTimeoutResult<'a, Mo, KeEv, KeCo, MsEv, MsCo, ToEv, ToCo, PeEv, PeCo>
     where
-        KeSw: Eq + Hash,
-        MsSw: Eq + Hash,
-        Mo: Eq + Hash + Ord,
-        Ti: Clone + Ord,
-        //
-        Ti: std::fmt::Debug,
-        Mo: std::fmt::Debug,
-        KeEv: std::fmt::Debug,
-        KeTr: std::fmt::Debug,
-        KeSw: Clone + std::fmt::Debug,
-        MsEv: std::fmt::Debug,
-        MsTr: std::fmt::Debug,
-        MsSw: Clone + std::fmt::Debug,
+        KeSw: Clone + Eq + Hash + Debug,
+        MsSw: Clone + Eq + Hash + Debug,
+        ToSw: Clone + Eq + Hash + Debug,
+        PeSw: Clone + Eq + Hash + Debug,
+        Mo: Clone + Eq + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + Debug,
+        Du: Clone + TimeValue,
+        MsCo: Clone,
+        ToCo: Clone,
+        KeCo: Clone,
+        PeCo: Clone,
     {
+        use crate::unwrap_or_continue;
+
+        let scheduled: Vec<_> = self
+            .keyboard
+            .sequence_scheduler
+            .take_scheduled(&time)
+            .collect();
+        let mut keyboard_sequence = Vec::new();
+        for (_, requests) in scheduled {
+            for ((), request) in requests {
+                // Only an extendable sequence has bindings to fire on the timeout.
+                let steps = self.keyboard.sequence_state.on_timeout_event(&request);
+                keyboard_sequence.extend(steps.and_then(|steps| {
+                    self.keyboard_sequence_bindings(&steps, &mapping.keyboard().sequence)
+                }));
+            }
+        }
+
+        let mut keyboard_repeat = Vec::new();
+        let scheduled: Vec<_> = self
+            .keyboard
+            .repeat_scheduler
+            .take_scheduled(&time)
+            .collect();
+        for (deadline, requests) in scheduled {
+            for (switch, request) in requests {
+                let result =
+                    self.keyboard
+                        .repeat_state
+                        .on_timeout_event(&switch, request, deadline.clone());
+                let (_, next_deadline, request) = unwrap_or_continue!(result);
+                self.cancel_repeated_timed_events(&switch);
+                self.keyboard
+                    .repeat_scheduler
+                    .schedule(next_deadline, switch.clone(), request);
+                keyboard_repeat.extend(
+                    self.as_keyboard_state_mut()
+                        .with_repeat_event(&switch, mapping.keyboard()),
+                );
+            }
+        }
+
         let mut state = self.as_keyboard_state_mut();
-        let keyboard_long_press =
-            state.with_press_timeout(time_minus_long_press_duration.clone(), mapping.keyboard());
-        let keyboard_click_exact =
-            state.with_release_timeout(time_minus_click_exact_duration.clone(), mapping.keyboard());
+        let mut keyboard_long_press = state.with_press_timeout(time.clone(), mapping.keyboard());
+        let mut keyboard_click_exact = state.with_release_timeout(time.clone(), mapping.keyboard());
+        if self.text_input_state.is_composing() {
+            keyboard_long_press.clear();
+            keyboard_click_exact.clear();
+            keyboard_repeat.clear();
+        }
 
         let mut state = self.as_mouse_state_mut();
-        let mouse_long_press =
-            state.with_press_timeout(time_minus_long_press_duration.clone(), mapping.mouse());
-        let mouse_click_exact =
-            state.with_release_timeout(time_minus_click_exact_duration.clone(), mapping.mouse());
+        let mouse_long_press = state.with_press_timeout(time.clone(), mapping.mouse());
+        let mouse_click_exact = state.with_release_timeout(time.clone(), mapping.mouse());
+
+        let mouse_kinetic = with_kinetic_timeout(
+            &mut self.mouse.pointer_state,
+            &mut self.mouse.kinetic_scheduler,
+            &mapping.mouse().coords,
+            &self.context,
+            &self.hit_tester,
+            &time,
+        );
+        let touch_kinetic = with_kinetic_timeout(
+            &mut self.touch.pointer_state,
+            &mut self.touch.kinetic_scheduler,
+            &mapping.touch().coords,
+            &self.context,
+            &self.hit_tester,
+            &time,
+        );
+
+        let mut state = self.as_pen_state_mut();
+        let pen_long_press = state.with_press_timeout(time.clone(), mapping.pen());
+        let pen_click_exact = state.with_release_timeout(time.clone(), mapping.pen());
+
+        let mut state = self.as_touch_state_mut();
+        let touch_long_press = state.with_press_timeout(time.clone(), mapping.touch());
+        let touch_click_exact = state.with_release_timeout(time, mapping.touch());
 
         GlobalStateWithTimeoutResult {
-            keyboard_long_press,
-            keyboard_click_exact,
-            mouse_long_press,
-            mouse_click_exact,
+            keyboard_long_press: with_context_all(keyboard_long_press, &self.context),
+            keyboard_click_exact: with_context_all(keyboard_click_exact, &self.context),
+            keyboard_repeat: with_context_all(keyboard_repeat, &self.context),
+            keyboard_sequence,
+            mouse_long_press: with_context_all(mouse_long_press, &self.context),
+            mouse_click_exact: with_context_all(mouse_click_exact, &self.context),
+            touch_long_press: with_context_all(touch_long_press, &self.context),
+            touch_click_exact: with_context_all(touch_click_exact, &self.context),
+            pen_long_press: with_context_all(pen_long_press, &self.context),
+            pen_click_exact: with_context_all(pen_click_exact, &self.context),
+            mouse_kinetic,
+            touch_kinetic,
         }
     }
+
+    #[allow(clippy::type_complexity)]
+    pub fn with_drag_cancel_event<'a, KeTr, MsTr, ToTr, PeTr, KeEv, MsEv, ToEv, PeEv>(
+        &mut self,
+        mapping: &'a GlobalMappingCache<
+            DeviceMappingCache<KeSw, KeTr, Mo, KeEv>,
+            DeviceMappingCache<MsSw, MsTr, Mo, MsEv>,
+            DeviceMappingCache<ToSw, ToTr, Mo, ToEv>,
+            DeviceMappingCache<PeSw, PeTr, Mo, PeEv>,
+            MappingModifiersCache<Mo>,
+        >,
+    ) -> GlobalStateWithDragCancelResult<'a, Mo, KeEv, KeCo, MsEv, MsCo, ToEv, ToCo, PeEv, PeCo>
+    where
+        KeSw: Clone + Eq + Hash,
+        MsSw: Clone + Eq + Hash,
+        ToSw: Clone + Eq + Hash,
+        PeSw: Clone + Eq + Hash,
+        Mo: Clone + Hash + Ord,
+        KeCo: Clone,
+        MsCo: Clone,
+        ToCo: Clone,
+        PeCo: Clone,
+    {
+        let keyboard = self
+            .as_keyboard_state_mut()
+            .with_drag_cancel_event(mapping.keyboard());
+        let mouse = self
+            .as_mouse_state_mut()
+            .with_drag_cancel_event(mapping.mouse());
+        let touch = self
+            .as_touch_state_mut()
+            .with_drag_cancel_event(mapping.touch());
+        let pen = self
+            .as_pen_state_mut()
+            .with_drag_cancel_event(mapping.pen());
+
+        GlobalStateWithDragCancelResult {
+            keyboard: with_context_all(keyboard, &self.context),
+            mouse: with_context_all(mouse, &self.context),
+            touch: with_context_all(touch, &self.context),
+            pen: with_context_all(pen, &self.context),
+        }
+    }
+
+    // A drag cancel switch cancels the drags of every lane first, and is then
+    // handled as any other press.
+    #[allow(clippy::type_complexity)]
+    pub fn with_keyboard_press_event<'a, KeTr, MsTr, ToTr, PeTr, KeEv, MsEv, ToEv, PeEv>(
+        &mut self,
+        event: SwitchEvent<Ti, KeSw>,
+        mapping: &'a GlobalMappingCache<
+            DeviceMappingCache<KeSw, KeTr, Mo, KeEv>,
+            DeviceMappingCache<MsSw, MsTr, Mo, MsEv>,
+            DeviceMappingCache<ToSw, ToTr, Mo, ToEv>,
+            DeviceMappingCache<PeSw, PeTr, Mo, PeEv>,
+            MappingModifiersCache<Mo>,
+        >,
+    ) -> GlobalStateWithPressResult<
+        Option<Ti>,
+        Vec<(FilteredBindings<'a, Mo, KeEv>, KeCo)>,
+        GlobalStateWithDragCancelResult<'a, Mo, KeEv, KeCo, MsEv, MsCo, ToEv, ToCo, PeEv, PeCo>,
+    >
+    where
+        KeSw: Clone + Eq + Hash + Debug,
+        MsSw: Clone + Eq + Hash,
+        ToSw: Clone + Eq + Hash,
+        PeSw: Clone + Eq + Hash,
+        Mo: Clone + Eq + From<KeSw> + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + Debug,
+        Du: Clone,
+        KeCo: Clone,
+        MsCo: Clone,
+        ToCo: Clone,
+        PeCo: Clone,
+    {
+        // Keys typed into a composition belong to the input method.
+        let is_drag_cancel = mapping.keyboard().drag_cancel.contains(&event.switch)
+            && !self.text_input_state.is_composing();
+        let drag_cancel = if is_drag_cancel {
+            self.with_drag_cancel_event(mapping)
+        } else {
+            GlobalStateWithDragCancelResult::default()
+        };
+        let result = self.with_keyboard_press(event, mapping);
+
+        GlobalStateWithPressResult {
+            scheduled: result.scheduled,
+            bindings: result.bindings,
+            drag_cancel,
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn with_mouse_press_event<'a, KeTr, MsTr, ToTr, PeTr, KeEv, MsEv, ToEv, PeEv>(
+        &mut self,
+        event: SwitchEvent<Ti, MsSw>,
+        mapping: &'a GlobalMappingCache<
+            DeviceMappingCache<KeSw, KeTr, Mo, KeEv>,
+            DeviceMappingCache<MsSw, MsTr, Mo, MsEv>,
+            DeviceMappingCache<ToSw, ToTr, Mo, ToEv>,
+            DeviceMappingCache<PeSw, PeTr, Mo, PeEv>,
+            MappingModifiersCache<Mo>,
+        >,
+    ) -> GlobalStateWithPressResult<
+        Option<Ti>,
+        Option<(FilteredBindings<'a, Mo, MsEv>, MsCo)>,
+        GlobalStateWithDragCancelResult<'a, Mo, KeEv, KeCo, MsEv, MsCo, ToEv, ToCo, PeEv, PeCo>,
+    >
+    where
+        KeSw: Clone + Eq + Hash,
+        MsSw: Clone + Eq + Hash + Debug,
+        ToSw: Clone + Eq + Hash,
+        PeSw: Clone + Eq + Hash,
+        Mo: Clone + Eq + From<MsSw> + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + Debug,
+        Du: Clone,
+        KeCo: Clone,
+        MsCo: Clone,
+        ToCo: Clone,
+        PeCo: Clone,
+    {
+        let drag_cancel = if mapping.mouse().drag_cancel.contains(&event.switch) {
+            self.with_drag_cancel_event(mapping)
+        } else {
+            GlobalStateWithDragCancelResult::default()
+        };
+        let result = self.with_mouse_press(event, mapping);
+
+        GlobalStateWithPressResult {
+            scheduled: result.scheduled,
+            bindings: result.bindings,
+            drag_cancel,
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn with_touch_start_event<'a, KeTr, MsTr, ToTr, PeTr, KeEv, MsEv, ToEv, PeEv>(
+        &mut self,
+        event: TouchEvent<Ti, ToSw, ToCo>,
+        mapping: &'a GlobalMappingCache<
+            DeviceMappingCache<KeSw, KeTr, Mo, KeEv>,
+            DeviceMappingCache<MsSw, MsTr, Mo, MsEv>,
+            DeviceMappingCache<ToSw, ToTr, Mo, ToEv>,
+            DeviceMappingCache<PeSw, PeTr, Mo, PeEv>,
+            MappingModifiersCache<Mo>,
+        >,
+    ) -> GlobalStateWithPressResult<
+        Option<Ti>,
+        TouchBindings<'a, Mo, ToEv, ToCo>,
+        GlobalStateWithDragCancelResult<'a, Mo, KeEv, KeCo, MsEv, MsCo, ToEv, ToCo, PeEv, PeCo>,
+    >
+    where
+        KeSw: Clone + Eq + Hash,
+        MsSw: Clone + Eq + Hash,
+        ToSw: Clone + Eq + Hash + Debug,
+        PeSw: Clone + Eq + Hash,
+        Mo: Clone + Eq + From<ToSw> + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + Debug,
+        Du: Clone,
+        KeCo: Clone,
+        MsCo: Clone,
+        ToCo: Clone,
+        PeCo: Clone,
+    {
+        let drag_cancel = if mapping.touch().drag_cancel.contains(&event.id) {
+            self.with_drag_cancel_event(mapping)
+        } else {
+            GlobalStateWithDragCancelResult::default()
+        };
+        let result = self.with_touch_start(event, mapping);
+
+        GlobalStateWithPressResult {
+            scheduled: result.scheduled,
+            bindings: result.bindings,
+            drag_cancel,
+        }
+    }
+
+    // Releases every held switch as if the user let go while the window was
+    // unfocused: drags are cancelled, non-lock modifiers dropped and all the
+    // pending timers, sequences, repeats and accumulated deltas forgotten.
+    #[allow(clippy::type_complexity)]
+    pub fn with_focus_lost_event<'a, KeTr, MsTr, ToTr, PeTr, KeEv, MsEv, ToEv, PeEv>(
+        &mut self,
+        time: Ti,
+        releases: SyntheticReleases,
+        mapping: &'a GlobalMappingCache<
+            DeviceMappingCache<KeSw, KeTr, Mo, KeEv>,
+            DeviceMappingCache<MsSw, MsTr, Mo, MsEv>,
+            DeviceMappingCache<ToSw, ToTr, Mo, ToEv>,
+            DeviceMappingCache<PeSw, PeTr, Mo, PeEv>,
+            MappingModifiersCache<Mo>,
+        >,
+    ) -> GlobalStateWithFocusLostResult<
+        'a,
+        Mo,
+        KeSw,
+        KeEv,
+        KeCo,
+        MsSw,
+        MsEv,
+        MsCo,
+        ToSw,
+        ToEv,
+        ToCo,
+        PeSw,
+        PeEv,
+        PeCo,
+    >
+    where
+        KeSw: Clone + Eq + Hash + Debug,
+        MsSw: Clone + Eq + Hash + Debug,
+        ToSw: Clone + Eq + Hash + Debug,
+        PeSw: Clone + Eq + Hash + Debug,
+        Mo: Clone + Eq + From<KeSw> + From<MsSw> + From<ToSw> + From<PeSw> + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + TimeValue + Debug,
+        Du: Clone,
+        KeCo: Clone,
+        MsCo: Clone + CoordsDistance,
+        ToCo: Clone + CoordsDistance,
+        PeCo: Clone + CoordsDistance,
+        AxMs: BorrowMut<AxisState<MsTr>>,
+        AnKe: BorrowMut<AnalogState<KeTr, KeSw>>,
+        PnPe: BorrowMut<PenState<PeSw>>,
+    {
+        let drag_cancel = self.with_drag_cancel_event(mapping);
+        // The user did not let go here, so none of the releases is a click.
+        self.keyboard.pointer_state.cancel_clicks();
+        self.mouse.pointer_state.cancel_clicks();
+        self.touch.pointer_state.cancel_clicks();
+        self.pen.pointer_state.cancel_clicks();
+
+        // Modifiers without bindings are only tracked by the repeat state.
+        let mut released_keyboard: Vec<KeSw> = Vec::new();
+        let held = self
+            .keyboard
+            .repeat_state
+            .iter_switches()
+            .chain(self.keyboard.timed_state.iter_pressed())
+            .chain(self.keyboard.pointer_state.iter_switches());
+        for switch in held {
+            if !released_keyboard.contains(switch) {
+                released_keyboard.push(switch.clone());
+            }
+        }
+        let mut released_mouse: Vec<MsSw> = Vec::new();
+        let held = self
+            .mouse
+            .timed_state
+            .iter_pressed()
+            .chain(self.mouse.pointer_state.iter_switches());
+        for switch in held {
+            if !released_mouse.contains(switch) {
+                released_mouse.push(switch.clone());
+            }
+        }
+        // Modifiers go last so that the releases still see them held.
+        let modifiers = mapping.modifiers().switches();
+        released_keyboard.sort_by_key(|switch| modifiers.contains(&Mo::from(switch.clone())));
+        released_mouse.sort_by_key(|switch| modifiers.contains(&Mo::from(switch.clone())));
+        let touches = self.touch.gesture_state.pointers().to_vec();
+        let (released_pen, lifted) = self.lift_pen();
+
+        let mut keyboard = Vec::new();
+        for switch in &released_keyboard {
+            let event = SwitchEvent::new(time.clone(), switch.clone());
+            keyboard.extend(self.with_keyboard_release_event(event, mapping).bindings);
+        }
+        let mut mouse = Vec::new();
+        for switch in &released_mouse {
+            let event = SwitchEvent::new(time.clone(), switch.clone());
+            mouse.extend(self.with_mouse_release_event(event, mapping).bindings);
+        }
+        let mut touch = Vec::new();
+        let mut released_touch = Vec::new();
+        for (id, coords) in touches {
+            let event = TouchEvent::new(time.clone(), id.clone(), coords);
+            touch.push(self.with_touch_end_event(event, mapping).bindings);
+            released_touch.push(id);
+        }
+        let mut pen = self
+            .with_pen_event(&CoordsEvent::new(time, lifted), mapping)
+            .bindings;
+        if releases == SyntheticReleases::Suppress {
+            keyboard.clear();
+            mouse.clear();
+            touch.clear();
+            pen.clear();
+        }
+
+        let released_modifiers = self
+            .modifiers
+            .release_all(|modifier| mapping.modifiers().kind(modifier) == ModifierKind::Lock);
+
+        self.keyboard.timed_state.reset();
+        self.mouse.timed_state.reset();
+        self.touch.timed_state.reset();
+        self.pen.timed_state.reset();
+        self.keyboard.long_press_scheduler.clear();
+        self.keyboard.click_exact_scheduler.clear();
+        self.mouse.long_press_scheduler.clear();
+        self.mouse.click_exact_scheduler.clear();
+        self.touch.long_press_scheduler.clear();
+        self.touch.click_exact_scheduler.clear();
+        self.pen.long_press_scheduler.clear();
+        self.pen.click_exact_scheduler.clear();
+        self.mouse.pointer_state.stop_kinetics();
+        self.mouse.kinetic_scheduler.clear();
+        self.touch.pointer_state.stop_kinetics();
+        self.touch.kinetic_scheduler.clear();
+        let _ = self.keyboard.sequence_state.reset();
+        self.keyboard.sequence_scheduler.clear();
+        self.keyboard.repeat_state.reset();
+        self.keyboard.repeat_scheduler.clear();
+        self.mouse.axis_state.borrow_mut().reset();
+        let _ = self.keyboard.analog_state.borrow_mut().reset();
+        let _ = self.pen.pen_state.borrow_mut().reset();
+        let _ = self.text_input_state.reset();
+
+        GlobalStateWithFocusLostResult {
+            drag_cancel,
+            keyboard,
+            mouse,
+            touch,
+            pen,
+            released_keyboard,
+            released_mouse,
+            released_touch,
+            released_pen,
+            released_modifiers,
+        }
+    }
+
+    pub fn next_scheduled(&self) -> Option<&Ti>
+    where
+        Ti: Ord,
+    {
+        [
+            self.keyboard.long_press_scheduler.next_scheduled(),
+            self.keyboard.click_exact_scheduler.next_scheduled(),
+            self.mouse.long_press_scheduler.next_scheduled(),
+            self.mouse.click_exact_scheduler.next_scheduled(),
+            self.touch.long_press_scheduler.next_scheduled(),
+            self.touch.click_exact_scheduler.next_scheduled(),
+            self.pen.long_press_scheduler.next_scheduled(),
+            self.pen.click_exact_scheduler.next_scheduled(),
+            self.keyboard.sequence_scheduler.next_scheduled(),
+            self.keyboard.repeat_scheduler.next_scheduled(),
+            self.mouse.kinetic_scheduler.next_scheduled(),
+            self.touch.kinetic_scheduler.next_scheduled(),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
 }
 
-impl<Mo, Ti, Sw, Co, CsMs, TsMs, ShMsLo, ShMsCl, PoMs>
+impl<Mo, Ti, Du, Sw, Co, AnKe, Ms, To, Pe, Ht>
     GlobalState<
         Modifiers<Mo>,
-        CoordsState<Co>,
-        CsMs,
-        TimedState<Sw>,
-        TsMs,
-        DeviceSchedulerState<Ti, Sw, Mo, Co, LongPressHandleRequest>,
-        DeviceSchedulerState<Ti, Sw, Mo, Co, ClickExactHandleRequest>,
-        ShMsLo,
-        ShMsCl,
-        PointerState<Sw, Co>,
-        PoMs,
+        KeyboardLane<
+            CoordsState<Co>,
+            TimedState<Sw, Du>,
+            DeviceSchedulerState<Ti, Sw, Mo, Co, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, Sw, Mo, Co, ClickExactHandleRequest>,
+            PointerState<Sw, Co, Ti, Du>,
+            SequenceState<SequenceStep<Sw, Mo>, Du>,
+            SequenceSchedulerState<Ti, ()>,
+            RepeatState<Sw, Du>,
+            RepeatSchedulerState<Ti, Sw>,
+            AnKe,
+        >,
+        Ms,
+        To,
+        Pe,
+        Ht,
     >
+where
+    Ht: HitTester<Co>,
 {
-    fn as_keyboard_state_mut(
-        &mut self,
-    ) -> DeviceState<
-        &mut Modifiers<Mo>,
-        &mut CoordsState<Co>,
-        &mut TimedState<Sw>,
-        &mut DeviceSchedulerState<Ti, Sw, Mo, Co, LongPressHandleRequest>,
-        &mut DeviceSchedulerState<Ti, Sw, Mo, Co, ClickExactHandleRequest>,
-        &mut PointerState<Sw, Co>,
-    > {
+    fn as_keyboard_state_mut(&mut self) -> DeviceStateMut<'_, Mo, Ti, Du, Sw, Co, Ht> {
         DeviceState::new(
             &mut self.modifiers,
-            &mut self.keyboard_coords_state,
-            &mut self.keyboard_timed_state,
-            &mut self.keyboard_long_press_scheduler,
-            &mut self.keyboard_click_exact_scheduler,
-            &mut self.keyboard_pointer_state,
+            &mut self.keyboard.coords_state,
+            &mut self.keyboard.timed_state,
+            &mut self.keyboard.long_press_scheduler,
+            &mut self.keyboard.click_exact_scheduler,
+            &mut self.keyboard.pointer_state,
+            &mut self.recovery,
+            &self.hit_tester,
         )
     }
 
-    pub fn with_keyboard_press_event<'a, Tr, MsMa, Ev>(
+    fn with_keyboard_press<'a, Tr, MsMa, ToMa, PeMa, Ev>(
         &mut self,
         event: SwitchEvent<Ti, Sw>,
-        mapping: &'a GlobalMappingCache<
-            DeviceMappingCache<Sw, Tr, Mo, Ev>,
-            MsMa,
-            MappingModifiersCache<Mo>,
-        >,
-    ) -> GlobalStateWithEventResult<Option<Ti>, Option<(FilteredBindings<'a, Mo, Ev>, Co)>>
+        mapping: &'a KeyboardMappingCache<Sw, Tr, Mo, Ev, MsMa, ToMa, PeMa>,
+    ) -> GlobalStateWithEventResult<Option<Ti>, Vec<DeviceBindings<'a, Mo, Ev, Co>>>
     where
-        Sw: Clone + Eq + Hash,
+        Sw: Clone + Eq + Hash + Debug,
         Mo: Clone + Eq + From<Sw> + Hash + Ord,
-        Ti: Clone + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + Debug,
+        Du: Clone,
         Co: Clone,
-        // TODO: Remove after debugging
-        Ev: std::fmt::Debug,
-        Ti: std::fmt::Debug,
-        Sw: std::fmt::Debug,
-        Tr: std::fmt::Debug,
-        Mo: std::fmt::Debug,
-        Ev: std::fmt::Debug,
     {
         let mut state = self.as_keyboard_state_mut();
         let (scheduled, bindings) =
-            state.with_press_event(event, mapping.keyboard(), mapping.modifiers());
+            state.with_press_event(event.clone(), mapping.keyboard(), mapping.modifiers());
+        let mut bindings: Vec<_> = bindings.into_iter().collect();
+
+        let repeat = self
+            .keyboard
+            .repeat_state
+            .on_press_event(event.switch.clone(), event.time.clone());
+        // Only keys with repeat bindings are worth waking up for.
+        if let Some((deadline, request)) = repeat {
+            if mapping
+                .keyboard()
+                .repeat
+                .filter_by_switch(&event.switch)
+                .is_some()
+            {
+                self.keyboard
+                    .repeat_scheduler
+                    .schedule(deadline, event.switch.clone(), request);
+            }
+        }
+
+        let switch = event.switch.clone();
+        if self.text_input_state.is_composing() {
+            // Keys typed into a composition do not advance sequences either.
+            // The key that commits it is released after the commit, so the
+            // press stays suppressed until then.
+            bindings.clear();
+            self.keyboard.timed_state.suppress(&switch);
+        } else {
+            let (interrupted, sequence) = self.with_keyboard_sequence_step(event, mapping);
+            // A step of a sequence replaces the bindings of its key until
+            // the key is released.
+            if sequence.is_some() || self.keyboard.sequence_state.is_pending() {
+                bindings.clear();
+                self.keyboard.timed_state.suppress(&switch);
+            }
+            if let Some(interrupted) = interrupted {
+                bindings.insert(0, interrupted);
+            }
+            bindings.extend(sequence);
+        }
+        use_latches(&mut self.modifiers, switch, mapping.modifiers());
+
+        let scheduled = scheduled
+            .into_iter()
+            .chain(self.keyboard.sequence_scheduler.next_scheduled().cloned())
+            .chain(self.keyboard.repeat_scheduler.next_scheduled().cloned())
+            .min();
 
         GlobalStateWithEventResult {
             scheduled,
-            bindings,
+            bindings: with_context_all(bindings, &self.context),
         }
     }
 
-    pub fn with_keyboard_release_event<'a, Tr, MsMa, Ev>(
+    fn with_keyboard_sequence_step<'a, Tr, MsMa, ToMa, PeMa, Ev>(
         &mut self,
         event: SwitchEvent<Ti, Sw>,
-        mapping: &'a GlobalMappingCache<
-            DeviceMappingCache<Sw, Tr, Mo, Ev>,
-            MsMa,
-            MappingModifiersCache<Mo>,
-        >,
-    ) -> GlobalStateWithEventResult<Option<Ti>, Option<(FilteredBindings<'a, Mo, Ev>, Co)>>
+        mapping: &'a KeyboardMappingCache<Sw, Tr, Mo, Ev, MsMa, ToMa, PeMa>,
+    ) -> SequenceStepBindings<'a, Mo, Ev, Co>
     where
         Sw: Clone + Eq + Hash,
         Mo: Clone + Eq + From<Sw> + Hash + Ord,
-        Ti: Clone + Ord,
+        Ti: Ord + Add<Du, Output = Ti>,
+        Du: Clone,
         Co: Clone,
-        // TODO: Remove after debugging
-        Ev: std::fmt::Debug,
-        Ti: std::fmt::Debug,
-        Sw: std::fmt::Debug,
-        Tr: std::fmt::Debug,
-        Mo: std::fmt::Debug,
-        Ev: std::fmt::Debug,
     {
+        let modifier = Mo::from(event.switch.clone());
+        if mapping.modifiers().switches().contains(&modifier) {
+            return (None, None);
+        }
+
+        let mapping_modifiers = mapping.modifiers();
+        let mapping = &mapping.keyboard().sequence;
+        let modifiers = mapping_modifiers.aliases().generalize(&self.modifiers);
+        let unlocked = mapping_modifiers.without_locks(&modifiers);
+        // Sequence steps match exactly, so locks only count when a sequence mentions them.
+        let step = if modifiers == unlocked {
+            SequenceStep::new(event.switch, modifiers)
+        } else {
+            let step = SequenceStep::new(event.switch.clone(), modifiers);
+            let mut steps = self.keyboard.sequence_state.pending().to_vec();
+            steps.push(step.clone());
+            let is_locked_step = mapping.match_kind(&steps).is_some()
+                || mapping.match_kind(core::slice::from_ref(&step)).is_some();
+            if is_locked_step {
+                step
+            } else {
+                SequenceStep::new(event.switch, unlocked)
+            }
+        };
+        // An extendable sequence completes when the step does not extend it.
+        let pending = self.keyboard.sequence_state.pending();
+        let mut interrupted = None;
+        if mapping.match_kind(pending) == Some(SequenceMatchKind::Extendable) {
+            let mut steps = pending.to_vec();
+            steps.push(step.clone());
+            if mapping.match_kind(&steps).is_none() {
+                let steps = self.keyboard.sequence_state.reset();
+                interrupted = self.keyboard_sequence_bindings(&steps, mapping);
+            }
+        }
+
+        let data = self
+            .keyboard
+            .sequence_state
+            .on_step_event(step, |steps| mapping.match_kind(steps));
+        let sequence = match data {
+            Some(SequenceEventData::Pending(request)) => {
+                let deadline = self.keyboard.sequence_state.deadline(event.time);
+                self.keyboard
+                    .sequence_scheduler
+                    .schedule(deadline, (), request);
+                None
+            }
+            Some(SequenceEventData::Complete(steps)) => {
+                self.keyboard_sequence_bindings(&steps, mapping)
+            }
+            None => None,
+        };
+        (interrupted, sequence)
+    }
+
+    fn keyboard_sequence_bindings<'a, Ev>(
+        &self,
+        steps: &[SequenceStep<Sw, Mo>],
+        mapping: &'a SequenceMappingCache<Sw, Mo, Ev>,
+    ) -> Option<DeviceBindings<'a, Mo, Ev, Co>>
+    where
+        Sw: Eq + Hash,
+        Mo: Eq + Hash,
+        Co: Clone,
+    {
+        let bindings = mapping.bindings_by_steps(steps)?;
+        let bindings = bindings.filter_by_context(&self.context)?;
+        let coords = self.keyboard.coords_state.coords().clone();
+        Some((bindings, coords))
+    }
+
+    pub fn keyboard_pending_sequence(&self) -> &[SequenceStep<Sw, Mo>] {
+        self.keyboard.sequence_state.pending()
+    }
+
+    // A held key repeats until its release, which never comes once the input
+    // has ended.
+    pub fn stop_repeats(&mut self) {
+        self.keyboard.repeat_scheduler.clear();
+    }
+
+    // A repeating key is neither long pressed nor clicked on its release.
+    fn cancel_repeated_timed_events(&mut self, switch: &Sw)
+    where
+        Sw: Eq + Hash,
+    {
+        self.keyboard.timed_state.cancel_long_press(switch);
+        self.keyboard.pointer_state.cancel_click(switch);
+    }
+
+    pub fn with_keyboard_release_event<'a, Tr, MsMa, ToMa, PeMa, Ev>(
+        &mut self,
+        event: SwitchEvent<Ti, Sw>,
+        mapping: &'a KeyboardMappingCache<Sw, Tr, Mo, Ev, MsMa, ToMa, PeMa>,
+    ) -> GlobalStateWithEventResult<Option<Ti>, Option<DeviceBindings<'a, Mo, Ev, Co>>>
+    where
+        Sw: Clone + Eq + Hash + Debug,
+        Mo: Clone + Eq + From<Sw> + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + Debug,
+        Du: Clone,
+        Co: Clone,
+    {
+        let _ = self.keyboard.repeat_state.on_release_event(&event.switch);
+        let is_suppressed = self.keyboard.timed_state.is_suppressed(&event.switch);
+
+        let switch = event.switch.clone();
         let mut state = self.as_keyboard_state_mut();
         let (scheduled, bindings) =
             state.with_release_event(event, mapping.keyboard(), mapping.modifiers());
+        consume_latches(&mut self.modifiers, switch, mapping.modifiers());
+        let bindings = bindings.filter(|_| !is_suppressed);
+
+        GlobalStateWithEventResult {
+            scheduled,
+            bindings: with_context(bindings, &self.context),
+        }
+    }
+
+    pub fn with_keyboard_repeat_event<'a, Tr, MsMa, ToMa, PeMa, Ev>(
+        &mut self,
+        event: &SwitchEvent<Ti, Sw>,
+        mapping: &'a KeyboardMappingCache<Sw, Tr, Mo, Ev, MsMa, ToMa, PeMa>,
+    ) -> GlobalStateWithEventResult<(), Option<DeviceBindings<'a, Mo, Ev, Co>>>
+    where
+        Sw: Eq + Hash,
+        Mo: Clone + Hash + Ord,
+        Co: Clone,
+    {
+        let num_repeats = self.keyboard.repeat_state.on_repeat_event(&event.switch);
+        let bindings = num_repeats.and_then(|_| {
+            self.cancel_repeated_timed_events(&event.switch);
+            self.as_keyboard_state_mut()
+                .with_repeat_event(&event.switch, mapping.keyboard())
+        });
+        let bindings = bindings.filter(|_| !self.text_input_state.is_composing());
+
+        GlobalStateWithEventResult {
+            scheduled: (),
+            bindings: with_context(bindings, &self.context),
+        }
+    }
+
+    // Threshold crossings are fed to the keyboard lane as virtual switches,
+    // so they get clicks, long presses and repeats like any other key.
+    pub fn with_keyboard_analog_event<'a, Tr, MsMa, ToMa, PeMa, Ev>(
+        &mut self,
+        event: AnalogEvent<Ti, Tr>,
+        mapping: &'a KeyboardMappingCache<Sw, Tr, Mo, Ev, MsMa, ToMa, PeMa>,
+    ) -> GlobalStateWithEventResult<Option<Ti>, Vec<DeviceBindings<'a, Mo, Ev, Co>>>
+    where
+        AnKe: BorrowMut<AnalogState<Tr, Sw>>,
+        Tr: Eq + Hash,
+        Sw: Clone + Eq + Hash + Debug,
+        Mo: Clone + Eq + From<Sw> + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + Debug,
+        Du: Clone,
+        Co: Clone,
+    {
+        let data = self
+            .keyboard
+            .analog_state
+            .borrow_mut()
+            .on_value_event(event.axis, event.value);
+
+        let mut scheduled = None;
+        let mut bindings = Vec::new();
+        for switch in data.released {
+            let result = self
+                .with_keyboard_release_event(SwitchEvent::new(event.time.clone(), switch), mapping);
+            scheduled = scheduled.into_iter().chain(result.scheduled).min();
+            bindings.extend(result.bindings);
+        }
+        for switch in data.pressed {
+            let result =
+                self.with_keyboard_press(SwitchEvent::new(event.time.clone(), switch), mapping);
+            scheduled = scheduled.into_iter().chain(result.scheduled).min();
+            bindings.extend(result.bindings);
+        }
 
         GlobalStateWithEventResult {
             scheduled,
@@ -339,15 +1183,11 @@ impl<Mo, Ti, Sw, Co, CsMs, TsMs, ShMsLo, ShMsCl, PoMs>
         }
     }
 
-    pub fn with_keyboard_trigger_event<'a, Tr, MsMa, Ev>(
+    pub fn with_keyboard_trigger_event<'a, Tr, MsMa, ToMa, PeMa, Ev>(
         &mut self,
         event: TriggerEvent<Ti, Tr>,
-        mapping: &'a GlobalMappingCache<
-            DeviceMappingCache<Sw, Tr, Mo, Ev>,
-            MsMa,
-            MappingModifiersCache<Mo>,
-        >,
-    ) -> GlobalStateWithEventResult<(), Option<(FilteredBindings<'a, Mo, Ev>, Co)>>
+        mapping: &'a KeyboardMappingCache<Sw, Tr, Mo, Ev, MsMa, ToMa, PeMa>,
+    ) -> GlobalStateWithEventResult<(), Option<DeviceBindings<'a, Mo, Ev, Co>>>
     where
         Tr: Eq + Hash,
         Mo: Clone + Hash + Ord,
@@ -358,144 +1198,130 @@ impl<Mo, Ti, Sw, Co, CsMs, TsMs, ShMsLo, ShMsCl, PoMs>
 
         GlobalStateWithEventResult {
             scheduled: (),
-            bindings,
+            bindings: with_context(bindings, &self.context),
         }
     }
 
-    pub fn with_keyboard_coords_event<'a, F, Tr, MsMa, Ev>(
+    pub fn with_keyboard_coords_event<'a, Tr, MsMa, ToMa, PeMa, Ev>(
         &mut self,
         event: CoordsEvent<Ti, Co>,
-        mapping: &'a GlobalMappingCache<
-            DeviceMappingCache<Sw, Tr, Mo, Ev>,
-            MsMa,
-            MappingModifiersCache<Mo>,
-        >,
-        is_dragged_fn: F,
-    ) -> GlobalStateWithEventResult<(), Vec<(FilteredBindings<'a, Mo, Ev>, Co)>>
+        mapping: &'a KeyboardMappingCache<Sw, Tr, Mo, Ev, MsMa, ToMa, PeMa>,
+    ) -> GlobalStateWithEventResult<(), Vec<DeviceBindings<'a, Mo, Ev, Co>>>
     where
-        F: FnMut(&Co, &Co) -> bool,
         Sw: Clone + Eq + Hash,
         Mo: Clone + Hash + Ord,
-        Co: Clone,
+        Ti: Clone + Ord + Add<Du, Output = Ti>,
+        Du: Clone,
+        Co: Clone + CoordsDistance,
     {
         let mut state = self.as_keyboard_state_mut();
-        let bindings = state.with_coords_event(event, mapping.keyboard(), is_dragged_fn);
+        let bindings = state.with_coords_event(event, mapping.keyboard());
 
         GlobalStateWithEventResult {
             scheduled: (),
-            bindings,
+            bindings: with_context_all(bindings, &self.context),
         }
     }
 }
 
-impl<Mo, Ti, Sw, Co, CsKe, TsKe, ShKeLo, ShKeCl, PoKe>
+impl<Mo, Ti, Du, Sw, Co, AxMs, Ke, To, Pe, Ht>
     GlobalState<
         Modifiers<Mo>,
-        CsKe,
-        CoordsState<Co>,
-        TsKe,
-        TimedState<Sw>,
-        ShKeLo,
-        ShKeCl,
-        DeviceSchedulerState<Ti, Sw, Mo, Co, LongPressHandleRequest>,
-        DeviceSchedulerState<Ti, Sw, Mo, Co, ClickExactHandleRequest>,
-        PoKe,
-        PointerState<Sw, Co>,
+        Ke,
+        MouseLane<
+            CoordsState<Co>,
+            TimedState<Sw, Du>,
+            DeviceSchedulerState<Ti, Sw, Mo, Co, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, Sw, Mo, Co, ClickExactHandleRequest>,
+            PointerState<Sw, Co, Ti, Du>,
+            AxMs,
+            DeviceSchedulerState<Ti, Sw, Mo, Co, KineticHandleRequest>,
+        >,
+        To,
+        Pe,
+        Ht,
     >
+where
+    Ht: HitTester<Co>,
 {
-    fn as_mouse_state_mut(
-        &mut self,
-    ) -> DeviceState<
-        &mut Modifiers<Mo>,
-        &mut CoordsState<Co>,
-        &mut TimedState<Sw>,
-        &mut DeviceSchedulerState<Ti, Sw, Mo, Co, LongPressHandleRequest>,
-        &mut DeviceSchedulerState<Ti, Sw, Mo, Co, ClickExactHandleRequest>,
-        &mut PointerState<Sw, Co>,
-    > {
+    fn as_mouse_state_mut(&mut self) -> DeviceStateMut<'_, Mo, Ti, Du, Sw, Co, Ht> {
         DeviceState::new(
             &mut self.modifiers,
-            &mut self.mouse_coords_state,
-            &mut self.mouse_timed_state,
-            &mut self.mouse_long_press_scheduler,
-            &mut self.mouse_click_exact_scheduler,
-            &mut self.mouse_pointer_state,
+            &mut self.mouse.coords_state,
+            &mut self.mouse.timed_state,
+            &mut self.mouse.long_press_scheduler,
+            &mut self.mouse.click_exact_scheduler,
+            &mut self.mouse.pointer_state,
+            &mut self.recovery,
+            &self.hit_tester,
         )
     }
 
-    pub fn with_mouse_press_event<'a, Tr, KeMa, Ev>(
+    fn with_mouse_press<'a, Tr, KeMa, ToMa, PeMa, Ev>(
         &mut self,
         event: SwitchEvent<Ti, Sw>,
-        mapping: &'a GlobalMappingCache<
-            KeMa,
-            DeviceMappingCache<Sw, Tr, Mo, Ev>,
-            MappingModifiersCache<Mo>,
-        >,
-    ) -> GlobalStateWithEventResult<Option<Ti>, Option<(FilteredBindings<'a, Mo, Ev>, Co)>>
+        mapping: &'a MouseMappingCache<Sw, Tr, Mo, Ev, KeMa, ToMa, PeMa>,
+    ) -> GlobalStateWithEventResult<Option<Ti>, Option<DeviceBindings<'a, Mo, Ev, Co>>>
     where
-        Sw: Clone + Eq + Hash,
+        Sw: Clone + Eq + Hash + Debug,
         Mo: Clone + Eq + From<Sw> + Hash + Ord,
-        Ti: Clone + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + Debug,
+        Du: Clone,
         Co: Clone,
-        // TODO: Remove after debugging
-        Ev: std::fmt::Debug,
-        Ti: std::fmt::Debug,
-        Sw: std::fmt::Debug,
-        Tr: std::fmt::Debug,
-        Mo: std::fmt::Debug,
-        Ev: std::fmt::Debug,
     {
+        let switch = event.switch.clone();
         let mut state = self.as_mouse_state_mut();
         let (scheduled, bindings) =
             state.with_press_event(event, mapping.mouse(), mapping.modifiers());
+        use_latches(&mut self.modifiers, switch, mapping.modifiers());
 
         GlobalStateWithEventResult {
             scheduled,
-            bindings,
+            bindings: with_context(bindings, &self.context),
         }
     }
 
-    pub fn with_mouse_release_event<'a, Tr, KeMa, Ev>(
+    pub fn with_mouse_release_event<'a, Tr, KeMa, ToMa, PeMa, Ev>(
         &mut self,
         event: SwitchEvent<Ti, Sw>,
-        mapping: &'a GlobalMappingCache<
-            KeMa,
-            DeviceMappingCache<Sw, Tr, Mo, Ev>,
-            MappingModifiersCache<Mo>,
-        >,
-    ) -> GlobalStateWithEventResult<Option<Ti>, Option<(FilteredBindings<'a, Mo, Ev>, Co)>>
+        mapping: &'a MouseMappingCache<Sw, Tr, Mo, Ev, KeMa, ToMa, PeMa>,
+    ) -> GlobalStateWithEventResult<Option<Ti>, Option<DeviceBindings<'a, Mo, Ev, Co>>>
     where
-        Sw: Clone + Eq + Hash,
+        Sw: Clone + Eq + Hash + Debug,
         Mo: Clone + Eq + From<Sw> + Hash + Ord,
-        Ti: Clone + Ord,
-        Co: Clone,
-        // TODO: Remove after debugging
-        Ev: std::fmt::Debug,
-        Ti: std::fmt::Debug,
-        Sw: std::fmt::Debug,
-        Tr: std::fmt::Debug,
-        Mo: std::fmt::Debug,
-        Ev: std::fmt::Debug,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + TimeValue + Debug,
+        Du: Clone,
+        Co: Clone + CoordsDistance,
     {
+        schedule_fling(
+            &mut self.mouse.pointer_state,
+            &mut self.mouse.kinetic_scheduler,
+            &self.modifiers,
+            self.mouse.coords_state.coords(),
+            &event,
+        );
+
+        let switch = event.switch.clone();
         let mut state = self.as_mouse_state_mut();
         let (scheduled, bindings) =
             state.with_release_event(event, mapping.mouse(), mapping.modifiers());
+        consume_latches(&mut self.modifiers, switch, mapping.modifiers());
+        let scheduled = scheduled
+            .into_iter()
+            .chain(self.mouse.kinetic_scheduler.next_scheduled().cloned())
+            .min();
 
         GlobalStateWithEventResult {
             scheduled,
-            bindings,
+            bindings: with_context(bindings, &self.context),
         }
     }
 
-    pub fn with_mouse_trigger_event<'a, Tr, KeMa, Ev>(
+    pub fn with_mouse_trigger_event<'a, Tr, KeMa, ToMa, PeMa, Ev>(
         &mut self,
         event: TriggerEvent<Ti, Tr>,
-        mapping: &'a GlobalMappingCache<
-            KeMa,
-            DeviceMappingCache<Sw, Tr, Mo, Ev>,
-            MappingModifiersCache<Mo>,
-        >,
-    ) -> GlobalStateWithEventResult<(), Option<(FilteredBindings<'a, Mo, Ev>, Co)>>
+        mapping: &'a MouseMappingCache<Sw, Tr, Mo, Ev, KeMa, ToMa, PeMa>,
+    ) -> GlobalStateWithEventResult<(), Option<DeviceBindings<'a, Mo, Ev, Co>>>
     where
         Tr: Eq + Hash,
         Mo: Clone + Hash + Ord,
@@ -506,36 +1332,524 @@ impl<Mo, Ti, Sw, Co, CsKe, TsKe, ShKeLo, ShKeCl, PoKe>
 
         GlobalStateWithEventResult {
             scheduled: (),
-            bindings,
+            bindings: with_context(bindings, &self.context),
         }
     }
 
-    pub fn with_mouse_coords_event<'a, F, Tr, KeMa, Ev>(
+    pub fn with_mouse_axis_event<'a, Tr, KeMa, ToMa, PeMa, Ev>(
+        &mut self,
+        event: &AxisEvent<Ti, Tr, Co>,
+        mapping: &'a MouseMappingCache<Sw, Tr, Mo, Ev, KeMa, ToMa, PeMa>,
+    ) -> GlobalStateWithEventResult<(), Option<DeviceBindings<'a, Mo, Ev, AxisEventData>>>
+    where
+        AxMs: BorrowMut<AxisState<Tr>>,
+        Tr: Clone + Eq + Hash,
+        Mo: Clone + Hash + Ord,
+        Co: Coords2d,
+    {
+        let data = self
+            .mouse
+            .axis_state
+            .borrow_mut()
+            .on_axis_event(event.axis.clone(), (event.delta.x(), event.delta.y()));
+        let mut state = self.as_mouse_state_mut();
+        let bindings = state.with_axis_event(&event.axis, data, mapping.mouse());
+
+        GlobalStateWithEventResult {
+            scheduled: (),
+            bindings: with_context(bindings, &self.context),
+        }
+    }
+
+    pub fn with_mouse_coords_event<'a, Tr, KeMa, ToMa, PeMa, Ev>(
         &mut self,
         event: CoordsEvent<Ti, Co>,
-        mapping: &'a GlobalMappingCache<
-            KeMa,
-            DeviceMappingCache<Sw, Tr, Mo, Ev>,
-            MappingModifiersCache<Mo>,
-        >,
-        is_dragged_fn: F,
-    ) -> GlobalStateWithEventResult<(), Vec<(FilteredBindings<'a, Mo, Ev>, Co)>>
+        mapping: &'a MouseMappingCache<Sw, Tr, Mo, Ev, KeMa, ToMa, PeMa>,
+    ) -> GlobalStateWithEventResult<(), Vec<DeviceBindings<'a, Mo, Ev, Co>>>
     where
-        F: FnMut(&Co, &Co) -> bool,
         Sw: Clone + Eq + Hash,
         Mo: Clone + Hash + Ord,
-        Co: Clone,
+        Ti: Clone + Ord + Add<Du, Output = Ti>,
+        Du: Clone,
+        Co: Clone + CoordsDistance,
     {
         let mut state = self.as_mouse_state_mut();
-        let bindings = state.with_coords_event(event, mapping.mouse(), is_dragged_fn);
+        let bindings = state.with_coords_event(event, mapping.mouse());
 
         GlobalStateWithEventResult {
             scheduled: (),
+            bindings: with_context_all(bindings, &self.context),
+        }
+    }
+}
+
+impl<Mo, Ti, Du, Sw, Co, Ke, Ms, Pe, Ht>
+    GlobalState<
+        Modifiers<Mo>,
+        Ke,
+        Ms,
+        TouchLane<
+            CoordsState<Co>,
+            TimedState<Sw, Du>,
+            DeviceSchedulerState<Ti, Sw, Mo, Co, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, Sw, Mo, Co, ClickExactHandleRequest>,
+            PointerState<Sw, Co, Ti, Du>,
+            GestureState<Sw, Co>,
+            DeviceSchedulerState<Ti, Sw, Mo, Co, KineticHandleRequest>,
+        >,
+        Pe,
+        Ht,
+    >
+where
+    Ht: HitTester<Co>,
+{
+    fn as_touch_state_mut(&mut self) -> DeviceStateMut<'_, Mo, Ti, Du, Sw, Co, Ht> {
+        DeviceState::new(
+            &mut self.modifiers,
+            &mut self.touch.coords_state,
+            &mut self.touch.timed_state,
+            &mut self.touch.long_press_scheduler,
+            &mut self.touch.click_exact_scheduler,
+            &mut self.touch.pointer_state,
+            &mut self.recovery,
+            &self.hit_tester,
+        )
+    }
+
+    fn with_touch_start<'a, Tr, KeMa, MsMa, PeMa, Ev>(
+        &mut self,
+        event: TouchEvent<Ti, Sw, Co>,
+        mapping: &'a TouchMappingCache<Sw, Tr, Mo, Ev, KeMa, MsMa, PeMa>,
+    ) -> GlobalStateWithEventResult<Option<Ti>, TouchBindings<'a, Mo, Ev, Co>>
+    where
+        Sw: Clone + Eq + Hash + Debug,
+        Mo: Clone + Eq + From<Sw> + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + Debug,
+        Du: Clone,
+        Co: Clone,
+    {
+        let gesture = self
+            .touch
+            .gesture_state
+            .on_press_event(event.id.clone(), event.coords.clone());
+        let gesture = match gesture {
+            Ok(gesture) => gesture,
+            Err(err) => {
+                if self.recovery.on_error(
+                    "input_core::GestureState::on_press_event",
+                    &err,
+                    &event.id,
+                ) {
+                    let _ = self.touch.gesture_state.on_release_event(&event.id);
+                    self.touch
+                        .gesture_state
+                        .on_press_event(event.id.clone(), event.coords.clone())
+                        .ok()
+                        .flatten()
+                } else {
+                    None
+                }
+            }
+        };
+        let gestures = with_gesture_events(
+            &mapping.touch().gesture,
+            &self.modifiers,
+            &self.context,
+            &self.hit_tester,
+            &event.coords,
+            gesture,
+        );
+
+        // Bindings see the primary touch as the primary switch.
+        let switch = self.touch.gesture_state.touch_switch(&event.id);
+        let mut state = self.as_touch_state_mut();
+        state.coords_state.set_coords(event.coords);
+        let event = SwitchEvent::new(event.time, switch);
+        let switch = event.switch.clone();
+        let (scheduled, pointer) =
+            state.with_press_event(event, mapping.touch(), mapping.modifiers());
+        use_latches(&mut self.modifiers, switch, mapping.modifiers());
+
+        GlobalStateWithEventResult {
+            scheduled,
+            bindings: TouchBindings {
+                pointer: with_context(pointer, &self.context),
+                gestures,
+            },
+        }
+    }
+
+    pub fn with_touch_move_event<'a, Tr, KeMa, MsMa, PeMa, Ev>(
+        &mut self,
+        event: TouchEvent<Ti, Sw, Co>,
+        mapping: &'a TouchMappingCache<Sw, Tr, Mo, Ev, KeMa, MsMa, PeMa>,
+    ) -> GlobalStateWithEventResult<(), TouchBindings<'a, Mo, Ev, Co>>
+    where
+        Sw: Clone + Eq + Hash,
+        Mo: Clone + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti>,
+        Du: Clone,
+        Co: Clone + Coords2d,
+    {
+        let switch = self.touch.gesture_state.touch_switch(&event.id);
+        let gesture = self
+            .touch
+            .gesture_state
+            .on_move_event(&event.id, event.coords.clone());
+        let gestures = with_gesture_events(
+            &mapping.touch().gesture,
+            &self.modifiers,
+            &self.context,
+            &self.hit_tester,
+            &event.coords,
+            gesture,
+        );
+
+        let mut state = self.as_touch_state_mut();
+        let pointer = state.with_switch_coords_event(
+            &switch,
+            CoordsEvent::new(event.time, event.coords),
+            mapping.touch(),
+        );
+
+        GlobalStateWithEventResult {
+            scheduled: (),
+            bindings: TouchBindings {
+                pointer: with_context(pointer, &self.context),
+                gestures,
+            },
+        }
+    }
+
+    pub fn with_touch_end_event<'a, Tr, KeMa, MsMa, PeMa, Ev>(
+        &mut self,
+        event: TouchEvent<Ti, Sw, Co>,
+        mapping: &'a TouchMappingCache<Sw, Tr, Mo, Ev, KeMa, MsMa, PeMa>,
+    ) -> GlobalStateWithEventResult<Option<Ti>, TouchBindings<'a, Mo, Ev, Co>>
+    where
+        Sw: Clone + Eq + Hash + Debug,
+        Mo: Clone + Eq + From<Sw> + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + TimeValue + Debug,
+        Du: Clone,
+        Co: Clone + CoordsDistance,
+    {
+        // The release may end the primary touch, look its switch up first.
+        let switch = self.touch.gesture_state.touch_switch(&event.id);
+        let gesture = self.touch.gesture_state.on_release_event(&event.id);
+        let gesture = match gesture {
+            Ok(gesture) => gesture,
+            // A released touch is already in sync with the host.
+            Err(err) => {
+                let _ = self.recovery.on_error(
+                    "input_core::GestureState::on_release_event",
+                    &err,
+                    &event.id,
+                );
+                None
+            }
+        };
+        let gestures = with_gesture_events(
+            &mapping.touch().gesture,
+            &self.modifiers,
+            &self.context,
+            &self.hit_tester,
+            &event.coords,
+            gesture,
+        );
+
+        self.touch.coords_state.set_coords(event.coords);
+        let event = SwitchEvent::new(event.time, switch);
+        schedule_fling(
+            &mut self.touch.pointer_state,
+            &mut self.touch.kinetic_scheduler,
+            &self.modifiers,
+            self.touch.coords_state.coords(),
+            &event,
+        );
+
+        let switch = event.switch.clone();
+        let mut state = self.as_touch_state_mut();
+        let (scheduled, pointer) =
+            state.with_release_event(event, mapping.touch(), mapping.modifiers());
+        consume_latches(&mut self.modifiers, switch, mapping.modifiers());
+        let scheduled = scheduled
+            .into_iter()
+            .chain(self.touch.kinetic_scheduler.next_scheduled().cloned())
+            .min();
+
+        GlobalStateWithEventResult {
+            scheduled,
+            bindings: TouchBindings {
+                pointer: with_context(pointer, &self.context),
+                gestures,
+            },
+        }
+    }
+}
+
+impl<Mo, Ti, Du, Sw, Co, PnPe, Ke, Ms, To, Ht>
+    GlobalState<
+        Modifiers<Mo>,
+        Ke,
+        Ms,
+        To,
+        PenLane<
+            CoordsState<Co>,
+            TimedState<Sw, Du>,
+            DeviceSchedulerState<Ti, Sw, Mo, Co, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, Sw, Mo, Co, ClickExactHandleRequest>,
+            PointerState<Sw, Co, Ti, Du>,
+            PnPe,
+        >,
+        Ht,
+    >
+where
+    Ht: HitTester<Co>,
+{
+    fn as_pen_state_mut(&mut self) -> DeviceStateMut<'_, Mo, Ti, Du, Sw, Co, Ht> {
+        DeviceState::new(
+            &mut self.modifiers,
+            &mut self.pen.coords_state,
+            &mut self.pen.timed_state,
+            &mut self.pen.long_press_scheduler,
+            &mut self.pen.click_exact_scheduler,
+            &mut self.pen.pointer_state,
+            &mut self.recovery,
+            &self.hit_tester,
+        )
+    }
+
+    // Lifting the pen releases its switches in the order the pen state keeps.
+    fn lift_pen(&self) -> (Vec<Sw>, PenCoords<Co>)
+    where
+        PnPe: BorrowMut<PenState<Sw>>,
+        Sw: Clone,
+        Co: Clone,
+    {
+        let pen_state = self.pen.pen_state.borrow();
+        let released = pen_state.pressed().iter().rev().cloned().collect();
+        let lifted = pen_state
+            .sample()
+            .with_coords(self.pen.coords_state.coords().clone())
+            .with_pressure(0.0)
+            .with_barrel_pressed(false);
+        (released, lifted)
+    }
+
+    // The pen state turns samples into switches: the contact of the tool, its
+    // pressure thresholds and the barrel button. The sample is moved to first,
+    // so that presses and releases happen where the pen is.
+    pub fn with_pen_event<'a, Tr, KeMa, MsMa, ToMa, Ev>(
+        &mut self,
+        event: &CoordsEvent<Ti, PenCoords<Co>>,
+        mapping: &'a PenMappingCache<Sw, Tr, Mo, Ev, KeMa, MsMa, ToMa>,
+    ) -> GlobalStateWithEventResult<Option<Ti>, Vec<PenBindings<'a, Mo, Ev, Co>>>
+    where
+        PnPe: BorrowMut<PenState<Sw>>,
+        Sw: Clone + Eq + Hash + Debug,
+        Mo: Clone + Eq + From<Sw> + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + Debug,
+        Du: Clone,
+        Co: Clone + CoordsDistance,
+    {
+        let data = self.pen.pen_state.borrow_mut().on_pen_event(&event.coords);
+
+        let mut state = self.as_pen_state_mut();
+        let coords = CoordsEvent::new(event.time.clone(), event.coords.coords.clone());
+        let mut bindings = state.with_coords_event(coords, mapping.pen());
+
+        let mut scheduled = None;
+        for switch in data.released {
+            let event = SwitchEvent::new(event.time.clone(), switch.clone());
+            let mut state = self.as_pen_state_mut();
+            let (deadline, released) =
+                state.with_release_event(event, mapping.pen(), mapping.modifiers());
+            consume_latches(&mut self.modifiers, switch, mapping.modifiers());
+            scheduled = scheduled.into_iter().chain(deadline).min();
+            bindings.extend(released);
+        }
+        for switch in data.pressed {
+            let event = SwitchEvent::new(event.time.clone(), switch.clone());
+            let mut state = self.as_pen_state_mut();
+            let (deadline, pressed) =
+                state.with_press_event(event, mapping.pen(), mapping.modifiers());
+            use_latches(&mut self.modifiers, switch, mapping.modifiers());
+            scheduled = scheduled.into_iter().chain(deadline).min();
+            bindings.extend(pressed);
+        }
+
+        let bindings = with_context_all(bindings, &self.context)
+            .into_iter()
+            .map(|(bindings, coords)| (bindings, event.coords.with_coords(coords)))
+            .collect();
+        GlobalStateWithEventResult {
+            scheduled,
             bindings,
         }
     }
 }
 
+fn with_gesture_events<'a, Mo, Ev, Co, Ht>(
+    mapping: &'a CoordsMappingCache<GestureEventKind, Mo, Ev>,
+    modifiers: &Modifiers<Mo>,
+    context: &ContextStack,
+    hit_tester: &Ht,
+    coords: &Co,
+    events: impl IntoIterator<Item = GestureEventData>,
+) -> Vec<(FilteredBindings<'a, Mo, Ev>, GestureEventData)>
+where
+    Mo: Clone + Hash + Ord,
+    Ht: HitTester<Co>,
+{
+    use crate::unwrap_or_continue;
+
+    let mut all_bindings = vec![];
+    for data in events {
+        let mapping = mapping.filter_by_pointer_data(&data.kind);
+        let mapping = unwrap_or_continue!(mapping);
+        let mapping = mapping.filter_by_modifiers(modifiers);
+        let mapping = unwrap_or_continue!(mapping);
+        let mapping = unwrap_or_continue!(mapping.filter_by_context(context));
+        let bindings = unwrap_or_continue!(mapping.filter_by_target(hit_tester, coords));
+
+        all_bindings.push((bindings, data));
+    }
+
+    all_bindings
+}
+
+// Drags that are fast enough at the release are flung. The fling is scheduled
+// at the release time, so it comes with the next timeout after the release
+// bindings, and the kinetic updates follow it.
+fn schedule_fling<Ti, Du, Sw, Mo, Co>(
+    pointer_state: &mut PointerState<Sw, Co, Ti, Du>,
+    scheduler: &mut DeviceSchedulerState<Ti, Sw, Mo, Co, KineticHandleRequest>,
+    modifiers: &Modifiers<Mo>,
+    coords: &Co,
+    event: &SwitchEvent<Ti, Sw>,
+) where
+    Sw: Clone + Eq + Hash,
+    Mo: Clone,
+    Ti: Clone + Ord + Add<Du, Output = Ti> + TimeValue,
+    Du: Clone,
+    Co: Clone + CoordsDistance,
+{
+    let request = pointer_state.on_fling_event(&event.switch, &event.time);
+    let Some(request) = request else {
+        return;
+    };
+    // Targets are hit-tested against what was under the pointer at press time.
+    let coords = pointer_state
+        .press_coords(&event.switch)
+        .unwrap_or(coords)
+        .clone();
+    scheduler.schedule(
+        event.time.clone(),
+        (event.clone(), modifiers.clone(), coords),
+        request,
+    );
+}
+
+fn with_kinetic_timeout<'a, Ti, Du, Sw, Mo, Co, Ev, Ht>(
+    pointer_state: &mut PointerState<Sw, Co, Ti, Du>,
+    scheduler: &mut DeviceSchedulerState<Ti, Sw, Mo, Co, KineticHandleRequest>,
+    mapping: &'a CoordsMappingCache<PointerMoveEventData<Sw>, Mo, Ev>,
+    context: &ContextStack,
+    hit_tester: &Ht,
+    time: &Ti,
+) -> Vec<(FilteredBindings<'a, Mo, Ev>, KineticEventData)>
+where
+    Sw: Clone + Eq + Hash,
+    Mo: Clone + Hash + Ord,
+    Ti: Clone + Ord + Add<Du, Output = Ti>,
+    Du: Clone + TimeValue,
+    Ht: HitTester<Co>,
+{
+    use crate::unwrap_or_continue;
+
+    let mut all_bindings = vec![];
+    let deadlines: Vec<_> = scheduler.take_scheduled(time).collect();
+    for (deadline, requests) in deadlines {
+        for ((event, modifiers, coords), request) in requests {
+            let result =
+                pointer_state.on_kinetic_timeout_event(&event.switch, request, deadline.clone());
+            let (data, next) = unwrap_or_continue!(result);
+            let pointer_data = PointerMoveEventData {
+                switch: event.switch.clone(),
+                kind: data.kind,
+            };
+            let bindings = mapping
+                .filter_by_pointer_data(&pointer_data)
+                .and_then(|mapping| mapping.filter_by_modifiers(&modifiers))
+                .and_then(|mapping| mapping.filter_by_context(context))
+                .and_then(|mapping| mapping.filter_by_target(hit_tester, &coords));
+            if let Some((deadline, request)) = next {
+                scheduler.schedule(deadline, (event, modifiers, coords), request);
+            }
+            if let Some(bindings) = bindings {
+                all_bindings.push((bindings, data));
+            }
+        }
+    }
+
+    all_bindings
+}
+
+// The device state of a lane, borrowed together with the shared state.
+type DeviceStateMut<'a, Mo, Ti, Du, Sw, Co, Ht> = DeviceState<
+    &'a mut Modifiers<Mo>,
+    &'a mut CoordsState<Co>,
+    &'a mut TimedState<Sw, Du>,
+    &'a mut DeviceSchedulerState<Ti, Sw, Mo, Co, LongPressHandleRequest>,
+    &'a mut DeviceSchedulerState<Ti, Sw, Mo, Co, ClickExactHandleRequest>,
+    &'a mut PointerState<Sw, Co, Ti, Du>,
+    &'a mut Recovery,
+    &'a Ht,
+>;
+
+// The mapping of a single lane, with the other lanes left generic.
+type KeyboardMappingCache<Sw, Tr, Mo, Ev, MsMa, ToMa, PeMa> = GlobalMappingCache<
+    DeviceMappingCache<Sw, Tr, Mo, Ev>,
+    MsMa,
+    ToMa,
+    PeMa,
+    MappingModifiersCache<Mo>,
+>;
+
+type MouseMappingCache<Sw, Tr, Mo, Ev, KeMa, ToMa, PeMa> = GlobalMappingCache<
+    KeMa,
+    DeviceMappingCache<Sw, Tr, Mo, Ev>,
+    ToMa,
+    PeMa,
+    MappingModifiersCache<Mo>,
+>;
+
+type TouchMappingCache<Sw, Tr, Mo, Ev, KeMa, MsMa, PeMa> = GlobalMappingCache<
+    KeMa,
+    MsMa,
+    DeviceMappingCache<Sw, Tr, Mo, Ev>,
+    PeMa,
+    MappingModifiersCache<Mo>,
+>;
+
+type PenMappingCache<Sw, Tr, Mo, Ev, KeMa, MsMa, ToMa> = GlobalMappingCache<
+    KeMa,
+    MsMa,
+    ToMa,
+    DeviceMappingCache<Sw, Tr, Mo, Ev>,
+    MappingModifiersCache<Mo>,
+>;
+
+type DeviceBindings<'a, Mo, Ev, Co> = (FilteredBindings<'a, Mo, Ev>, Co);
+
+type PenBindings<'a, Mo, Ev, Co> = DeviceBindings<'a, Mo, Ev, PenCoords<Co>>;
+
+// The bindings of the sequence that a step interrupts and of the one it completes.
+type SequenceStepBindings<'a, Mo, Ev, Co> = (
+    Option<DeviceBindings<'a, Mo, Ev, Co>>,
+    Option<DeviceBindings<'a, Mo, Ev, Co>>,
+);
+
 #[derive(Clone, Debug)]
 pub struct GlobalStateWithEventResult<Ti, Bi> {
     pub scheduled: Ti,
@@ -543,11 +1857,92 @@ pub struct GlobalStateWithEventResult<Ti, Bi> {
 }
 
 #[derive(Clone, Debug)]
-pub struct GlobalStateWithTimeoutResult<'a, Mo, KeEv, KeCo, MsEv, MsCo> {
+pub struct GlobalStateWithPressResult<Ti, Bi, Dc> {
+    pub scheduled: Ti,
+    pub bindings: Bi,
+    pub drag_cancel: Dc,
+}
+
+#[derive(Clone, Debug)]
+pub struct TouchBindings<'a, Mo, Ev, Co> {
+    pub pointer: Option<DeviceBindings<'a, Mo, Ev, Co>>,
+    pub gestures: Vec<(FilteredBindings<'a, Mo, Ev>, GestureEventData)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GlobalStateWithTimeoutResult<'a, Mo, KeEv, KeCo, MsEv, MsCo, ToEv, ToCo, PeEv, PeCo> {
     pub keyboard_long_press: Vec<(FilteredBindings<'a, Mo, KeEv>, KeCo)>,
     pub keyboard_click_exact: Vec<(FilteredBindings<'a, Mo, KeEv>, KeCo)>,
+    pub keyboard_repeat: Vec<(FilteredBindings<'a, Mo, KeEv>, KeCo)>,
+    pub keyboard_sequence: Vec<(FilteredBindings<'a, Mo, KeEv>, KeCo)>,
     pub mouse_long_press: Vec<(FilteredBindings<'a, Mo, MsEv>, MsCo)>,
     pub mouse_click_exact: Vec<(FilteredBindings<'a, Mo, MsEv>, MsCo)>,
+    pub touch_long_press: Vec<(FilteredBindings<'a, Mo, ToEv>, ToCo)>,
+    pub touch_click_exact: Vec<(FilteredBindings<'a, Mo, ToEv>, ToCo)>,
+    pub pen_long_press: Vec<(FilteredBindings<'a, Mo, PeEv>, PeCo)>,
+    pub pen_click_exact: Vec<(FilteredBindings<'a, Mo, PeEv>, PeCo)>,
+    pub mouse_kinetic: Vec<(FilteredBindings<'a, Mo, MsEv>, KineticEventData)>,
+    pub touch_kinetic: Vec<(FilteredBindings<'a, Mo, ToEv>, KineticEventData)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GlobalStateWithDragCancelResult<'a, Mo, KeEv, KeCo, MsEv, MsCo, ToEv, ToCo, PeEv, PeCo> {
+    pub keyboard: Vec<(FilteredBindings<'a, Mo, KeEv>, KeCo)>,
+    pub mouse: Vec<(FilteredBindings<'a, Mo, MsEv>, MsCo)>,
+    pub touch: Vec<(FilteredBindings<'a, Mo, ToEv>, ToCo)>,
+    pub pen: Vec<(FilteredBindings<'a, Mo, PeEv>, PeCo)>,
+}
+
+impl<Mo, KeEv, KeCo, MsEv, MsCo, ToEv, ToCo, PeEv, PeCo> Default
+    for GlobalStateWithDragCancelResult<'_, Mo, KeEv, KeCo, MsEv, MsCo, ToEv, ToCo, PeEv, PeCo>
+{
+    fn default() -> Self {
+        Self {
+            keyboard: Vec::new(),
+            mouse: Vec::new(),
+            touch: Vec::new(),
+            pen: Vec::new(),
+        }
+    }
+}
+
+// Whether the releases synthesised on focus loss go through the mapping.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyntheticReleases {
+    Emit,
+    #[default]
+    Suppress,
+}
+
+#[derive(Clone, Debug)]
+pub struct GlobalStateWithFocusLostResult<
+    'a,
+    Mo,
+    KeSw,
+    KeEv,
+    KeCo,
+    MsSw,
+    MsEv,
+    MsCo,
+    ToSw,
+    ToEv,
+    ToCo,
+    PeSw,
+    PeEv,
+    PeCo,
+> {
+    pub drag_cancel:
+        GlobalStateWithDragCancelResult<'a, Mo, KeEv, KeCo, MsEv, MsCo, ToEv, ToCo, PeEv, PeCo>,
+    pub keyboard: Vec<(FilteredBindings<'a, Mo, KeEv>, KeCo)>,
+    pub mouse: Vec<(FilteredBindings<'a, Mo, MsEv>, MsCo)>,
+    pub touch: Vec<TouchBindings<'a, Mo, ToEv, ToCo>>,
+    pub pen: Vec<(FilteredBindings<'a, Mo, PeEv>, PenCoords<PeCo>)>,
+    pub released_keyboard: Vec<KeSw>,
+    pub released_mouse: Vec<MsSw>,
+    pub released_touch: Vec<ToSw>,
+    pub released_pen: Vec<PeSw>,
+    pub released_modifiers: Vec<Mo>,
 }
 
 /*
@@ -586,10 +1981,10 @@ fn test1() {
     let global_state = global_state.with_state(state);
 
     assert_eq!(global_state.modifiers, 11);
-    assert_eq!(global_state.keyboard_coords_state, (2, 3, 1));
-    assert_eq!(global_state.keyboard_timed_state, true);
-    assert_eq!(global_state.keyboard_long_press_scheduler, "23");
-    assert_eq!(global_state.keyboard_pointer_state, (2, 1));
+    assert_eq!(global_state.keyboard.coords_state, (2, 3, 1));
+    assert_eq!(global_state.keyboard.timed_state, true);
+    assert_eq!(global_state.keyboard.long_press_scheduler, "23");
+    assert_eq!(global_state.keyboard.pointer_state, (2, 1));
 }
 */
 /*
@@ -605,3 +2000,54 @@ fn test2() {
     };
     global_state.with_field(state.modifiers);
 }*/
+
+// A latch applies to the next pressed switch that is not a modifier itself.
+fn use_latches<Sw, Mo>(
+    modifiers: &mut Modifiers<Mo>,
+    switch: Sw,
+    mapping_modifiers: &MappingModifiersCache<Mo>,
+) where
+    Mo: Clone + Eq + From<Sw> + Hash + Ord,
+{
+    if !mapping_modifiers.switches().contains(&Mo::from(switch)) {
+        modifiers.use_latches();
+    }
+}
+
+// Latches last until the release of the switch that used them, so that its
+// release, click and click exact bindings see them too.
+fn consume_latches<Sw, Mo>(
+    modifiers: &mut Modifiers<Mo>,
+    switch: Sw,
+    mapping_modifiers: &MappingModifiersCache<Mo>,
+) where
+    Mo: Clone + Eq + From<Sw> + Hash + Ord,
+{
+    if !mapping_modifiers.switches().contains(&Mo::from(switch)) {
+        modifiers.consume_latches();
+    }
+}
+
+fn with_context<'a, Mo, Ev, Da>(
+    bindings: Option<(FilteredBindings<'a, Mo, Ev>, Da)>,
+    context: &ContextStack,
+) -> Option<(FilteredBindings<'a, Mo, Ev>, Da)>
+where
+    Mo: Eq + Hash,
+{
+    let (bindings, data) = bindings?;
+    Some((bindings.filter_by_context(context)?, data))
+}
+
+fn with_context_all<'a, Mo, Ev, Da>(
+    bindings: Vec<(FilteredBindings<'a, Mo, Ev>, Da)>,
+    context: &ContextStack,
+) -> Vec<(FilteredBindings<'a, Mo, Ev>, Da)>
+where
+    Mo: Eq + Hash,
+{
+    bindings
+        .into_iter()
+        .filter_map(|bindings| with_context(Some(bindings), context))
+        .collect()
+}
@@ -0,0 +1,26 @@
+use crate::{ContextExpr, ContextLayer, ContextStack};
+
+pub trait HitTester<Co> {
+    fn hit_test(&self, coords: &Co, target: &ContextExpr) -> bool;
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct NoHitTester;
+
+impl<Co> HitTester<Co> for NoHitTester {
+    // Nothing lies under the pointer, so targeted bindings never match.
+    fn hit_test(&self, _: &Co, _: &ContextExpr) -> bool {
+        false
+    }
+}
+
+impl<Co, F> HitTester<Co> for F
+where
+    F: Fn(&Co) -> ContextLayer,
+{
+    fn hit_test(&self, coords: &Co, target: &ContextExpr) -> bool {
+        let mut context = ContextStack::new();
+        context.push(self(coords));
+        target.eval(&context)
+    }
+}
@@ -0,0 +1,520 @@
+use core::fmt;
+use core::hash::Hash;
+use core::marker::PhantomData;
+use core::str::FromStr;
+use std::sync::Arc;
+
+use input_core::{
+    Modifiers, NumPossibleClicks, PointerChangeEventData, PointerMoveEventData,
+    PointerMoveEventKind, TimedClickExactEventKind, TimedEventData, TimedLongPressEventKind,
+    TimedReleaseEventKind,
+};
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    Binding, BindingPropagation, ContextExpr, ContextExprParseError, CoordsBinding,
+    DeviceMappingCache, GlobalMapping, GlobalMappingCache, Mapping, MappingModifiersCache,
+    SequenceBinding, SequenceStep, SwitchBinding,
+};
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct KeymapEntry {
+    #[serde(default)]
+    pub device: KeymapDevice,
+    pub key: String,
+    pub command: String,
+    #[serde(default)]
+    pub when: Option<String>,
+    #[serde(default)]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub on: KeymapTrigger,
+    #[serde(default = "default_clicks")]
+    pub clicks: NumPossibleClicks,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub passthrough: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeymapDevice {
+    #[default]
+    Keyboard,
+    Mouse,
+    Touch,
+    Pen,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeymapTrigger {
+    #[default]
+    Press,
+    Release,
+    Click,
+    LongClick,
+    LongPress,
+    ClickExact,
+    LongClickExact,
+    Repeat,
+    DragStart,
+    DragMove,
+    DragEnd,
+    DragCancel,
+    Fling,
+    Kinetic,
+    // The key names the axis, e.g. `Ctrl+Wheel`.
+    Axis,
+}
+
+pub trait KeymapSection: Default {
+    /// # Errors
+    ///
+    /// Returns a [`KeymapEntryError`] if the entry is invalid or already bound.
+    fn insert_entry(&mut self, entry: KeymapEntry) -> Result<(), KeymapEntryError>;
+}
+
+const fn default_clicks() -> NumPossibleClicks {
+    1
+}
+
+impl KeymapEntry {
+    /// # Errors
+    ///
+    /// Returns a [`KeymapEntryError`] if a key, modifier, command, when clause or
+    /// target does not parse, or if a key sequence is bound on anything but press.
+    pub fn to_binding<Sw, Tr, Mo, Ev>(&self) -> Result<Binding<Sw, Tr, Mo, Ev>, KeymapEntryError>
+    where
+        Sw: FromStr,
+        Tr: FromStr,
+        Mo: FromStr + Ord,
+        Ev: FromStr,
+    {
+        let clicks = self.clicks;
+        let binding = match self.on {
+            KeymapTrigger::Press if self.key.split_whitespace().nth(1).is_some() => {
+                Binding::Sequence(self.to_sequence_binding()?)
+            }
+            KeymapTrigger::Press => Binding::Press(self.to_switch_binding((), ())?),
+            KeymapTrigger::Release => Binding::Release(self.to_switch_binding(None, None)?),
+            KeymapTrigger::Click => Binding::Release(self.to_switch_binding(
+                Some(TimedEventData::new(TimedReleaseEventKind::Click, clicks)),
+                None,
+            )?),
+            KeymapTrigger::LongClick => Binding::Release(self.to_switch_binding(
+                Some(TimedEventData::new(
+                    TimedReleaseEventKind::LongClick,
+                    clicks,
+                )),
+                None,
+            )?),
+            KeymapTrigger::DragEnd => Binding::Release(
+                self.to_switch_binding(None, Some(PointerChangeEventData::DragEnd))?,
+            ),
+            KeymapTrigger::LongPress => Binding::LongPress(self.to_switch_binding(
+                TimedEventData::new(TimedLongPressEventKind::LongPress, clicks),
+                (),
+            )?),
+            KeymapTrigger::ClickExact => Binding::ClickExact(self.to_switch_binding(
+                TimedEventData::new(TimedClickExactEventKind::ClickExact, clicks),
+                (),
+            )?),
+            KeymapTrigger::LongClickExact => Binding::ClickExact(self.to_switch_binding(
+                TimedEventData::new(TimedClickExactEventKind::LongClickExact, clicks),
+                (),
+            )?),
+            KeymapTrigger::Repeat => Binding::Repeat(self.to_switch_binding((), ())?),
+            KeymapTrigger::DragStart => self.to_pointer_binding(PointerMoveEventKind::DragStart)?,
+            KeymapTrigger::DragMove => self.to_pointer_binding(PointerMoveEventKind::DragMove)?,
+            KeymapTrigger::DragCancel => {
+                self.to_pointer_binding(PointerMoveEventKind::DragCancel)?
+            }
+            KeymapTrigger::Fling => self.to_pointer_binding(PointerMoveEventKind::Fling)?,
+            KeymapTrigger::Kinetic => self.to_pointer_binding(PointerMoveEventKind::Kinetic)?,
+            KeymapTrigger::Axis => {
+                let (axis, modifiers) = self.parse_key()?;
+                Binding::Axis(self.to_coords_binding(axis, modifiers)?)
+            }
+        };
+        Ok(binding)
+    }
+
+    fn to_sequence_binding<Sw, Mo, Ev>(
+        &self,
+    ) -> Result<SequenceBinding<Sw, Mo, Ev>, KeymapEntryError>
+    where
+        Sw: FromStr,
+        Mo: FromStr + Ord,
+        Ev: FromStr,
+    {
+        let mut chords = self
+            .key
+            .split_whitespace()
+            .map(parse_chord)
+            .collect::<Result<Vec<_>, _>>()?;
+        let (switch, modifiers) = chords
+            .pop()
+            .ok_or_else(|| KeymapEntryError::EmptyKey(self.key.clone()))?;
+        let event = self.parse_command()?;
+        let when = self.parse_when()?;
+        if self.parse_target()?.is_some() {
+            return Err(KeymapEntryError::SequenceTarget(self.key.clone()));
+        }
+        Ok(SequenceBinding {
+            prefix: chords
+                .into_iter()
+                .map(|(switch, modifiers)| SequenceStep::new(switch, modifiers))
+                .collect(),
+            switch,
+            modifiers,
+            when,
+            priority: self.priority,
+            propagation: self.propagation(),
+            event,
+        })
+    }
+
+    fn to_switch_binding<Sw, Mo, Td, Pd, Ev>(
+        &self,
+        timed_data: Td,
+        pointer_data: Pd,
+    ) -> Result<SwitchBinding<Sw, Mo, Td, Pd, Ev>, KeymapEntryError>
+    where
+        Sw: FromStr,
+        Mo: FromStr + Ord,
+        Ev: FromStr,
+    {
+        let (switch, modifiers) = self.parse_key()?;
+        let mut binding = SwitchBinding::new(
+            switch,
+            modifiers,
+            timed_data,
+            pointer_data,
+            self.parse_command()?,
+        )
+        .with_priority(self.priority)
+        .with_propagation(self.propagation());
+        if let Some(when) = self.parse_when()? {
+            binding = binding.with_when(when);
+        }
+        if let Some(target) = self.parse_target()? {
+            binding = binding.with_target(target);
+        }
+        Ok(binding)
+    }
+
+    fn to_pointer_binding<Sw, Tr, Mo, Ev>(
+        &self,
+        kind: PointerMoveEventKind,
+    ) -> Result<Binding<Sw, Tr, Mo, Ev>, KeymapEntryError>
+    where
+        Sw: FromStr,
+        Mo: FromStr + Ord,
+        Ev: FromStr,
+    {
+        let (switch, modifiers) = self.parse_key()?;
+        let pointer_data = PointerMoveEventData { switch, kind };
+        Ok(Binding::Coords(
+            self.to_coords_binding(pointer_data, modifiers)?,
+        ))
+    }
+
+    fn to_coords_binding<Pd, Mo, Ev>(
+        &self,
+        pointer_data: Pd,
+        modifiers: Modifiers<Mo>,
+    ) -> Result<CoordsBinding<Pd, Mo, Ev>, KeymapEntryError>
+    where
+        Ev: FromStr,
+    {
+        let mut binding = CoordsBinding::new(pointer_data, modifiers, self.parse_command()?)
+            .with_priority(self.priority)
+            .with_propagation(self.propagation());
+        if let Some(when) = self.parse_when()? {
+            binding = binding.with_when(when);
+        }
+        if let Some(target) = self.parse_target()? {
+            binding = binding.with_target(target);
+        }
+        Ok(binding)
+    }
+
+    // Only press bindings take a sequence of chords.
+    fn parse_key<Sw, Mo>(&self) -> Result<(Sw, Modifiers<Mo>), KeymapEntryError>
+    where
+        Sw: FromStr,
+        Mo: FromStr + Ord,
+    {
+        let chords: Vec<_> = self.key.split_whitespace().collect();
+        match chords.as_slice() {
+            [chord] => parse_chord(chord),
+            [] => Err(KeymapEntryError::EmptyKey(self.key.clone())),
+            _ => Err(KeymapEntryError::SequenceTrigger(self.on)),
+        }
+    }
+
+    fn parse_command<Ev>(&self) -> Result<Ev, KeymapEntryError>
+    where
+        Ev: FromStr,
+    {
+        self.command
+            .parse()
+            .map_err(|_| KeymapEntryError::UnknownCommand(self.command.clone()))
+    }
+
+    fn parse_when(&self) -> Result<Option<ContextExpr>, KeymapEntryError> {
+        self.when
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(KeymapEntryError::InvalidWhen)
+    }
+
+    fn parse_target(&self) -> Result<Option<ContextExpr>, KeymapEntryError> {
+        self.target
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(KeymapEntryError::InvalidTarget)
+    }
+
+    const fn propagation(&self) -> BindingPropagation {
+        if self.passthrough {
+            BindingPropagation::Passthrough
+        } else {
+            BindingPropagation::Consume
+        }
+    }
+}
+
+fn parse_chord<Sw, Mo>(chord: &str) -> Result<(Sw, Modifiers<Mo>), KeymapEntryError>
+where
+    Sw: FromStr,
+    Mo: FromStr + Ord,
+{
+    let mut names: Vec<_> = chord.split('+').collect();
+    let switch = names
+        .pop()
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| KeymapEntryError::EmptyKey(chord.to_owned()))?;
+    let switch = switch
+        .parse()
+        .map_err(|_| KeymapEntryError::UnknownKey(switch.to_owned()))?;
+    let modifiers = names
+        .into_iter()
+        .map(|name| {
+            name.parse()
+                .map_err(|_| KeymapEntryError::UnknownModifier(name.to_owned()))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((switch, Modifiers::from(Arc::new(modifiers))))
+}
+
+impl<Sw, Tr, Mo, Ev> KeymapSection for Mapping<Sw, Tr, Mo, Ev>
+where
+    Sw: Eq + FromStr + Hash,
+    Tr: Eq + FromStr + Hash,
+    Mo: Eq + FromStr + Hash + Ord,
+    Ev: Eq + FromStr + Hash,
+{
+    fn insert_entry(&mut self, entry: KeymapEntry) -> Result<(), KeymapEntryError> {
+        let binding = entry.to_binding()?;
+        if self.bindings.insert(binding) {
+            Ok(())
+        } else {
+            Err(KeymapEntryError::Duplicate(entry.key))
+        }
+    }
+}
+
+impl<Ke, Ms, To, Pe> GlobalMapping<Ke, Ms, To, Pe>
+where
+    Ke: KeymapSection,
+    Ms: KeymapSection,
+    To: KeymapSection,
+    Pe: KeymapSection,
+{
+    /// # Errors
+    ///
+    /// Returns a [`KeymapError`] at the first invalid entry.
+    pub fn from_keymap(keymap: &str) -> Result<Self, KeymapError> {
+        Ok(serde_json::from_str(keymap)?)
+    }
+}
+
+impl<'de, Ke, Ms, To, Pe> Deserialize<'de> for GlobalMapping<Ke, Ms, To, Pe>
+where
+    Ke: KeymapSection,
+    Ms: KeymapSection,
+    To: KeymapSection,
+    Pe: KeymapSection,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(KeymapVisitor(PhantomData))
+    }
+}
+
+struct KeymapVisitor<Ke, Ms, To, Pe>(PhantomData<(Ke, Ms, To, Pe)>);
+
+impl<'de, Ke, Ms, To, Pe> Visitor<'de> for KeymapVisitor<Ke, Ms, To, Pe>
+where
+    Ke: KeymapSection,
+    Ms: KeymapSection,
+    To: KeymapSection,
+    Pe: KeymapSection,
+{
+    type Value = GlobalMapping<Ke, Ms, To, Pe>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a list of keymap entries")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut mapping = GlobalMapping {
+            keyboard: Ke::default(),
+            mouse: Ms::default(),
+            touch: To::default(),
+            pen: Pe::default(),
+        };
+        while seq.next_element_seed(KeymapEntrySeed(&mut mapping))? == Some(()) {}
+        Ok(mapping)
+    }
+}
+
+struct KeymapEntrySeed<'a, Ke, Ms, To, Pe>(&'a mut GlobalMapping<Ke, Ms, To, Pe>);
+
+impl<'de, Ke, Ms, To, Pe> DeserializeSeed<'de> for KeymapEntrySeed<'_, Ke, Ms, To, Pe>
+where
+    Ke: KeymapSection,
+    Ms: KeymapSection,
+    To: KeymapSection,
+    Pe: KeymapSection,
+{
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, Ke, Ms, To, Pe> Visitor<'de> for KeymapEntrySeed<'_, Ke, Ms, To, Pe>
+where
+    Ke: KeymapSection,
+    Ms: KeymapSection,
+    To: KeymapSection,
+    Pe: KeymapSection,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a keymap entry")
+    }
+
+    // The entry is converted inside `visit_map` so that errors point at the entry.
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let entry = KeymapEntry::deserialize(MapAccessDeserializer::new(map))?;
+        let result = match entry.device {
+            KeymapDevice::Keyboard => self.0.keyboard.insert_entry(entry),
+            KeymapDevice::Mouse => self.0.mouse.insert_entry(entry),
+            KeymapDevice::Touch => self.0.touch.insert_entry(entry),
+            KeymapDevice::Pen => self.0.pen.insert_entry(entry),
+        };
+        result.map_err(de::Error::custom)
+    }
+}
+
+impl<Mo, KeSw, KeTr, KeEv, MsSw, MsTr, MsEv, ToSw, ToTr, ToEv, PeSw, PeTr, PeEv>
+    GlobalMappingCache<
+        DeviceMappingCache<KeSw, KeTr, Mo, KeEv>,
+        DeviceMappingCache<MsSw, MsTr, Mo, MsEv>,
+        DeviceMappingCache<ToSw, ToTr, Mo, ToEv>,
+        DeviceMappingCache<PeSw, PeTr, Mo, PeEv>,
+        MappingModifiersCache<Mo>,
+    >
+where
+    Mo: Clone + Eq + FromStr + Hash + Ord,
+    KeSw: Clone + Eq + FromStr + Hash,
+    MsSw: Clone + Eq + FromStr + Hash,
+    ToSw: Clone + Eq + FromStr + Hash,
+    PeSw: Clone + Eq + FromStr + Hash,
+    KeTr: Clone + Eq + FromStr + Hash,
+    MsTr: Clone + Eq + FromStr + Hash,
+    ToTr: Clone + Eq + FromStr + Hash,
+    PeTr: Clone + Eq + FromStr + Hash,
+    KeEv: Clone + Eq + FromStr + Hash,
+    MsEv: Clone + Eq + FromStr + Hash,
+    ToEv: Clone + Eq + FromStr + Hash,
+    PeEv: Clone + Eq + FromStr + Hash,
+{
+    /// # Errors
+    ///
+    /// Returns a [`KeymapError`] at the first invalid entry.
+    pub fn from_keymap(keymap: &str) -> Result<Self, KeymapError> {
+        Ok(Self::from_mapping(GlobalMapping::from_keymap(keymap)?))
+    }
+
+    /// # Errors
+    ///
+    /// Returns a [`KeymapError`] at the first invalid entry and keeps the
+    /// current mapping.
+    pub fn reload(&mut self, keymap: &str) -> Result<(), KeymapError> {
+        *self = Self::from_keymap(keymap)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error(transparent)]
+pub struct KeymapError(#[from] serde_json::Error);
+
+impl KeymapError {
+    #[must_use]
+    pub fn line(&self) -> usize {
+        self.0.line()
+    }
+
+    #[must_use]
+    pub fn column(&self) -> usize {
+        self.0.column()
+    }
+}
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum KeymapEntryError {
+    #[error("Empty key in {0:?}")]
+    EmptyKey(String),
+    #[error("Unknown key name {0:?}")]
+    UnknownKey(String),
+    #[error("Unknown modifier name {0:?}")]
+    UnknownModifier(String),
+    #[error("Unknown command {0:?}")]
+    UnknownCommand(String),
+    #[error("Invalid when clause: {0}")]
+    InvalidWhen(ContextExprParseError),
+    #[error("Invalid target: {0}")]
+    InvalidTarget(ContextExprParseError),
+    #[error("Key sequences can only be bound on press, not {0:?}")]
+    SequenceTrigger(KeymapTrigger),
+    #[error("Key sequences cannot have a target, in {0:?}")]
+    SequenceTarget(String),
+    #[error("Duplicate binding for key {0:?}")]
+    Duplicate(String),
+}
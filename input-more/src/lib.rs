@@ -23,26 +23,46 @@
 )]
 
 mod binding;
+mod build_report;
+mod coalesce;
+mod context;
 mod device_state;
 mod event;
 mod global_mapping;
 mod global_mapping_cache;
 mod global_state;
+mod hit_test;
+mod keymap;
 mod mapping;
+mod mapping_analyzer;
 mod mapping_cache;
 mod mapping_modifiers_cache;
+mod recovery;
+mod replay;
+mod sequence_mapping_cache;
+mod snapshot;
 mod switch_mapping_cache;
 mod unwrap_or;
 
 pub use binding::*;
+pub use build_report::*;
+pub use coalesce::*;
+pub use context::*;
 pub use device_state::*;
 pub use event::*;
 pub use global_mapping::*;
 pub use global_mapping_cache::*;
 pub use global_state::*;
+pub use hit_test::*;
+pub use keymap::*;
 pub use mapping::*;
+pub use mapping_analyzer::*;
 pub use mapping_cache::*;
 pub use mapping_modifiers_cache::*;
+pub use recovery::*;
+pub use replay::*;
+pub use sequence_mapping_cache::*;
+pub use snapshot::*;
 pub use switch_mapping_cache::*;
 pub use unwrap_or::*;
 
@@ -53,4 +73,3 @@ pub use input_core;
 //   how to create binding for any relase event but not only for click | dblclick | longpress
 //   tripleclick => emit dblclick and singleclick is we do not handle triple click
 //   keyboard shortcut "create node at mouse position", how do we obtain coords
-//   corrent move move thresholds
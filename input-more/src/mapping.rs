@@ -1,15 +1,63 @@
+use core::hash::Hash;
 use std::collections::HashSet;
 
+use input_core::ModifierAliases;
+
 use crate::Binding;
 
 #[derive(Clone, Debug)]
 pub struct Mapping<Sw, Tr, Mo, Ev> {
     pub bindings: HashSet<Binding<Sw, Tr, Mo, Ev>>,
+    pub drag_cancel_switches: HashSet<Sw>,
+    pub modifier_aliases: ModifierAliases<Mo>,
+    pub lock_modifiers: HashSet<Mo>,
+    pub latch_modifiers: HashSet<Mo>,
 }
 
 impl<Sw, Tr, Mo, Ev> Mapping<Sw, Tr, Mo, Ev> {
     pub fn new(bindings: HashSet<Binding<Sw, Tr, Mo, Ev>>) -> Self {
-        Self { bindings }
+        Self {
+            bindings,
+            drag_cancel_switches: HashSet::new(),
+            modifier_aliases: ModifierAliases::new(),
+            lock_modifiers: HashSet::new(),
+            latch_modifiers: HashSet::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_drag_cancel_switches(mut self, switches: impl IntoIterator<Item = Sw>) -> Self
+    where
+        Sw: Eq + Hash,
+    {
+        self.drag_cancel_switches.extend(switches);
+        self
+    }
+
+    #[must_use]
+    pub fn with_modifier_aliases(mut self, aliases: ModifierAliases<Mo>) -> Self {
+        self.modifier_aliases = aliases;
+        self
+    }
+
+    // Locks toggle on press and are ignored by bindings that do not mention them.
+    #[must_use]
+    pub fn with_lock_modifiers(mut self, modifiers: impl IntoIterator<Item = Mo>) -> Self
+    where
+        Mo: Eq + Hash,
+    {
+        self.lock_modifiers.extend(modifiers);
+        self
+    }
+
+    // Latches released without use stay pressed until the next switch is pressed.
+    #[must_use]
+    pub fn with_latch_modifiers(mut self, modifiers: impl IntoIterator<Item = Mo>) -> Self
+    where
+        Mo: Eq + Hash,
+    {
+        self.latch_modifiers.extend(modifiers);
+        self
     }
 
     pub fn bindings(&self) -> &HashSet<Binding<Sw, Tr, Mo, Ev>> {
@@ -19,12 +67,36 @@ impl<Sw, Tr, Mo, Ev> Mapping<Sw, Tr, Mo, Ev> {
     pub fn into_bindings(self) -> HashSet<Binding<Sw, Tr, Mo, Ev>> {
         self.bindings
     }
+
+    #[must_use]
+    pub const fn drag_cancel_switches(&self) -> &HashSet<Sw> {
+        &self.drag_cancel_switches
+    }
+
+    #[must_use]
+    pub const fn modifier_aliases(&self) -> &ModifierAliases<Mo> {
+        &self.modifier_aliases
+    }
+
+    #[must_use]
+    pub const fn lock_modifiers(&self) -> &HashSet<Mo> {
+        &self.lock_modifiers
+    }
+
+    #[must_use]
+    pub const fn latch_modifiers(&self) -> &HashSet<Mo> {
+        &self.latch_modifiers
+    }
 }
 
 impl<Sw, Tr, Mo, Ev> Default for Mapping<Sw, Tr, Mo, Ev> {
     fn default() -> Self {
         Self {
             bindings: HashSet::default(),
+            drag_cancel_switches: HashSet::default(),
+            modifier_aliases: ModifierAliases::default(),
+            lock_modifiers: HashSet::default(),
+            latch_modifiers: HashSet::default(),
         }
     }
 }
@@ -0,0 +1,256 @@
+use core::hash::Hash;
+use std::sync::Arc;
+
+use input_core::{
+    GestureEventKind, Modifiers, PointerChangeEventData, PointerMoveEventData,
+    TimedClickExactEventData, TimedClickExactEventKind, TimedLongPressEventData,
+    TimedReleaseEventData,
+};
+
+use crate::{Binding, BindingPropagation, Mapping, MappingModifiersCache, SequenceStep};
+
+#[derive(Clone, Debug)]
+pub struct MappingReport<'a, Sw, Tr, Mo, Ev> {
+    issues: Vec<MappingIssue<'a, Sw, Tr, Mo, Ev>>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MappingIssue<'a, Sw, Tr, Mo, Ev> {
+    Duplicate {
+        binding: &'a Binding<Sw, Tr, Mo, Ev>,
+        other: &'a Binding<Sw, Tr, Mo, Ev>,
+    },
+    Shadowed {
+        binding: &'a Binding<Sw, Tr, Mo, Ev>,
+        by: &'a Binding<Sw, Tr, Mo, Ev>,
+    },
+    Ambiguous {
+        binding: &'a Binding<Sw, Tr, Mo, Ev>,
+        other: &'a Binding<Sw, Tr, Mo, Ev>,
+    },
+    /// A `LongClickExact` binding whose switch has no `LongPress` binding under its modifiers.
+    ///
+    /// A switch only becomes long pressed when a `LongPress` binding matches it, so a
+    /// `LongPress` can never make a binding unreachable: a `ClickExact` of the same switch
+    /// still fires whenever it is released before the long press deadline.
+    ClickExactUnreachable {
+        binding: &'a Binding<Sw, Tr, Mo, Ev>,
+    },
+    ModifierAsSwitch {
+        binding: &'a Binding<Sw, Tr, Mo, Ev>,
+        switch: &'a Sw,
+    },
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum BindingKey<'a, Sw, Tr, Mo> {
+    Press(&'a Sw),
+    Release(
+        &'a Sw,
+        &'a Option<TimedReleaseEventData>,
+        &'a Option<PointerChangeEventData>,
+    ),
+    LongPress(&'a Sw, &'a TimedLongPressEventData),
+    ClickExact(&'a Sw, &'a TimedClickExactEventData),
+    Repeat(&'a Sw),
+    Trigger(&'a Tr),
+    Coords(&'a PointerMoveEventData<Sw>),
+    Gesture(&'a GestureEventKind),
+    Axis(&'a Tr),
+    Sequence(&'a [SequenceStep<Sw, Mo>], &'a Sw),
+}
+
+impl<Sw, Tr, Mo, Ev> Mapping<Sw, Tr, Mo, Ev> {
+    #[must_use]
+    pub fn analyze(
+        &self,
+        modifiers: &MappingModifiersCache<Mo>,
+    ) -> MappingReport<'_, Sw, Tr, Mo, Ev>
+    where
+        Sw: Clone + Ord,
+        Tr: Ord,
+        Mo: Clone + Eq + From<Sw> + Hash + Ord,
+        Ev: Ord,
+    {
+        let mut bindings: Vec<_> = self.bindings.iter().collect();
+        bindings.sort();
+
+        let mut issues = Vec::new();
+        for (j, &binding) in bindings.iter().enumerate() {
+            let key = BindingKey::from_binding(binding);
+            for &other in &bindings[j + 1..] {
+                if key != BindingKey::from_binding(other) {
+                    continue;
+                }
+                let is_exact = matches!(key, BindingKey::Sequence(..));
+                if binding.modifiers() == other.modifiers()
+                    && binding.when() == other.when()
+                    && binding.target() == other.target()
+                    && binding.priority() == other.priority()
+                {
+                    issues.push(MappingIssue::Duplicate { binding, other });
+                } else if is_shadowed_by(binding, other, is_exact) {
+                    issues.push(MappingIssue::Shadowed { binding, by: other });
+                } else if is_shadowed_by(other, binding, is_exact) {
+                    issues.push(MappingIssue::Shadowed {
+                        binding: other,
+                        by: binding,
+                    });
+                } else if !is_exact && is_ambiguous(binding, other, &bindings) {
+                    issues.push(MappingIssue::Ambiguous { binding, other });
+                }
+            }
+
+            if let Binding::ClickExact(click_exact) = binding {
+                if click_exact.timed_data.kind == TimedClickExactEventKind::LongClickExact {
+                    let is_reachable = bindings.iter().any(|&other| match other {
+                        Binding::LongPress(long_press) => {
+                            long_press.switch == click_exact.switch
+                                && is_subset(&long_press.modifiers, &click_exact.modifiers)
+                        }
+                        _ => false,
+                    });
+                    if !is_reachable {
+                        issues.push(MappingIssue::ClickExactUnreachable { binding });
+                    }
+                }
+            }
+
+            let mut switches: Vec<_> = binding_switches(binding).collect();
+            switches.dedup();
+            for switch in switches {
+                if modifiers.switches().contains(&Mo::from(switch.clone())) {
+                    issues.push(MappingIssue::ModifierAsSwitch { binding, switch });
+                }
+            }
+        }
+
+        MappingReport { issues }
+    }
+}
+
+impl<'a, Sw, Tr, Mo, Ev> MappingReport<'a, Sw, Tr, Mo, Ev> {
+    #[must_use]
+    pub fn issues(&self) -> &[MappingIssue<'a, Sw, Tr, Mo, Ev>] {
+        &self.issues
+    }
+
+    #[must_use]
+    pub fn into_issues(self) -> Vec<MappingIssue<'a, Sw, Tr, Mo, Ev>> {
+        self.issues
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl<'a, Sw, Tr, Mo, Ev> MappingIssue<'a, Sw, Tr, Mo, Ev> {
+    #[must_use]
+    pub const fn binding(&self) -> &'a Binding<Sw, Tr, Mo, Ev> {
+        match *self {
+            Self::Duplicate { binding, .. }
+            | Self::Shadowed { binding, .. }
+            | Self::Ambiguous { binding, .. }
+            | Self::ClickExactUnreachable { binding }
+            | Self::ModifierAsSwitch { binding, .. } => binding,
+        }
+    }
+}
+
+impl<'a, Sw, Tr, Mo> BindingKey<'a, Sw, Tr, Mo> {
+    fn from_binding<Ev>(binding: &'a Binding<Sw, Tr, Mo, Ev>) -> Self {
+        match binding {
+            Binding::Press(binding) => Self::Press(&binding.switch),
+            Binding::Release(binding) => {
+                Self::Release(&binding.switch, &binding.timed_data, &binding.pointer_data)
+            }
+            Binding::LongPress(binding) => Self::LongPress(&binding.switch, &binding.timed_data),
+            Binding::ClickExact(binding) => Self::ClickExact(&binding.switch, &binding.timed_data),
+            Binding::Repeat(binding) => Self::Repeat(&binding.switch),
+            Binding::Trigger(binding) => Self::Trigger(&binding.trigger),
+            Binding::Coords(binding) => Self::Coords(&binding.pointer_data),
+            Binding::Gesture(binding) => Self::Gesture(&binding.pointer_data),
+            Binding::Axis(binding) => Self::Axis(&binding.pointer_data),
+            Binding::Sequence(binding) => Self::Sequence(&binding.prefix, &binding.switch),
+        }
+    }
+}
+
+fn is_subset<Mo>(modifiers: &Modifiers<Mo>, other: &Modifiers<Mo>) -> bool
+where
+    Mo: Ord,
+{
+    modifiers.switches().is_subset(other.switches())
+}
+
+fn is_shadowed_by<Sw, Tr, Mo, Ev>(
+    binding: &Binding<Sw, Tr, Mo, Ev>,
+    by: &Binding<Sw, Tr, Mo, Ev>,
+    is_exact: bool,
+) -> bool
+where
+    Mo: Ord,
+{
+    let is_covered = if is_exact {
+        by.modifiers() == binding.modifiers()
+    } else {
+        is_subset(by.modifiers(), binding.modifiers())
+    };
+    is_covered
+        && by.priority() > binding.priority()
+        && by.propagation() == BindingPropagation::Consume
+        && (by.when().is_none() || by.when() == binding.when())
+        && (by.target().is_none() || by.target() == binding.target())
+}
+
+fn is_ambiguous<Sw, Tr, Mo, Ev>(
+    binding: &Binding<Sw, Tr, Mo, Ev>,
+    other: &Binding<Sw, Tr, Mo, Ev>,
+    bindings: &[&Binding<Sw, Tr, Mo, Ev>],
+) -> bool
+where
+    Sw: PartialEq,
+    Tr: PartialEq,
+    Mo: Clone + Ord,
+{
+    if binding.priority() != other.priority()
+        || binding.when() != other.when()
+        || binding.target() != other.target()
+        || is_subset(binding.modifiers(), other.modifiers())
+        || is_subset(other.modifiers(), binding.modifiers())
+    {
+        return false;
+    }
+    let key = BindingKey::from_binding(binding);
+    let union: Modifiers<Mo> = Modifiers::from(Arc::new(
+        binding
+            .modifiers()
+            .switches()
+            .union(other.modifiers().switches())
+            .cloned()
+            .collect(),
+    ));
+    !bindings.iter().any(|&resolver| {
+        BindingKey::from_binding(resolver) == key
+            && resolver.priority() == binding.priority()
+            && resolver.target() == binding.target()
+            && resolver.modifiers() == &union
+    })
+}
+
+fn binding_switches<Sw, Tr, Mo, Ev>(
+    binding: &Binding<Sw, Tr, Mo, Ev>,
+) -> impl Iterator<Item = &Sw> {
+    let (prefix, switch) = match binding {
+        Binding::Press(binding) | Binding::Repeat(binding) => (&[][..], Some(&binding.switch)),
+        Binding::Release(binding) => (&[][..], Some(&binding.switch)),
+        Binding::LongPress(binding) => (&[][..], Some(&binding.switch)),
+        Binding::ClickExact(binding) => (&[][..], Some(&binding.switch)),
+        Binding::Coords(binding) => (&[][..], Some(&binding.pointer_data.switch)),
+        Binding::Sequence(binding) => (binding.prefix.as_slice(), Some(&binding.switch)),
+        Binding::Trigger(_) | Binding::Gesture(_) | Binding::Axis(_) => (&[][..], None),
+    };
+    prefix.iter().map(|step| &step.switch).chain(switch)
+}
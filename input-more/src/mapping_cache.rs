@@ -1,24 +1,30 @@
 use core::hash::Hash;
 use core::marker::PhantomData;
+use std::collections::HashSet;
 
 use input_core::{
-    Modifiers, PointerChangeEventData, PointerMoveEventData, TimedClickExactEventData,
-    TimedLongPressEventData, TimedReleaseEventData,
+    GestureEventKind, ModifierAliases, Modifiers, PointerChangeEventData, PointerMoveEventData,
+    TimedClickExactEventData, TimedLongPressEventData, TimedReleaseEventData,
 };
 
 use crate::{
-    Binding, CoordsMappingCache, SwitchMappingByModifiers, SwitchMappingBySwitch,
-    SwitchMappingByTimed, SwitchMappingCache, TriggerMappingCache,
+    Binding, CoordsMappingCache, Mapping, SequenceMappingCache, SwitchMappingByModifiers,
+    SwitchMappingBySwitch, SwitchMappingByTimed, SwitchMappingCache, TriggerMappingCache,
 };
 
 #[derive(Clone, Debug)]
-pub struct MappingCache<Pr, Re, Lo, Cl, Tr, Co> {
+pub struct MappingCache<Pr, Re, Lo, Cl, Rp, Tr, Co, Ge, Ax, Sq, Dc> {
     pub press: Pr,
     pub release: Re,
     pub long_press: Lo,
     pub click_exact: Cl,
+    pub repeat: Rp,
     pub trigger: Tr,
     pub coords: Co,
+    pub gesture: Ge,
+    pub axis: Ax,
+    pub sequence: Sq,
+    pub drag_cancel: Dc,
 }
 
 pub type DeviceMappingCache<Sw, Tr, Mo, Ev> = MappingCache<
@@ -26,8 +32,13 @@ pub type DeviceMappingCache<Sw, Tr, Mo, Ev> = MappingCache<
     SwitchMappingCache<Sw, Mo, Option<TimedReleaseEventData>, Option<PointerChangeEventData>, Ev>,
     SwitchMappingCache<Sw, Mo, TimedLongPressEventData, (), Ev>,
     SwitchMappingCache<Sw, Mo, TimedClickExactEventData, (), Ev>,
+    SwitchMappingCache<Sw, Mo, (), (), Ev>,
     TriggerMappingCache<Tr, Mo, Ev>,
     CoordsMappingCache<PointerMoveEventData<Sw>, Mo, Ev>,
+    CoordsMappingCache<GestureEventKind, Mo, Ev>,
+    CoordsMappingCache<Tr, Mo, Ev>,
+    SequenceMappingCache<Sw, Mo, Ev>,
+    HashSet<Sw>,
 >;
 
 impl<Sw, Tr, Mo, Ev> DeviceMappingCache<Sw, Tr, Mo, Ev> {
@@ -42,16 +53,24 @@ impl<Sw, Tr, Mo, Ev> DeviceMappingCache<Sw, Tr, Mo, Ev> {
         let mut release = Vec::new();
         let mut long_press = Vec::new();
         let mut click_exact = Vec::new();
+        let mut repeat = Vec::new();
         let mut trigger = Vec::new();
         let mut coords = Vec::new();
+        let mut gesture = Vec::new();
+        let mut axis = Vec::new();
+        let mut sequence = Vec::new();
         for binding in mapping.into_iter() {
             match binding {
                 Binding::Press(binding) => press.push(binding.clone()),
                 Binding::Release(binding) => release.push(binding.clone()),
                 Binding::LongPress(binding) => long_press.push(binding.clone()),
                 Binding::ClickExact(binding) => click_exact.push(binding.clone()),
+                Binding::Repeat(binding) => repeat.push(binding.clone()),
                 Binding::Trigger(binding) => trigger.push(binding.clone()),
                 Binding::Coords(binding) => coords.push(binding.clone()),
+                Binding::Gesture(binding) => gesture.push(binding.clone()),
+                Binding::Axis(binding) => axis.push(binding.clone()),
+                Binding::Sequence(binding) => sequence.push(binding.clone()),
             }
         }
         Self {
@@ -59,20 +78,82 @@ impl<Sw, Tr, Mo, Ev> DeviceMappingCache<Sw, Tr, Mo, Ev> {
             release: SwitchMappingCache::from_bindings(release),
             long_press: SwitchMappingCache::from_bindings(long_press),
             click_exact: SwitchMappingCache::from_bindings(click_exact),
+            repeat: SwitchMappingCache::from_bindings(repeat),
             trigger: TriggerMappingCache::from_bindings(trigger),
             coords: CoordsMappingCache::from_bindings(coords),
+            gesture: CoordsMappingCache::from_bindings(gesture),
+            axis: CoordsMappingCache::from_bindings(axis),
+            sequence: SequenceMappingCache::from_bindings(sequence),
+            drag_cancel: HashSet::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn from_mapping(mapping: &Mapping<Sw, Tr, Mo, Ev>) -> Self
+    where
+        Sw: Clone + Eq + Hash,
+        Tr: Clone + Eq + Hash,
+        Mo: Clone + Eq + Hash + Ord,
+        Ev: Clone,
+    {
+        Self::from_mapping_with_aliases(mapping, mapping.modifier_aliases())
+    }
+
+    #[must_use]
+    pub fn from_mapping_with_aliases(
+        mapping: &Mapping<Sw, Tr, Mo, Ev>,
+        aliases: &ModifierAliases<Mo>,
+    ) -> Self
+    where
+        Sw: Clone + Eq + Hash,
+        Tr: Clone + Eq + Hash,
+        Mo: Clone + Eq + Hash + Ord,
+        Ev: Clone,
+    {
+        let bindings: Vec<_> = mapping
+            .bindings()
+            .iter()
+            .map(|binding| binding.with_modifier_aliases(aliases))
+            .collect();
+        Self {
+            drag_cancel: mapping.drag_cancel_switches().clone(),
+            ..Self::from_bindings(&bindings)
         }
     }
 }
 
-impl<Sw, Mo, TdPr, TdRe, TdLo, TdCl, PdPr, PdRe, PrLo, PrCl, Ev, TrCa, CoCa>
+impl<
+        Sw,
+        Mo,
+        TdPr,
+        TdRe,
+        TdLo,
+        TdCl,
+        PdPr,
+        PdRe,
+        PrLo,
+        PrCl,
+        Ev,
+        RpCa,
+        TrCa,
+        CoCa,
+        GeCa,
+        AxCa,
+        SqCa,
+        DcCa,
+    >
     MappingCache<
         SwitchMappingCache<Sw, Mo, TdPr, PdPr, Ev>,
         SwitchMappingCache<Sw, Mo, TdRe, PdRe, Ev>,
         SwitchMappingCache<Sw, Mo, TdLo, PrLo, Ev>,
         SwitchMappingCache<Sw, Mo, TdCl, PrCl, Ev>,
+        RpCa,
         TrCa,
         CoCa,
+        GeCa,
+        AxCa,
+        SqCa,
+        DcCa,
     >
 {
     pub fn filter_by_switch<'a>(
@@ -86,6 +167,11 @@ impl<Sw, Mo, TdPr, TdRe, TdLo, TdCl, PdPr, PdRe, PrLo, PrCl, Ev, TrCa, CoCa>
             Option<SwitchMappingBySwitch<'a, Mo, TdCl, PrCl, Ev>>,
             (),
             (),
+            (),
+            (),
+            (),
+            (),
+            (),
         >,
     >
     where
@@ -103,21 +189,50 @@ impl<Sw, Mo, TdPr, TdRe, TdLo, TdCl, PdPr, PdRe, PrLo, PrCl, Ev, TrCa, CoCa>
                 release,
                 long_press,
                 click_exact,
+                repeat: (),
                 trigger: (),
                 coords: (),
+                gesture: (),
+                axis: (),
+                sequence: (),
+                drag_cancel: (),
             }),
         }
     }
 }
 
-impl<'a, Mo, TdPr, TdRe, TdLo, TdCl, PdPr, PdRe, PrLo, PrCl, TrCa, CoCa, Ev>
+impl<
+        'a,
+        Mo,
+        TdPr,
+        TdRe,
+        TdLo,
+        TdCl,
+        PdPr,
+        PdRe,
+        PrLo,
+        PrCl,
+        RpCa,
+        TrCa,
+        CoCa,
+        GeCa,
+        AxCa,
+        SqCa,
+        DcCa,
+        Ev,
+    >
     MappingCache<
         Option<SwitchMappingBySwitch<'a, Mo, TdPr, PdPr, Ev>>,
         Option<SwitchMappingBySwitch<'a, Mo, TdRe, PdRe, Ev>>,
         Option<SwitchMappingBySwitch<'a, Mo, TdLo, PrLo, Ev>>,
         Option<SwitchMappingBySwitch<'a, Mo, TdCl, PrCl, Ev>>,
+        RpCa,
         TrCa,
         CoCa,
+        GeCa,
+        AxCa,
+        SqCa,
+        DcCa,
     >
 {
     pub fn filter_by_modifiers(
@@ -131,6 +246,11 @@ impl<'a, Mo, TdPr, TdRe, TdLo, TdCl, PdPr, PdRe, PrLo, PrCl, TrCa, CoCa, Ev>
             Option<SwitchMappingByModifiers<'a, Mo, TdCl, PrCl, Ev>>,
             (),
             (),
+            (),
+            (),
+            (),
+            (),
+            (),
         >,
     >
     where
@@ -159,8 +279,13 @@ impl<'a, Mo, TdPr, TdRe, TdLo, TdCl, PdPr, PdRe, PrLo, PrCl, TrCa, CoCa, Ev>
                 release,
                 long_press,
                 click_exact,
+                repeat: (),
                 trigger: (),
                 coords: (),
+                gesture: (),
+                axis: (),
+                sequence: (),
+                drag_cancel: (),
             }),
         }
     }
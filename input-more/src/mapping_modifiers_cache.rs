@@ -1,11 +1,16 @@
 use core::hash::Hash;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use input_core::{ModifierAliases, ModifierKind, Modifiers};
 
 use crate::Binding;
 
 #[derive(Clone, Debug)]
 pub struct MappingModifiersCache<Mo> {
     switches: HashSet<Mo>,
+    aliases: ModifierAliases<Mo>,
+    kinds: HashMap<Mo, ModifierKind>,
 }
 
 impl<Mo> MappingModifiersCache<Mo>
@@ -15,7 +20,52 @@ where
     pub fn from_switches(switches: impl IntoIterator<Item = Mo>) -> Self {
         Self {
             switches: switches.into_iter().collect(),
+            aliases: ModifierAliases::new(),
+            kinds: HashMap::new(),
+        }
+    }
+
+    // Switches of a used alias act as modifiers too.
+    #[must_use]
+    pub fn with_aliases(mut self, aliases: ModifierAliases<Mo>) -> Self
+    where
+        Mo: Ord,
+    {
+        for (alias, group) in aliases.aliases() {
+            if self.switches.contains(alias) {
+                self.switches.extend(group.iter().cloned());
+            }
         }
+        self.aliases = aliases;
+        self
+    }
+
+    // Lock and latch switches act as modifiers even if no binding uses them.
+    #[must_use]
+    pub fn with_kinds(mut self, kinds: impl IntoIterator<Item = (Mo, ModifierKind)>) -> Self {
+        for (switch, kind) in kinds {
+            let _ = self.switches.insert(switch.clone());
+            let _ = self.kinds.insert(switch, kind);
+        }
+        self
+    }
+
+    pub fn kind(&self, switch: &Mo) -> ModifierKind {
+        self.kinds.get(switch).copied().unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn without_locks(&self, modifiers: &Modifiers<Mo>) -> Modifiers<Mo>
+    where
+        Mo: Ord,
+    {
+        let switches = modifiers
+            .switches()
+            .iter()
+            .filter(|switch| self.kind(switch) != ModifierKind::Lock)
+            .cloned()
+            .collect();
+        Modifiers::from(Arc::new(switches))
     }
 
     /*pub fn from_bindings<'a, Sw, Tr, Ev>(
@@ -61,12 +111,19 @@ impl<Mo> MappingModifiersCache<Mo> {
     pub fn switches(&self) -> &HashSet<Mo> {
         &self.switches
     }
+
+    #[must_use]
+    pub const fn aliases(&self) -> &ModifierAliases<Mo> {
+        &self.aliases
+    }
 }
 
 impl<Mo> Default for MappingModifiersCache<Mo> {
     fn default() -> Self {
         Self {
             switches: HashSet::new(),
+            aliases: ModifierAliases::default(),
+            kinds: HashMap::new(),
         }
     }
 }
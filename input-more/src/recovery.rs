@@ -0,0 +1,123 @@
+use core::fmt::Debug;
+
+// What to do with host events that do not match the state, e.g. a press of
+// a pressed switch after the host dropped its release.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum RecoveryPolicy {
+    // Keeps the state as it is and records a diagnostic.
+    #[default]
+    Strict,
+    // Synthesises the missing release of a pressed switch and records a
+    // diagnostic. The missing press of a released switch is not synthesised,
+    // as its time and coords are lost and its bindings would fire late, so
+    // the release only resets the clicks and emits nothing.
+    Resync,
+    // Keeps the state as it is silently.
+    Ignore,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Diagnostic {
+    pub source: &'static str,
+    pub message: String,
+    pub is_resynced: bool,
+}
+
+// Diagnostics are kept until taken, up to `limit` of the latest ones, so
+// that a caller that never takes them does not grow the buffer forever.
+#[derive(Clone, Debug)]
+pub struct Recovery {
+    pub policy: RecoveryPolicy,
+    limit: usize,
+    diagnostics: Vec<Diagnostic>,
+    num_dropped: usize,
+}
+
+impl Recovery {
+    pub const DEFAULT_LIMIT: usize = 64;
+
+    #[must_use]
+    pub const fn new(policy: RecoveryPolicy) -> Self {
+        Self {
+            policy,
+            limit: Self::DEFAULT_LIMIT,
+            diagnostics: Vec::new(),
+            num_dropped: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self.drop_excess();
+        self
+    }
+
+    #[must_use]
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    // The number of diagnostics dropped since they were last taken.
+    #[must_use]
+    pub const fn num_dropped(&self) -> usize {
+        self.num_dropped
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        self.num_dropped = 0;
+        core::mem::take(&mut self.diagnostics)
+    }
+
+    // Returns whether the caller should resynchronise the state.
+    pub fn on_error(
+        &mut self,
+        source: &'static str,
+        error: &impl Debug,
+        event: &impl Debug,
+    ) -> bool {
+        let is_resynced = self.policy == RecoveryPolicy::Resync;
+        self.record(source, error, event, is_resynced);
+        is_resynced
+    }
+
+    // Errors that have nothing to resynchronise, e.g. stale timeouts.
+    pub fn on_internal_error(
+        &mut self,
+        source: &'static str,
+        error: &impl Debug,
+        event: &impl Debug,
+    ) {
+        self.record(source, error, event, false);
+    }
+
+    fn record(
+        &mut self,
+        source: &'static str,
+        error: &impl Debug,
+        event: &impl Debug,
+        is_resynced: bool,
+    ) {
+        if self.policy == RecoveryPolicy::Ignore {
+            return;
+        }
+        self.diagnostics.push(Diagnostic {
+            source,
+            message: format!("{error:?} for event: {event:?}"),
+            is_resynced,
+        });
+        self.drop_excess();
+    }
+
+    fn drop_excess(&mut self) {
+        let excess = self.diagnostics.len().saturating_sub(self.limit);
+        let _ = self.diagnostics.drain(..excess);
+        self.num_dropped += excess;
+    }
+}
+
+impl Default for Recovery {
+    fn default() -> Self {
+        Self::new(RecoveryPolicy::default())
+    }
+}
@@ -0,0 +1,517 @@
+use core::borrow::BorrowMut;
+use core::fmt::Debug;
+use core::hash::Hash;
+use core::ops::Add;
+use std::io::{self, Write};
+
+use input_core::{
+    AnalogState, AxisEventData, AxisState, ClickExactHandleRequest, Coords2d, CoordsState,
+    GestureState, KineticHandleRequest, LongPressHandleRequest, Modifiers, PenCoords, PenState,
+    PointerState, RepeatSchedulerState, RepeatState, SequenceSchedulerState, SequenceState,
+    TextInputEventData, TimeValue, TimedState,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    AnalogEvent, AxisEvent, CoalescedEvent, CoordsEvent, DeviceMappingCache, DeviceSchedulerState,
+    FilteredBindings, GlobalMappingCache, GlobalState, GlobalStateWithDragCancelResult, HitTester,
+    KeyboardLane, MappingModifiersCache, MouseLane, PenLane, SequenceStep, SwitchEvent,
+    SyntheticReleases, TextInputEvent, TouchBindings, TouchEvent, TouchLane, TriggerEvent,
+};
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RawEvent<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co> {
+    KeyboardPress(SwitchEvent<Ti, KeSw>),
+    KeyboardRelease(SwitchEvent<Ti, KeSw>),
+    KeyboardRepeat(SwitchEvent<Ti, KeSw>),
+    KeyboardTrigger(TriggerEvent<Ti, KeTr>),
+    KeyboardAnalog(AnalogEvent<Ti, KeTr>),
+    KeyboardCoords(CoordsEvent<Ti, Co>),
+    MousePress(SwitchEvent<Ti, MsSw>),
+    MouseRelease(SwitchEvent<Ti, MsSw>),
+    MouseTrigger(TriggerEvent<Ti, MsTr>),
+    MouseAxis(AxisEvent<Ti, MsTr, Co>),
+    MouseCoords(CoordsEvent<Ti, Co>),
+    TouchStart(TouchEvent<Ti, ToSw, Co>),
+    TouchMove(TouchEvent<Ti, ToSw, Co>),
+    TouchEnd(TouchEvent<Ti, ToSw, Co>),
+    Pen(CoordsEvent<Ti, PenCoords<Co>>),
+    TextInput(TextInputEvent<Ti>),
+    // Sent when the window loses focus, as the releases of held switches
+    // will not come.
+    FocusLost {
+        time: Ti,
+        #[serde(default)]
+        releases: SyntheticReleases,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ReplayedEvent<Ti, Ev> {
+    pub time: Ti,
+    pub event: ReplayedPayload<Ev>,
+}
+
+// Bindings carry the app events, while text input is passed through as is.
+// Axis bindings keep the deltas, as apps scroll by them.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayedPayload<Ev> {
+    Binding(Ev),
+    Axis { event: Ev, data: AxisEventData },
+    TextInput(TextInputEventData),
+}
+
+// Replays emit the same app events for every lane.
+type ReplayMappingCache<KeSw, KeTr, MsSw, MsTr, ToSw, ToTr, PeSw, PeTr, Mo, Ev> =
+    GlobalMappingCache<
+        DeviceMappingCache<KeSw, KeTr, Mo, Ev>,
+        DeviceMappingCache<MsSw, MsTr, Mo, Ev>,
+        DeviceMappingCache<ToSw, ToTr, Mo, Ev>,
+        DeviceMappingCache<PeSw, PeTr, Mo, Ev>,
+        MappingModifiersCache<Mo>,
+    >;
+
+#[derive(Debug)]
+pub struct InputRecorder<W> {
+    writer: W,
+    num_events: usize,
+}
+
+impl<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co> RawEvent<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co> {
+    pub const fn time(&self) -> &Ti {
+        match self {
+            Self::KeyboardPress(event)
+            | Self::KeyboardRelease(event)
+            | Self::KeyboardRepeat(event) => &event.time,
+            Self::MousePress(event) | Self::MouseRelease(event) => &event.time,
+            Self::KeyboardTrigger(event) => &event.time,
+            Self::KeyboardAnalog(event) => &event.time,
+            Self::MouseTrigger(event) => &event.time,
+            Self::MouseAxis(event) => &event.time,
+            Self::KeyboardCoords(event) | Self::MouseCoords(event) => &event.time,
+            Self::Pen(event) => &event.time,
+            Self::TextInput(event) => &event.time,
+            Self::FocusLost { time, .. } => time,
+            Self::TouchStart(event) | Self::TouchMove(event) | Self::TouchEnd(event) => &event.time,
+        }
+    }
+}
+
+impl<Ti, Ev> ReplayedEvent<Ti, Ev> {
+    pub const fn new(time: Ti, event: Ev) -> Self {
+        Self {
+            time,
+            event: ReplayedPayload::Binding(event),
+        }
+    }
+
+    pub const fn axis(time: Ti, event: Ev, data: AxisEventData) -> Self {
+        Self {
+            time,
+            event: ReplayedPayload::Axis { event, data },
+        }
+    }
+
+    pub const fn text_input(time: Ti, data: TextInputEventData) -> Self {
+        Self {
+            time,
+            event: ReplayedPayload::TextInput(data),
+        }
+    }
+}
+
+impl<Ev> ReplayedPayload<Ev> {
+    pub const fn event(&self) -> Option<&Ev> {
+        match self {
+            Self::Binding(event) | Self::Axis { event, .. } => Some(event),
+            Self::TextInput(_) => None,
+        }
+    }
+
+    pub fn into_event(self) -> Option<Ev> {
+        match self {
+            Self::Binding(event) | Self::Axis { event, .. } => Some(event),
+            Self::TextInput(_) => None,
+        }
+    }
+}
+
+impl<W> InputRecorder<W>
+where
+    W: Write,
+{
+    pub const fn new(writer: W) -> Self {
+        Self {
+            writer,
+            num_events: 0,
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Returns an [`InputLogError`] if the event cannot be serialised or written.
+    pub fn record<E>(&mut self, event: &E) -> Result<(), InputLogError>
+    where
+        E: Serialize,
+    {
+        self.num_events += 1;
+        serde_json::to_writer(&mut self.writer, event).map_err(|source| InputLogError::Json {
+            line: self.num_events,
+            source,
+        })?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub const fn num_events(&self) -> usize {
+        self.num_events
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// # Errors
+///
+/// Returns [`InputLogError::Json`] with the line of the first invalid entry.
+pub fn read_input_log<'de, E>(log: &'de str) -> Result<Vec<E>, InputLogError>
+where
+    E: Deserialize<'de>,
+{
+    log.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(j, line)| {
+            serde_json::from_str(line).map_err(|source| InputLogError::Json {
+                line: j + 1,
+                source,
+            })
+        })
+        .collect()
+}
+
+impl<Mo, Ti, Du, KeSw, MsSw, ToSw, PeSw, Co, AxMs, AnKe, PnPe, Ht>
+    GlobalState<
+        Modifiers<Mo>,
+        KeyboardLane<
+            CoordsState<Co>,
+            TimedState<KeSw, Du>,
+            DeviceSchedulerState<Ti, KeSw, Mo, Co, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, KeSw, Mo, Co, ClickExactHandleRequest>,
+            PointerState<KeSw, Co, Ti, Du>,
+            SequenceState<SequenceStep<KeSw, Mo>, Du>,
+            SequenceSchedulerState<Ti, ()>,
+            RepeatState<KeSw, Du>,
+            RepeatSchedulerState<Ti, KeSw>,
+            AnKe,
+        >,
+        MouseLane<
+            CoordsState<Co>,
+            TimedState<MsSw, Du>,
+            DeviceSchedulerState<Ti, MsSw, Mo, Co, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, MsSw, Mo, Co, ClickExactHandleRequest>,
+            PointerState<MsSw, Co, Ti, Du>,
+            AxMs,
+            DeviceSchedulerState<Ti, MsSw, Mo, Co, KineticHandleRequest>,
+        >,
+        TouchLane<
+            CoordsState<Co>,
+            TimedState<ToSw, Du>,
+            DeviceSchedulerState<Ti, ToSw, Mo, Co, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, ToSw, Mo, Co, ClickExactHandleRequest>,
+            PointerState<ToSw, Co, Ti, Du>,
+            GestureState<ToSw, Co>,
+            DeviceSchedulerState<Ti, ToSw, Mo, Co, KineticHandleRequest>,
+        >,
+        PenLane<
+            CoordsState<Co>,
+            TimedState<PeSw, Du>,
+            DeviceSchedulerState<Ti, PeSw, Mo, Co, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, PeSw, Mo, Co, ClickExactHandleRequest>,
+            PointerState<PeSw, Co, Ti, Du>,
+            PnPe,
+        >,
+        Ht,
+    >
+where
+    Ht: HitTester<Co>,
+{
+    pub fn replay<KeTr, MsTr, ToTr, PeTr, Ev>(
+        &mut self,
+        events: impl IntoIterator<Item = RawEvent<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co>>,
+        mapping: &ReplayMappingCache<KeSw, KeTr, MsSw, MsTr, ToSw, ToTr, PeSw, PeTr, Mo, Ev>,
+    ) -> Vec<ReplayedEvent<Ti, Ev>>
+    where
+        KeSw: Clone + Eq + Hash + Debug,
+        MsSw: Clone + Eq + Hash + Debug,
+        ToSw: Clone + Eq + Hash + Debug,
+        PeSw: Clone + Eq + Hash + Debug,
+        KeTr: Eq + Hash,
+        MsTr: Clone + Eq + Hash,
+        AxMs: BorrowMut<AxisState<MsTr>>,
+        AnKe: BorrowMut<AnalogState<KeTr, KeSw>>,
+        PnPe: BorrowMut<PenState<PeSw>>,
+        Mo: Clone + Eq + From<KeSw> + From<MsSw> + From<ToSw> + From<PeSw> + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + TimeValue + Debug,
+        Du: Clone + TimeValue,
+        Co: Clone + Coords2d,
+        Ev: Clone,
+    {
+        let mut replayed = Vec::new();
+        for event in events {
+            replayed.extend(self.with_timeouts_until(event.time(), mapping));
+            replayed.extend(self.with_raw_event(event, mapping));
+        }
+        self.stop_repeats();
+        while let Some(time) = self.next_scheduled().cloned() {
+            replayed.extend(self.with_timeouts_until(&time, mapping));
+        }
+        replayed
+    }
+
+    // Unlike `replay`, leaves the timeouts after the last event pending.
+    pub fn with_coalesced_events<KeTr, MsTr, ToTr, PeTr, Ev>(
+        &mut self,
+        events: impl IntoIterator<Item = CoalescedEvent<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co>>,
+        mapping: &ReplayMappingCache<KeSw, KeTr, MsSw, MsTr, ToSw, ToTr, PeSw, PeTr, Mo, Ev>,
+    ) -> Vec<ReplayedEvent<Ti, Ev>>
+    where
+        KeSw: Clone + Eq + Hash + Debug,
+        MsSw: Clone + Eq + Hash + Debug,
+        ToSw: Clone + Eq + Hash + Debug,
+        PeSw: Clone + Eq + Hash + Debug,
+        KeTr: Eq + Hash,
+        MsTr: Clone + Eq + Hash,
+        AxMs: BorrowMut<AxisState<MsTr>>,
+        AnKe: BorrowMut<AnalogState<KeTr, KeSw>>,
+        PnPe: BorrowMut<PenState<PeSw>>,
+        Mo: Clone + Eq + From<KeSw> + From<MsSw> + From<ToSw> + From<PeSw> + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + TimeValue + Debug,
+        Du: Clone + TimeValue,
+        Co: Clone + Coords2d,
+        Ev: Clone,
+    {
+        let mut replayed = Vec::new();
+        for event in events {
+            for event in event.into_raw() {
+                replayed.extend(self.with_timeouts_until(event.time(), mapping));
+                replayed.extend(self.with_raw_event(event, mapping));
+            }
+        }
+        replayed
+    }
+
+    pub fn with_timeouts_until<KeTr, MsTr, ToTr, PeTr, Ev>(
+        &mut self,
+        time: &Ti,
+        mapping: &ReplayMappingCache<KeSw, KeTr, MsSw, MsTr, ToSw, ToTr, PeSw, PeTr, Mo, Ev>,
+    ) -> Vec<ReplayedEvent<Ti, Ev>>
+    where
+        KeSw: Clone + Eq + Hash + Debug,
+        MsSw: Clone + Eq + Hash + Debug,
+        ToSw: Clone + Eq + Hash + Debug,
+        PeSw: Clone + Eq + Hash + Debug,
+        Mo: Clone + Eq + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + TimeValue + Debug,
+        Du: Clone + TimeValue,
+        Co: Clone,
+        Ev: Clone,
+    {
+        let mut replayed = Vec::new();
+        while let Some(deadline) = self.next_scheduled().cloned() {
+            if deadline > *time {
+                break;
+            }
+            let result = self.with_timeout(deadline.clone(), mapping);
+            let bindings = [
+                result.keyboard_long_press,
+                result.keyboard_click_exact,
+                result.keyboard_repeat,
+                result.keyboard_sequence,
+                result.mouse_long_press,
+                result.mouse_click_exact,
+                result.touch_long_press,
+                result.touch_click_exact,
+                result.pen_long_press,
+                result.pen_click_exact,
+            ];
+            let kinetic = [result.mouse_kinetic, result.touch_kinetic];
+            replayed.extend(build_replayed(
+                &deadline,
+                bindings
+                    .into_iter()
+                    .flatten()
+                    .map(|(bindings, _)| bindings)
+                    .chain(kinetic.into_iter().flatten().map(|(bindings, _)| bindings)),
+            ));
+        }
+        replayed
+    }
+
+    pub fn with_raw_event<KeTr, MsTr, ToTr, PeTr, Ev>(
+        &mut self,
+        event: RawEvent<Ti, KeSw, KeTr, MsSw, MsTr, ToSw, Co>,
+        mapping: &ReplayMappingCache<KeSw, KeTr, MsSw, MsTr, ToSw, ToTr, PeSw, PeTr, Mo, Ev>,
+    ) -> Vec<ReplayedEvent<Ti, Ev>>
+    where
+        KeSw: Clone + Eq + Hash + Debug,
+        MsSw: Clone + Eq + Hash + Debug,
+        ToSw: Clone + Eq + Hash + Debug,
+        PeSw: Clone + Eq + Hash + Debug,
+        KeTr: Eq + Hash,
+        MsTr: Clone + Eq + Hash,
+        AxMs: BorrowMut<AxisState<MsTr>>,
+        AnKe: BorrowMut<AnalogState<KeTr, KeSw>>,
+        PnPe: BorrowMut<PenState<PeSw>>,
+        Mo: Clone + Eq + From<KeSw> + From<MsSw> + From<ToSw> + From<PeSw> + Hash + Ord,
+        Ti: Clone + Ord + Add<Du, Output = Ti> + TimeValue + Debug,
+        Du: Clone + TimeValue,
+        Co: Clone + Coords2d,
+        Ev: Clone,
+    {
+        let time = event.time().clone();
+        let bindings = match event {
+            RawEvent::KeyboardPress(event) => {
+                let result = self.with_keyboard_press_event(event, mapping);
+                drag_cancel_bindings(result.drag_cancel)
+                    .into_iter()
+                    .chain(result.bindings.into_iter().map(|(bindings, _)| bindings))
+                    .collect()
+            }
+            RawEvent::KeyboardRelease(event) => {
+                device_bindings(self.with_keyboard_release_event(event, mapping).bindings)
+            }
+            RawEvent::KeyboardRepeat(event) => {
+                device_bindings(self.with_keyboard_repeat_event(&event, mapping).bindings)
+            }
+            RawEvent::KeyboardTrigger(event) => {
+                device_bindings(self.with_keyboard_trigger_event(event, mapping).bindings)
+            }
+            RawEvent::KeyboardAnalog(event) => {
+                device_bindings(self.with_keyboard_analog_event(event, mapping).bindings)
+            }
+            RawEvent::KeyboardCoords(event) => {
+                device_bindings(self.with_keyboard_coords_event(event, mapping).bindings)
+            }
+            RawEvent::MousePress(event) => {
+                let result = self.with_mouse_press_event(event, mapping);
+                drag_cancel_bindings(result.drag_cancel)
+                    .into_iter()
+                    .chain(result.bindings.map(|(bindings, _)| bindings))
+                    .collect()
+            }
+            RawEvent::MouseRelease(event) => {
+                device_bindings(self.with_mouse_release_event(event, mapping).bindings)
+            }
+            RawEvent::MouseTrigger(event) => {
+                device_bindings(self.with_mouse_trigger_event(event, mapping).bindings)
+            }
+            RawEvent::MouseAxis(event) => {
+                let result = self.with_mouse_axis_event(&event, mapping);
+                let Some((bindings, data)) = result.bindings else {
+                    return Vec::new();
+                };
+                return bindings
+                    .build(|event| Some(event.clone()))
+                    .into_iter()
+                    .map(|event| ReplayedEvent::axis(time.clone(), event, data))
+                    .collect();
+            }
+            RawEvent::MouseCoords(event) => {
+                device_bindings(self.with_mouse_coords_event(event, mapping).bindings)
+            }
+            RawEvent::TouchStart(event) => {
+                let result = self.with_touch_start_event(event, mapping);
+                let mut bindings = drag_cancel_bindings(result.drag_cancel);
+                bindings.extend(touch_bindings(result.bindings));
+                bindings
+            }
+            RawEvent::TouchMove(event) => {
+                let result = self.with_touch_move_event(event, mapping);
+                touch_bindings(result.bindings)
+            }
+            RawEvent::TouchEnd(event) => {
+                let result = self.with_touch_end_event(event, mapping);
+                touch_bindings(result.bindings)
+            }
+            RawEvent::Pen(event) => device_bindings(self.with_pen_event(&event, mapping).bindings),
+            RawEvent::TextInput(event) => {
+                let data = self.with_text_input_event(event).bindings;
+                return data
+                    .map(|data| ReplayedEvent::text_input(time, data))
+                    .into_iter()
+                    .collect();
+            }
+            RawEvent::FocusLost { time, releases } => {
+                let result = self.with_focus_lost_event(time, releases, mapping);
+                drag_cancel_bindings(result.drag_cancel)
+                    .into_iter()
+                    .chain(result.keyboard.into_iter().map(|(bindings, _)| bindings))
+                    .chain(result.mouse.into_iter().map(|(bindings, _)| bindings))
+                    .chain(result.touch.into_iter().flat_map(touch_bindings))
+                    .chain(result.pen.into_iter().map(|(bindings, _)| bindings))
+                    .collect()
+            }
+        };
+        build_replayed(&time, bindings)
+    }
+}
+
+fn device_bindings<'a, Mo, Ev, Co>(
+    bindings: impl IntoIterator<Item = (FilteredBindings<'a, Mo, Ev>, Co)>,
+) -> Vec<FilteredBindings<'a, Mo, Ev>> {
+    bindings.into_iter().map(|(bindings, _)| bindings).collect()
+}
+
+fn touch_bindings<Mo, Ev, Co>(
+    bindings: TouchBindings<'_, Mo, Ev, Co>,
+) -> Vec<FilteredBindings<'_, Mo, Ev>> {
+    bindings
+        .pointer
+        .map(|(bindings, _)| bindings)
+        .into_iter()
+        .chain(bindings.gestures.into_iter().map(|(bindings, _)| bindings))
+        .collect()
+}
+
+fn drag_cancel_bindings<Mo, Ev, Co>(
+    result: GlobalStateWithDragCancelResult<'_, Mo, Ev, Co, Ev, Co, Ev, Co, Ev, Co>,
+) -> Vec<FilteredBindings<'_, Mo, Ev>> {
+    [result.keyboard, result.mouse, result.touch, result.pen]
+        .into_iter()
+        .flatten()
+        .map(|(bindings, _)| bindings)
+        .collect()
+}
+
+fn build_replayed<'a, Ti, Mo, Ev>(
+    time: &Ti,
+    bindings: impl IntoIterator<Item = FilteredBindings<'a, Mo, Ev>>,
+) -> Vec<ReplayedEvent<Ti, Ev>>
+where
+    Ti: Clone,
+    Mo: 'a + Eq + Hash + Ord,
+    Ev: 'a + Clone,
+{
+    bindings
+        .into_iter()
+        .flat_map(|bindings| bindings.build(|event| Some(event.clone())))
+        .map(|event| ReplayedEvent::new(time.clone(), event))
+        .collect()
+}
+
+#[derive(Debug, Error)]
+pub enum InputLogError {
+    #[error("Failed to write input log: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid input log entry at line {line}: {source}")]
+    Json {
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+}
@@ -0,0 +1,140 @@
+use core::hash::Hash;
+use std::collections::HashMap;
+
+use input_core::SequenceMatchKind;
+
+use crate::{BindingEvent, FilteredBindings, SequenceBinding, SequenceStep};
+
+#[derive(Clone, Debug)]
+pub struct SequenceMappingCache<Sw, Mo, Bu>(SequenceMappingData<Sw, Mo, Bu>);
+
+#[derive(Clone, Debug)]
+pub struct SequenceMappingNode<Sw, Mo, Bu> {
+    bindings: Vec<BindingEvent<Bu>>,
+    next: SequenceMappingCache<Sw, Mo, Bu>,
+}
+
+type SequenceMappingData<Sw, Mo, Bu> =
+    HashMap<SequenceStep<Sw, Mo>, SequenceMappingNode<Sw, Mo, Bu>>;
+type SequenceMappingEntry<'a, Sw, Mo, Bu> = (
+    &'a SequenceStep<Sw, Mo>,
+    &'a SequenceMappingNode<Sw, Mo, Bu>,
+);
+
+impl<Sw, Mo, Bu> SequenceMappingCache<Sw, Mo, Bu>
+where
+    Sw: Eq + Hash,
+    Mo: Eq + Hash,
+{
+    pub fn from_bindings(mapping: impl IntoIterator<Item = SequenceBinding<Sw, Mo, Bu>>) -> Self {
+        let mut data = Self::default();
+        for binding in mapping {
+            let mut cache = &mut data;
+            for step in binding.prefix {
+                cache = &mut cache.0.entry(step).or_default().next;
+            }
+            let step = SequenceStep::new(binding.switch, binding.modifiers);
+            cache
+                .0
+                .entry(step)
+                .or_default()
+                .bindings
+                .push(BindingEvent::new(
+                    binding.when,
+                    None,
+                    binding.priority,
+                    binding.propagation,
+                    binding.event,
+                ));
+        }
+
+        data
+    }
+}
+
+impl<Sw, Mo, Bu> SequenceMappingCache<Sw, Mo, Bu> {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn filter_by_steps(
+        &self,
+        steps: &[SequenceStep<Sw, Mo>],
+    ) -> Option<SequenceMappingEntry<'_, Sw, Mo, Bu>>
+    where
+        Sw: Eq + Hash,
+        Mo: Eq + Hash,
+    {
+        let (last, prefix) = steps.split_last()?;
+        let mut cache = self;
+        for step in prefix {
+            cache = &cache.0.get(step)?.next;
+        }
+        cache.0.get_key_value(last)
+    }
+
+    pub fn match_kind(&self, steps: &[SequenceStep<Sw, Mo>]) -> Option<SequenceMatchKind>
+    where
+        Sw: Eq + Hash,
+        Mo: Eq + Hash,
+    {
+        self.filter_by_steps(steps)
+            .and_then(|(_, node)| node.match_kind())
+    }
+
+    pub fn bindings_by_steps(
+        &self,
+        steps: &[SequenceStep<Sw, Mo>],
+    ) -> Option<FilteredBindings<'_, Mo, Bu>>
+    where
+        Sw: Eq + Hash,
+        Mo: Eq + Hash,
+    {
+        let (step, node) = self.filter_by_steps(steps)?;
+        if node.bindings.is_empty() {
+            None
+        } else {
+            Some(FilteredBindings(
+                core::iter::once((&step.modifiers, node.bindings.iter().collect())).collect(),
+            ))
+        }
+    }
+}
+
+impl<Sw, Mo, Bu> SequenceMappingNode<Sw, Mo, Bu> {
+    #[must_use]
+    pub fn bindings(&self) -> &[BindingEvent<Bu>] {
+        &self.bindings
+    }
+
+    #[must_use]
+    pub const fn next(&self) -> &SequenceMappingCache<Sw, Mo, Bu> {
+        &self.next
+    }
+
+    #[must_use]
+    pub fn match_kind(&self) -> Option<SequenceMatchKind> {
+        match (self.bindings.is_empty(), self.next.is_empty()) {
+            (false, true) => Some(SequenceMatchKind::Complete),
+            (false, false) => Some(SequenceMatchKind::Extendable),
+            (true, false) => Some(SequenceMatchKind::Prefix),
+            (true, true) => None,
+        }
+    }
+}
+
+impl<Sw, Mo, Bu> Default for SequenceMappingCache<Sw, Mo, Bu> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+
+impl<Sw, Mo, Bu> Default for SequenceMappingNode<Sw, Mo, Bu> {
+    fn default() -> Self {
+        Self {
+            bindings: Vec::new(),
+            next: SequenceMappingCache::default(),
+        }
+    }
+}
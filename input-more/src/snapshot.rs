@@ -0,0 +1,279 @@
+use core::borrow::{Borrow, BorrowMut};
+use core::hash::Hash;
+
+use input_core::{
+    AnalogState, AnalogStateSnapshot, AxisState, AxisStateSnapshot, ClickExactHandleRequest,
+    CoordsState, GestureState, GestureStateSnapshot, LongPressHandleRequest, Modifiers,
+    ModifiersSnapshot, PenState, PenStateSnapshot, PointerState, PointerStateSnapshot,
+    RepeatSchedulerState, RepeatState, RepeatStateSnapshot, SchedulerSnapshot,
+    SequenceSchedulerState, SequenceState, SequenceStateSnapshot, TextInputState, TimedState,
+    TimedStateSnapshot,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ContextStack, DeviceSchedulerSnapshot, DeviceSchedulerState, GlobalState, KeyboardLane,
+    MouseLane, PenLane, SequenceStep, TouchLane,
+};
+
+// Everything that changes while handling events. Policies, axis configuration
+// and the hit tester belong to the state the snapshot is restored into.
+// Kinetic scrolling after a fling is left out along with its schedulers: it is
+// a short animation, so a restored state starts without one.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(
+    bound(deserialize = "Mo: Deserialize<'de> + Ord, Ti: Deserialize<'de>, \
+    KeSw: Deserialize<'de>, KeTr: Deserialize<'de>, MsSw: Deserialize<'de>, \
+    MsTr: Deserialize<'de>, ToSw: Deserialize<'de>, PeSw: Deserialize<'de>, \
+    Co: Deserialize<'de>")
+)]
+pub struct GlobalStateSnapshot<Mo, Ti, KeSw, KeTr, MsSw, MsTr, ToSw, PeSw, Co> {
+    pub modifiers: ModifiersSnapshot<Mo>,
+    pub keyboard_coords: Co,
+    pub mouse_coords: Co,
+    pub touch_coords: Co,
+    pub keyboard_timed_state: TimedStateSnapshot<KeSw>,
+    pub mouse_timed_state: TimedStateSnapshot<MsSw>,
+    pub touch_timed_state: TimedStateSnapshot<ToSw>,
+    pub keyboard_long_press_scheduler: DeviceSchedulerSnapshot<Ti, KeSw, Mo, Co>,
+    pub keyboard_click_exact_scheduler: DeviceSchedulerSnapshot<Ti, KeSw, Mo, Co>,
+    pub mouse_long_press_scheduler: DeviceSchedulerSnapshot<Ti, MsSw, Mo, Co>,
+    pub mouse_click_exact_scheduler: DeviceSchedulerSnapshot<Ti, MsSw, Mo, Co>,
+    pub touch_long_press_scheduler: DeviceSchedulerSnapshot<Ti, ToSw, Mo, Co>,
+    pub touch_click_exact_scheduler: DeviceSchedulerSnapshot<Ti, ToSw, Mo, Co>,
+    pub keyboard_pointer_state: PointerStateSnapshot<KeSw, Co, Ti>,
+    pub mouse_pointer_state: PointerStateSnapshot<MsSw, Co, Ti>,
+    pub touch_pointer_state: PointerStateSnapshot<ToSw, Co, Ti>,
+    pub touch_gesture_state: GestureStateSnapshot<ToSw, Co>,
+    pub keyboard_sequence_state: SequenceStateSnapshot<SequenceStep<KeSw, Mo>>,
+    pub keyboard_sequence_scheduler: SchedulerSnapshot<Ti, ()>,
+    pub keyboard_repeat_state: RepeatStateSnapshot<KeSw>,
+    pub keyboard_repeat_scheduler: SchedulerSnapshot<Ti, KeSw>,
+    pub mouse_axis_state: AxisStateSnapshot<MsTr>,
+    pub keyboard_analog_state: AnalogStateSnapshot<KeTr, KeSw>,
+    pub pen_coords: Co,
+    pub pen_timed_state: TimedStateSnapshot<PeSw>,
+    pub pen_long_press_scheduler: DeviceSchedulerSnapshot<Ti, PeSw, Mo, Co>,
+    pub pen_click_exact_scheduler: DeviceSchedulerSnapshot<Ti, PeSw, Mo, Co>,
+    pub pen_pointer_state: PointerStateSnapshot<PeSw, Co, Ti>,
+    pub pen_state: PenStateSnapshot<PeSw>,
+    pub text_input_state: TextInputState,
+    pub context: ContextStack,
+}
+
+impl<Mo, Ti, Du, KeSw, MsSw, ToSw, PeSw, Co, AxMs, AnKe, ShMsKn, ShToKn, PnPe, Ht>
+    GlobalState<
+        Modifiers<Mo>,
+        KeyboardLane<
+            CoordsState<Co>,
+            TimedState<KeSw, Du>,
+            DeviceSchedulerState<Ti, KeSw, Mo, Co, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, KeSw, Mo, Co, ClickExactHandleRequest>,
+            PointerState<KeSw, Co, Ti, Du>,
+            SequenceState<SequenceStep<KeSw, Mo>, Du>,
+            SequenceSchedulerState<Ti, ()>,
+            RepeatState<KeSw, Du>,
+            RepeatSchedulerState<Ti, KeSw>,
+            AnKe,
+        >,
+        MouseLane<
+            CoordsState<Co>,
+            TimedState<MsSw, Du>,
+            DeviceSchedulerState<Ti, MsSw, Mo, Co, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, MsSw, Mo, Co, ClickExactHandleRequest>,
+            PointerState<MsSw, Co, Ti, Du>,
+            AxMs,
+            ShMsKn,
+        >,
+        TouchLane<
+            CoordsState<Co>,
+            TimedState<ToSw, Du>,
+            DeviceSchedulerState<Ti, ToSw, Mo, Co, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, ToSw, Mo, Co, ClickExactHandleRequest>,
+            PointerState<ToSw, Co, Ti, Du>,
+            GestureState<ToSw, Co>,
+            ShToKn,
+        >,
+        PenLane<
+            CoordsState<Co>,
+            TimedState<PeSw, Du>,
+            DeviceSchedulerState<Ti, PeSw, Mo, Co, LongPressHandleRequest>,
+            DeviceSchedulerState<Ti, PeSw, Mo, Co, ClickExactHandleRequest>,
+            PointerState<PeSw, Co, Ti, Du>,
+            PnPe,
+        >,
+        Ht,
+    >
+{
+    pub fn snapshot<KeTr, MsTr>(
+        &self,
+    ) -> GlobalStateSnapshot<Mo, Ti, KeSw, KeTr, MsSw, MsTr, ToSw, PeSw, Co>
+    where
+        AxMs: Borrow<AxisState<MsTr>>,
+        AnKe: Borrow<AnalogState<KeTr, KeSw>>,
+        PnPe: Borrow<PenState<PeSw>>,
+        Mo: Clone,
+        Ti: Clone,
+        KeSw: Clone,
+        KeTr: Clone,
+        MsSw: Clone,
+        MsTr: Clone,
+        ToSw: Clone,
+        PeSw: Clone,
+        Co: Clone,
+    {
+        GlobalStateSnapshot {
+            modifiers: self.modifiers.snapshot(),
+            keyboard_coords: self.keyboard.coords_state.coords().clone(),
+            mouse_coords: self.mouse.coords_state.coords().clone(),
+            touch_coords: self.touch.coords_state.coords().clone(),
+            keyboard_timed_state: self.keyboard.timed_state.snapshot(),
+            mouse_timed_state: self.mouse.timed_state.snapshot(),
+            touch_timed_state: self.touch.timed_state.snapshot(),
+            keyboard_long_press_scheduler: self.keyboard.long_press_scheduler.snapshot(),
+            keyboard_click_exact_scheduler: self.keyboard.click_exact_scheduler.snapshot(),
+            mouse_long_press_scheduler: self.mouse.long_press_scheduler.snapshot(),
+            mouse_click_exact_scheduler: self.mouse.click_exact_scheduler.snapshot(),
+            touch_long_press_scheduler: self.touch.long_press_scheduler.snapshot(),
+            touch_click_exact_scheduler: self.touch.click_exact_scheduler.snapshot(),
+            keyboard_pointer_state: self.keyboard.pointer_state.snapshot(),
+            mouse_pointer_state: self.mouse.pointer_state.snapshot(),
+            touch_pointer_state: self.touch.pointer_state.snapshot(),
+            touch_gesture_state: self.touch.gesture_state.snapshot(),
+            keyboard_sequence_state: self.keyboard.sequence_state.snapshot(),
+            keyboard_sequence_scheduler: self.keyboard.sequence_scheduler.snapshot(),
+            keyboard_repeat_state: self.keyboard.repeat_state.snapshot(),
+            keyboard_repeat_scheduler: self.keyboard.repeat_scheduler.snapshot(),
+            mouse_axis_state: self.mouse.axis_state.borrow().snapshot(),
+            keyboard_analog_state: self.keyboard.analog_state.borrow().snapshot(),
+            pen_coords: self.pen.coords_state.coords().clone(),
+            pen_timed_state: self.pen.timed_state.snapshot(),
+            pen_long_press_scheduler: self.pen.long_press_scheduler.snapshot(),
+            pen_click_exact_scheduler: self.pen.click_exact_scheduler.snapshot(),
+            pen_pointer_state: self.pen.pointer_state.snapshot(),
+            pen_state: self.pen.pen_state.borrow().snapshot(),
+            text_input_state: self.text_input_state.clone(),
+            context: self.context.clone(),
+        }
+    }
+
+    // The states are restored first, so that the schedulers can take
+    // fresh handle requests from them.
+    pub fn restore<KeTr, MsTr>(
+        &mut self,
+        snapshot: GlobalStateSnapshot<Mo, Ti, KeSw, KeTr, MsSw, MsTr, ToSw, PeSw, Co>,
+    ) where
+        AxMs: BorrowMut<AxisState<MsTr>>,
+        AnKe: BorrowMut<AnalogState<KeTr, KeSw>>,
+        PnPe: BorrowMut<PenState<PeSw>>,
+        Mo: Ord,
+        Ti: Ord,
+        KeSw: Eq + Hash,
+        KeTr: Eq + Hash,
+        MsSw: Eq + Hash,
+        MsTr: Eq + Hash,
+        ToSw: Eq + Hash,
+        PeSw: Eq + Hash,
+    {
+        self.modifiers.restore(snapshot.modifiers);
+        self.keyboard
+            .coords_state
+            .set_coords(snapshot.keyboard_coords);
+        self.mouse.coords_state.set_coords(snapshot.mouse_coords);
+        self.touch.coords_state.set_coords(snapshot.touch_coords);
+        self.keyboard
+            .timed_state
+            .restore(snapshot.keyboard_timed_state);
+        self.mouse.timed_state.restore(snapshot.mouse_timed_state);
+        self.touch.timed_state.restore(snapshot.touch_timed_state);
+        self.keyboard
+            .pointer_state
+            .restore(snapshot.keyboard_pointer_state);
+        self.mouse
+            .pointer_state
+            .restore(snapshot.mouse_pointer_state);
+        self.touch
+            .pointer_state
+            .restore(snapshot.touch_pointer_state);
+        self.touch
+            .gesture_state
+            .restore(snapshot.touch_gesture_state);
+        self.keyboard
+            .sequence_state
+            .restore(snapshot.keyboard_sequence_state);
+        self.keyboard
+            .repeat_state
+            .restore(snapshot.keyboard_repeat_state);
+        self.mouse
+            .axis_state
+            .borrow_mut()
+            .restore(snapshot.mouse_axis_state);
+        self.keyboard
+            .analog_state
+            .borrow_mut()
+            .restore(snapshot.keyboard_analog_state);
+        self.pen.coords_state.set_coords(snapshot.pen_coords);
+        self.pen.timed_state.restore(snapshot.pen_timed_state);
+        self.pen.pointer_state.restore(snapshot.pen_pointer_state);
+        self.pen.pen_state.borrow_mut().restore(snapshot.pen_state);
+        self.text_input_state = snapshot.text_input_state;
+        self.context = snapshot.context;
+
+        let timed = &self.keyboard.timed_state;
+        self.keyboard
+            .long_press_scheduler
+            .restore(snapshot.keyboard_long_press_scheduler, |(event, _, _)| {
+                timed.long_press_request(&event.switch)
+            });
+        self.keyboard
+            .click_exact_scheduler
+            .restore(snapshot.keyboard_click_exact_scheduler, |(event, _, _)| {
+                timed.click_exact_request(&event.switch)
+            });
+        let timed = &self.mouse.timed_state;
+        self.mouse
+            .long_press_scheduler
+            .restore(snapshot.mouse_long_press_scheduler, |(event, _, _)| {
+                timed.long_press_request(&event.switch)
+            });
+        self.mouse
+            .click_exact_scheduler
+            .restore(snapshot.mouse_click_exact_scheduler, |(event, _, _)| {
+                timed.click_exact_request(&event.switch)
+            });
+        let timed = &self.touch.timed_state;
+        self.touch
+            .long_press_scheduler
+            .restore(snapshot.touch_long_press_scheduler, |(event, _, _)| {
+                timed.long_press_request(&event.switch)
+            });
+        self.touch
+            .click_exact_scheduler
+            .restore(snapshot.touch_click_exact_scheduler, |(event, _, _)| {
+                timed.click_exact_request(&event.switch)
+            });
+        let timed = &self.pen.timed_state;
+        self.pen
+            .long_press_scheduler
+            .restore(snapshot.pen_long_press_scheduler, |(event, _, _)| {
+                timed.long_press_request(&event.switch)
+            });
+        self.pen
+            .click_exact_scheduler
+            .restore(snapshot.pen_click_exact_scheduler, |(event, _, _)| {
+                timed.click_exact_request(&event.switch)
+            });
+        let sequence = &self.keyboard.sequence_state;
+        self.keyboard
+            .sequence_scheduler
+            .restore(snapshot.keyboard_sequence_scheduler, |()| {
+                sequence.request()
+            });
+        let repeat = &self.keyboard.repeat_state;
+        self.keyboard
+            .repeat_scheduler
+            .restore(snapshot.keyboard_repeat_scheduler, |switch| {
+                repeat.request(switch)
+            });
+    }
+}
@@ -3,7 +3,10 @@ use std::collections::HashMap;
 
 use input_core::Modifiers;
 
-use crate::{CoordsBinding, SwitchBinding, TriggerBinding};
+use crate::{
+    BindingEvent, BindingOutcome, BindingPropagation, BuildReport, BuildReportEntry, ContextStack,
+    CoordsBinding, HitTester, SwitchBinding, TriggerBinding,
+};
 
 #[derive(Clone, Debug)]
 pub struct SwitchMappingCache<Sw, Mo, Td, Pd, Bu>(SwitchMappingData<Sw, Mo, Td, Pd, Bu>);
@@ -25,7 +28,7 @@ type SwitchMappingData<Sw, Mo, Td, Pd, Bu> = HashMap<Sw, SwitchMappingDataBySwit
 type SwitchMappingDataBySwitch<Mo, Td, Pd, Bu> =
     HashMap<Modifiers<Mo>, SwitchMappingDataByModifiers<Td, Pd, Bu>>;
 type SwitchMappingDataByModifiers<Td, Pd, Bu> = HashMap<Td, SwitchMappingDataByTimed<Pd, Bu>>;
-type SwitchMappingDataByTimed<Pd, Bu> = HashMap<Pd, Vec<Bu>>;
+type SwitchMappingDataByTimed<Pd, Bu> = HashMap<Pd, Vec<BindingEvent<Bu>>>;
 
 #[derive(Clone, Debug)]
 pub struct TriggerMappingCache<Tr, Mo, Bu>(TriggerMappingData<Tr, Mo, Bu>);
@@ -34,7 +37,7 @@ pub struct TriggerMappingCache<Tr, Mo, Bu>(TriggerMappingData<Tr, Mo, Bu>);
 pub struct TriggerMappingByTrigger<'a, Mo, Bu>(&'a TriggerMappingDataByTrigger<Mo, Bu>);
 
 type TriggerMappingData<Tr, Mo, Bu> = HashMap<Tr, TriggerMappingDataByTrigger<Mo, Bu>>;
-type TriggerMappingDataByTrigger<Mo, Bu> = HashMap<Modifiers<Mo>, Vec<Bu>>;
+type TriggerMappingDataByTrigger<Mo, Bu> = HashMap<Modifiers<Mo>, Vec<BindingEvent<Bu>>>;
 
 #[derive(Clone, Debug)]
 pub struct CoordsMappingCache<Pd, Mo, Bu>(CoordsMappingData<Pd, Mo, Bu>);
@@ -43,10 +46,12 @@ pub struct CoordsMappingCache<Pd, Mo, Bu>(CoordsMappingData<Pd, Mo, Bu>);
 pub struct CoordsMappingByPointer<'a, Mo, Bu>(&'a CoordsMappingDataByPointer<Mo, Bu>);
 
 type CoordsMappingData<Pd, Mo, Bu> = HashMap<Pd, CoordsMappingDataByPointer<Mo, Bu>>;
-type CoordsMappingDataByPointer<Mo, Bu> = HashMap<Modifiers<Mo>, Vec<Bu>>;
+type CoordsMappingDataByPointer<Mo, Bu> = HashMap<Modifiers<Mo>, Vec<BindingEvent<Bu>>>;
 
 #[derive(Clone, Debug)]
-pub struct FilteredBindings<'a, Mo, Bu>(HashMap<&'a Modifiers<Mo>, &'a Vec<Bu>>);
+pub struct FilteredBindings<'a, Mo, Bu>(
+    pub(crate) HashMap<&'a Modifiers<Mo>, Vec<&'a BindingEvent<Bu>>>,
+);
 
 impl<Sw, Mo, Td, Pd, Bu> SwitchMappingCache<Sw, Mo, Td, Pd, Bu>
 where
@@ -69,7 +74,13 @@ where
                 .or_default()
                 .entry(binding.pointer_data)
                 .or_default();
-            events.push(binding.event);
+            events.push(BindingEvent::new(
+                binding.when,
+                binding.target,
+                binding.priority,
+                binding.propagation,
+                binding.event,
+            ));
         }
 
         Self(data)
@@ -89,7 +100,13 @@ where
                 .or_default()
                 .entry(binding.modifiers)
                 .or_default();
-            events.push(binding.event);
+            events.push(BindingEvent::new(
+                binding.when,
+                None,
+                binding.priority,
+                binding.propagation,
+                binding.event,
+            ));
         }
 
         Self(data)
@@ -109,7 +126,13 @@ where
                 .or_default()
                 .entry(binding.modifiers)
                 .or_default();
-            events.push(binding.event);
+            events.push(BindingEvent::new(
+                binding.when,
+                binding.target,
+                binding.priority,
+                binding.propagation,
+                binding.event,
+            ));
         }
 
         Self(data)
@@ -206,7 +229,7 @@ impl<'a, Mo, Pd, Bu> SwitchMappingByTimed<'a, Mo, Pd, Bu> {
                 .filter_map(|(&modifiers, &filtered)| {
                     filtered
                         .get(pointer_data)
-                        .map(|filtered| (modifiers, filtered))
+                        .map(|filtered| (modifiers, filtered.iter().collect()))
                 })
                 .collect(),
         );
@@ -241,6 +264,7 @@ impl<'a, Mo, Bu> TriggerMappingByTrigger<'a, Mo, Bu> {
             .filter(|(binding_modifiers, _)| {
                 binding_modifiers.switches().is_subset(modifiers.switches())
             })
+            .map(|(binding_modifiers, events)| (binding_modifiers, events.iter().collect()))
             .collect();
         if bindings.is_empty() {
             None
@@ -276,6 +300,7 @@ impl<'a, Mo, Bu> CoordsMappingByPointer<'a, Mo, Bu> {
             .filter(|(binding_modifiers, _)| {
                 binding_modifiers.switches().is_subset(modifiers.switches())
             })
+            .map(|(binding_modifiers, events)| (binding_modifiers, events.iter().collect()))
             .collect();
         if bindings.is_empty() {
             None
@@ -286,31 +311,49 @@ impl<'a, Mo, Bu> CoordsMappingByPointer<'a, Mo, Bu> {
 }
 
 impl<'a, Mo, Bu> FilteredBindings<'a, Mo, Bu> {
-    pub fn into_inner(self) -> HashMap<&'a Modifiers<Mo>, &'a Vec<Bu>> {
+    #[must_use]
+    pub fn into_inner(self) -> HashMap<&'a Modifiers<Mo>, Vec<&'a BindingEvent<Bu>>> {
         self.0
     }
 
-    pub fn inner(&self) -> &HashMap<&'a Modifiers<Mo>, &'a Vec<Bu>> {
+    #[must_use]
+    pub const fn inner(&self) -> &HashMap<&'a Modifiers<Mo>, Vec<&'a BindingEvent<Bu>>> {
         &self.0
     }
 
-    pub fn build<F, Ev>(self, mut handler: F) -> Vec<Ev>
+    #[must_use]
+    pub fn filter_by_context(self, context: &ContextStack) -> Option<Self>
     where
-        F: FnMut(&Bu) -> Option<Ev>,
-        Mo: Eq + Hash + Ord,
-        // TODO: Remove Display
-        Mo: std::fmt::Debug,
-        Ev: std::fmt::Debug,
-        Bu: std::fmt::Debug,
+        Mo: Eq + Hash,
+    {
+        self.filter_events(|event| context.matches(event.when.as_ref()))
+    }
+
+    pub fn filter_by_target<Co, Ht>(self, hit_tester: &Ht, coords: &Co) -> Option<Self>
+    where
+        Mo: Eq + Hash,
+        Ht: HitTester<Co> + ?Sized,
+    {
+        self.filter_events(|event| {
+            event
+                .target
+                .as_ref()
+                .is_none_or(|target| hit_tester.hit_test(coords, target))
+        })
+    }
+
+    fn filter_events<F>(self, mut predicate: F) -> Option<Self>
+    where
+        F: FnMut(&BindingEvent<Bu>) -> bool,
+        Mo: Eq + Hash,
     {
-        // println!("BuBi {:?}", self);
         let bindings: HashMap<_, _> = self
-            .into_inner()
+            .0
             .into_iter()
             .filter_map(|(modifiers, events)| {
                 let events: Vec<_> = events
                     .into_iter()
-                    .filter_map(|binding| handler(binding))
+                    .filter(|event| predicate(event))
                     .collect();
                 if events.is_empty() {
                     None
@@ -319,26 +362,110 @@ impl<'a, Mo, Bu> FilteredBindings<'a, Mo, Bu> {
                 }
             })
             .collect();
+        if bindings.is_empty() {
+            None
+        } else {
+            Some(Self(bindings))
+        }
+    }
 
-        // println!("BuBi {:?}", bindings);
-        let events_mask: Vec<_> = bindings
-            .iter()
-            .map(|(modifiers, _)| {
-                bindings.iter().all(|(other_modifiers, _)| {
-                    modifiers.switches().is_superset(other_modifiers.switches())
+    pub fn build<F, Ev>(self, handler: F) -> Vec<Ev>
+    where
+        F: FnMut(&Bu) -> Option<Ev>,
+        Mo: Eq + Hash + Ord,
+    {
+        self.build_with_report(handler).0
+    }
+
+    pub fn build_with_report<F, Ev>(self, mut handler: F) -> (Vec<Ev>, BuildReport<'a, Mo, Bu>)
+    where
+        F: FnMut(&Bu) -> Option<Ev>,
+        Mo: Eq + Hash + Ord,
+    {
+        let mut candidates: Vec<_> = self
+            .into_inner()
+            .into_iter()
+            .flat_map(|(modifiers, bindings)| {
+                bindings
+                    .into_iter()
+                    .map(move |binding| (modifiers, binding))
+            })
+            .collect();
+        candidates.sort_by(|(lhs_modifiers, lhs), (rhs_modifiers, rhs)| {
+            rhs.priority
+                .cmp(&lhs.priority)
+                .then_with(|| {
+                    let lhs_len = lhs_modifiers.switches().len();
+                    rhs_modifiers.switches().len().cmp(&lhs_len)
                 })
+                .then_with(|| lhs_modifiers.cmp(rhs_modifiers))
+        });
+
+        let mut events: Vec<_> = candidates
+            .iter()
+            .map(|(_, binding)| handler(&binding.event))
+            .collect();
+        let mut outcomes: Vec<_> = events
+            .iter()
+            .map(|event| {
+                event
+                    .as_ref()
+                    .map_or(BindingOutcome::Disabled, |_| BindingOutcome::Shadowed)
             })
             .collect();
 
-        // println!("BuMa {:?}", events_mask);
-        let result = bindings
-            .into_iter()
-            .enumerate()
-            .filter_map(|(j, event)| if events_mask[j] { Some(event) } else { None })
-            .flat_map(|(_, events)| events)
+        let mut remaining: Vec<_> = (0..candidates.len())
+            .filter(|&j| events[j].is_some())
             .collect();
+        while let Some(&first) = remaining.first() {
+            let (top_modifiers, top_binding) = candidates[first];
+            let level: Vec<_> = remaining
+                .iter()
+                .copied()
+                .take_while(|&j| candidates[j].1.priority == top_binding.priority)
+                .collect();
+            let is_resolved = level.iter().all(|&j| {
+                top_modifiers
+                    .switches()
+                    .is_superset(candidates[j].0.switches())
+            });
+            if !is_resolved {
+                for &j in &level {
+                    outcomes[j] = BindingOutcome::Conflicted;
+                }
+                break;
+            }
+
+            let mut is_consumed = false;
+            for &j in &level {
+                if candidates[j].0 == top_modifiers {
+                    outcomes[j] = BindingOutcome::Applied;
+                    is_consumed |= candidates[j].1.propagation == BindingPropagation::Consume;
+                }
+            }
+            if is_consumed {
+                break;
+            }
+            remaining.retain(|&j| {
+                candidates[j].0 != top_modifiers || candidates[j].1.priority != top_binding.priority
+            });
+        }
 
-        //println!("BuEv {:?}", result);
-        result
+        let events = outcomes
+            .iter()
+            .zip(events.iter_mut())
+            .filter(|(outcome, _)| **outcome == BindingOutcome::Applied)
+            .filter_map(|(_, event)| event.take())
+            .collect();
+        let report = BuildReport::new(
+            candidates
+                .into_iter()
+                .zip(outcomes)
+                .map(|((modifiers, binding), outcome)| {
+                    BuildReportEntry::new(modifiers, binding, outcome)
+                })
+                .collect(),
+        );
+        (events, report)
     }
 }
@@ -61,13 +61,14 @@ fn test_chain() {
     use input_core::*;
     use input_more::*;
 
-    //type DurationMs = i64;
+    type DurationMs = i64;
     type TimestampMs = i64;
 
     #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
     enum Switch {
         Keyboard(KeyboardSwitch),
         Mouse(MouseSwitch),
+        Touch(TouchSwitch),
     }
 
     #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -76,6 +77,9 @@ fn test_chain() {
     #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
     struct MouseSwitch(&'static str);
 
+    #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+    struct TouchSwitch(u32);
+
     #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
     struct KeyboardTrigger(&'static str);
 
@@ -88,6 +92,18 @@ fn test_chain() {
     #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
     struct MouseCoords(i64, i64);
 
+    impl CoordsDistance for KeyboardCoords {
+        fn distance(&self, _: &Self) -> f64 {
+            0.0
+        }
+    }
+
+    impl CoordsDistance for MouseCoords {
+        fn distance(&self, other: &Self) -> f64 {
+            ((other.0 - self.0) as f64).hypot((other.1 - self.1) as f64)
+        }
+    }
+
     impl From<KeyboardSwitch> for Switch {
         fn from(switch: KeyboardSwitch) -> Self {
             Self::Keyboard(switch)
@@ -100,6 +116,12 @@ fn test_chain() {
         }
     }
 
+    impl From<TouchSwitch> for Switch {
+        fn from(switch: TouchSwitch) -> Self {
+            Self::Touch(switch)
+        }
+    }
+
     #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
     struct NodeId(u32);
 
@@ -319,6 +341,7 @@ fn test_chain() {
 
     type KeyboardMapping = Mapping<KeyboardSwitch, KeyboardTrigger, Switch, BasicAppEventBuilder>;
     type MouseMapping = Mapping<MouseSwitch, MouseTrigger, Switch, PointerAppEventBuilder>;
+    type TouchMapping = Mapping<TouchSwitch, MouseTrigger, Switch, PointerAppEventBuilder>;
 
     type KeyboardSwitchEvent = SwitchEvent<TimestampMs, KeyboardSwitch>;
     type MouseSwitchEvent = SwitchEvent<TimestampMs, MouseSwitch>;
@@ -328,11 +351,13 @@ fn test_chain() {
     type MouseCoordsEvent = CoordsEvent<TimestampMs, MouseCoords>;
 
     type Modifiers = input_core::Modifiers<Switch>;
-    type KeyboardTimedState = TimedState<KeyboardSwitch>;
-    type MouseTimedState = TimedState<MouseSwitch>;
+    type KeyboardTimedState = TimedState<KeyboardSwitch, DurationMs>;
+    type MouseTimedState = TimedState<MouseSwitch, DurationMs>;
+    type TouchTimedState = TimedState<TouchSwitch, DurationMs>;
 
     type KeyboardCoordsState = CoordsState<KeyboardCoords>;
     type MouseCoordsState = CoordsState<MouseCoords>;
+    type TouchCoordsState = CoordsState<MouseCoords>;
 
     type CustomScheduler<Sw, Re, Co> = DeviceSchedulerState<TimestampMs, Sw, Switch, Co, Re>;
 
@@ -344,26 +369,78 @@ fn test_chain() {
         CustomScheduler<MouseSwitch, LongPressHandleRequest, MouseCoords>;
     type MouseClickExactScheduler =
         CustomScheduler<MouseSwitch, ClickExactHandleRequest, MouseCoords>;
-    type KeyboardPointerState = PointerState<KeyboardSwitch, KeyboardCoords>;
-    type MousePointerState = PointerState<MouseSwitch, MouseCoords>;
+    type TouchLongPressScheduler =
+        CustomScheduler<TouchSwitch, LongPressHandleRequest, MouseCoords>;
+    type TouchClickExactScheduler =
+        CustomScheduler<TouchSwitch, ClickExactHandleRequest, MouseCoords>;
+    type KeyboardPointerState =
+        PointerState<KeyboardSwitch, KeyboardCoords, TimestampMs, DurationMs>;
+    type MousePointerState = PointerState<MouseSwitch, MouseCoords, TimestampMs, DurationMs>;
+    type TouchPointerState = PointerState<TouchSwitch, MouseCoords, TimestampMs, DurationMs>;
+    type TouchGestureState = GestureState<TouchSwitch, MouseCoords>;
+    type KeyboardSequenceState = SequenceState<SequenceStep<KeyboardSwitch, Switch>, DurationMs>;
+    type KeyboardSequenceScheduler = SequenceSchedulerState<TimestampMs, ()>;
+    type KeyboardRepeatState = RepeatState<KeyboardSwitch, DurationMs>;
+    type KeyboardRepeatScheduler = RepeatSchedulerState<TimestampMs, KeyboardSwitch>;
+    type MouseAxisState = AxisState<MouseTrigger>;
+    type KeyboardAnalogState = AnalogState<KeyboardTrigger, KeyboardSwitch>;
+    type MouseKineticScheduler = CustomScheduler<MouseSwitch, KineticHandleRequest, MouseCoords>;
+    type TouchKineticScheduler = CustomScheduler<TouchSwitch, KineticHandleRequest, MouseCoords>;
+    type PenCoordsState = CoordsState<MouseCoords>;
+    type PenTimedState = TimedState<TouchSwitch, DurationMs>;
+    type PenLongPressScheduler = CustomScheduler<TouchSwitch, LongPressHandleRequest, MouseCoords>;
+    type PenClickExactScheduler =
+        CustomScheduler<TouchSwitch, ClickExactHandleRequest, MouseCoords>;
+    type PenPointerState = PointerState<TouchSwitch, MouseCoords, TimestampMs, DurationMs>;
+    type PenState = input_core::PenState<TouchSwitch>;
 
-    type GlobalState = input_more::GlobalState<
-        Modifiers,
+    type KeyboardLane = input_more::KeyboardLane<
         KeyboardCoordsState,
-        MouseCoordsState,
         KeyboardTimedState,
-        MouseTimedState,
         KeyboardLongPressScheduler,
         KeyboardClickExactScheduler,
+        KeyboardPointerState,
+        KeyboardSequenceState,
+        KeyboardSequenceScheduler,
+        KeyboardRepeatState,
+        KeyboardRepeatScheduler,
+        KeyboardAnalogState,
+    >;
+    type MouseLane = input_more::MouseLane<
+        MouseCoordsState,
+        MouseTimedState,
         MouseLongPressScheduler,
         MouseClickExactScheduler,
-        KeyboardPointerState,
         MousePointerState,
+        MouseAxisState,
+        MouseKineticScheduler,
+    >;
+    type TouchLane = input_more::TouchLane<
+        TouchCoordsState,
+        TouchTimedState,
+        TouchLongPressScheduler,
+        TouchClickExactScheduler,
+        TouchPointerState,
+        TouchGestureState,
+        TouchKineticScheduler,
+    >;
+    type PenLane = input_more::PenLane<
+        PenCoordsState,
+        PenTimedState,
+        PenLongPressScheduler,
+        PenClickExactScheduler,
+        PenPointerState,
+        PenState,
     >;
 
+    type GlobalState =
+        input_more::GlobalState<Modifiers, KeyboardLane, MouseLane, TouchLane, PenLane>;
+
     type GlobalMappingCache = input_more::GlobalMappingCache<
         DeviceMappingCache<KeyboardSwitch, KeyboardTrigger, Switch, BasicAppEventBuilder>,
         DeviceMappingCache<MouseSwitch, MouseTrigger, Switch, PointerAppEventBuilder>,
+        DeviceMappingCache<TouchSwitch, MouseTrigger, Switch, PointerAppEventBuilder>,
+        DeviceMappingCache<TouchSwitch, MouseTrigger, Switch, PointerAppEventBuilder>,
         MappingModifiersCache<Switch>,
     >;
 
@@ -406,6 +483,10 @@ fn test_chain() {
             modifiers: Modifiers::new(),
             timed_data: click,
             pointer_data: None,
+            when: None,
+            target: None,
+            priority: 0,
+            propagation: BindingPropagation::Consume,
             event: BasicAppEventBuilder::Unselect,
         })]
         .into_iter()
@@ -418,6 +499,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: click,
                 pointer_data: None,
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::Unselect,
             }),
             Binding::Release(SwitchBinding {
@@ -425,6 +510,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: click,
                 pointer_data: None,
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::SelectNode,
             }),
             Binding::Release(SwitchBinding {
@@ -432,6 +521,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: dbl_click,
                 pointer_data: None,
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::CreateNode,
             }),
             Binding::Release(SwitchBinding {
@@ -439,6 +532,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: dbl_click,
                 pointer_data: None,
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::EditNode,
             }),
             Binding::Press(SwitchBinding {
@@ -446,6 +543,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: (),
                 pointer_data: (),
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::StartSelection,
             }),
             //
@@ -454,6 +555,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: None,
                 pointer_data: None,
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::CancelSelection,
             }),
             Binding::Release(SwitchBinding {
@@ -461,6 +566,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: None,
                 pointer_data: Some(PointerChangeEventData::DragEnd),
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::EndSelection,
             }),
             //
@@ -469,6 +578,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: click, // FIXME
                 pointer_data: None,
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::CancelSelection,
             }),
             Binding::Release(SwitchBinding {
@@ -476,6 +589,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: click, // FIXME
                 pointer_data: Some(PointerChangeEventData::DragEnd),
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::EndSelection,
             }),
             Binding::Release(SwitchBinding {
@@ -483,6 +600,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: dbl_click, // FIXME
                 pointer_data: None,
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::CancelSelection,
             }),
             Binding::Release(SwitchBinding {
@@ -490,6 +611,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: dbl_click, // FIXME
                 pointer_data: Some(PointerChangeEventData::DragEnd),
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::EndSelection,
             }),
             //
@@ -498,6 +623,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: (),
                 pointer_data: (),
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::StartMove,
             }),
             Binding::Release(SwitchBinding {
@@ -505,6 +634,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: None,
                 pointer_data: None,
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::CancelMove,
             }),
             Binding::Release(SwitchBinding {
@@ -512,6 +645,10 @@ fn test_chain() {
                 modifiers: Modifiers::new(),
                 timed_data: None,
                 pointer_data: Some(PointerChangeEventData::DragEnd),
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::EndMove,
             }),
             //
@@ -521,6 +658,10 @@ fn test_chain() {
                     kind: PointerMoveEventKind::DragMove,
                 },
                 modifiers: Modifiers::new(),
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::ContinueSelection,
             }),
             Binding::Coords(CoordsBinding {
@@ -529,6 +670,10 @@ fn test_chain() {
                     kind: PointerMoveEventKind::DragStart, // FIXME
                 },
                 modifiers: Modifiers::new(),
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::ContinueSelection,
             }),
             Binding::Coords(CoordsBinding {
@@ -537,6 +682,10 @@ fn test_chain() {
                     kind: PointerMoveEventKind::DragMove,
                 },
                 modifiers: Modifiers::new(),
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::ContinueMove,
             }),
             Binding::Coords(CoordsBinding {
@@ -545,6 +694,10 @@ fn test_chain() {
                     kind: PointerMoveEventKind::DragStart, // FIXME
                 },
                 modifiers: Modifiers::new(),
+                when: None,
+                target: None,
+                priority: 0,
+                propagation: BindingPropagation::Consume,
                 event: PointerAppEventBuilder::ContinueMove,
             }),
         ]
@@ -555,23 +708,53 @@ fn test_chain() {
     let mapping = GlobalMapping {
         keyboard: keyboard_mapping,
         mouse: mouse_mapping,
+        touch: TouchMapping::default(),
+        pen: TouchMapping::default(),
     };
 
     let mapping_cache = GlobalMappingCache::from_mapping(mapping);
 
-    let mut global_state = GlobalState::new(
-        Modifiers::default(),
+    let timed_policy = TimedPolicy::new(1000, 300, 3);
+
+    let keyboard = KeyboardLane::new(
         KeyboardCoordsState::with_coords(KeyboardCoords),
-        MouseCoordsState::with_coords(MouseCoords(0, 0)),
-        KeyboardTimedState::default(),
-        MouseTimedState::default(),
+        KeyboardTimedState::new(TimedPolicies::new(timed_policy)),
         KeyboardLongPressScheduler::default(),
         KeyboardClickExactScheduler::default(),
+        KeyboardPointerState::default(),
+        KeyboardSequenceState::new(1000),
+        KeyboardSequenceScheduler::default(),
+        KeyboardRepeatState::new(None),
+        KeyboardRepeatScheduler::default(),
+        KeyboardAnalogState::default(),
+    );
+    let mouse = MouseLane::new(
+        MouseCoordsState::with_coords(MouseCoords(0, 0)),
+        MouseTimedState::new(TimedPolicies::new(timed_policy)),
         MouseLongPressScheduler::default(),
         MouseClickExactScheduler::default(),
-        KeyboardPointerState::default(),
-        MousePointerState::default(),
+        MousePointerState::new(DragPolicy::new(5.0, 0.0, None)),
+        MouseAxisState::new(1.0),
+        MouseKineticScheduler::default(),
+    );
+    let touch = TouchLane::new(
+        TouchCoordsState::with_coords(MouseCoords(0, 0)),
+        TouchTimedState::new(TimedPolicies::new(timed_policy)),
+        TouchLongPressScheduler::default(),
+        TouchClickExactScheduler::default(),
+        TouchPointerState::default(),
+        TouchGestureState::default(),
+        TouchKineticScheduler::default(),
     );
+    let pen = PenLane::new(
+        PenCoordsState::with_coords(MouseCoords(0, 0)),
+        PenTimedState::new(TimedPolicies::new(timed_policy)),
+        PenLongPressScheduler::default(),
+        PenClickExactScheduler::default(),
+        PenPointerState::default(),
+        PenState::default(),
+    );
+    let mut global_state = GlobalState::new(Modifiers::default(), keyboard, mouse, touch, pen);
 
     #[derive(Clone, Debug)]
     enum RawEvent {
@@ -654,8 +837,7 @@ fn test_chain() {
     for event in events {
         println!("St: {:?}", global_state);
         println!("Co: {:?}", context);
-        let result =
-            global_state.with_timeout(event.time() - 1000, event.time() - 300, &mapping_cache);
+        let result = global_state.with_timeout(event.time(), &mapping_cache);
         println!("Ti: {:?}", event.time());
         println!("BiKeLo: {:?}", result.keyboard_long_press);
         println!("BiKeCl: {:?}", result.keyboard_click_exact);
@@ -686,8 +868,7 @@ fn test_chain() {
                 (None, result.bindings.into_iter().collect(), vec![])
             }
             RawEvent::KeyboardCoords(event) => {
-                let result =
-                    global_state.with_keyboard_coords_event(event, &mapping_cache, |a, b| a == b);
+                let result = global_state.with_keyboard_coords_event(event, &mapping_cache);
                 (None, result.bindings, vec![])
             }
             RawEvent::MousePress(event) => {
@@ -711,10 +892,7 @@ fn test_chain() {
                 (None, vec![], result.bindings.into_iter().collect())
             }
             RawEvent::MouseCoords(event) => {
-                let result =
-                    global_state.with_mouse_coords_event(event, &mapping_cache, |lhs, rhs| {
-                        (lhs.0 - rhs.0).pow(2) + (lhs.1 - rhs.1).pow(2) >= 5 * 5
-                    });
+                let result = global_state.with_mouse_coords_event(event, &mapping_cache);
                 (None, vec![], result.bindings)
             }
         };
@@ -770,3 +948,2658 @@ fn test_chain() {
 
     panic!();
 }
+
+#[test]
+fn test_timed_policy() {
+    use input_core::*;
+
+    let mut policies = TimedPolicies::new(TimedPolicy::new(1000, 300, 2));
+    let _ = policies.set_switch_policy("Space", TimedPolicy::new(500, 200, 2));
+    let mut state = TimedState::new(policies);
+
+    assert_eq!(state.long_press_deadline(&"Lmb", 10_i64), 1010);
+    assert_eq!(state.long_press_deadline(&"Space", 10_i64), 510);
+
+    for expected_clicks in [1, 2, 1] {
+        let _ = state.on_press_event("Lmb").unwrap();
+        let (data, _) = state.on_release_event("Lmb").unwrap().unwrap();
+        assert_eq!(data.num_possible_clicks, expected_clicks);
+
+        let deadline = state.click_exact_deadline(&"Lmb", 10_i64, &data);
+        if expected_clicks == 2 {
+            assert_eq!(deadline, 10);
+        } else {
+            assert_eq!(deadline, 310);
+        }
+    }
+}
+
+mod str_state {
+    use input_core::*;
+    use input_more::*;
+
+    pub type Mapping = input_more::Mapping<&'static str, (), &'static str, &'static str>;
+    pub type Coords = (f64, f64);
+    type Scheduler<Re> = DeviceSchedulerState<i64, &'static str, &'static str, Coords, Re>;
+    type Timed = TimedState<&'static str, i64>;
+    type Pointer = PointerState<&'static str, Coords, i64, i64>;
+    type Gesture = GestureState<&'static str, Coords>;
+    type Sequence = SequenceState<SequenceStep<&'static str, &'static str>, i64>;
+    type Repeat = RepeatState<&'static str, i64>;
+    type Axis = AxisState<()>;
+    type Analog = AnalogState<(), &'static str>;
+    type Pen = PenState<&'static str>;
+
+    pub type State = GlobalState<
+        Modifiers<&'static str>,
+        KeyboardLane<
+            CoordsState<Coords>,
+            Timed,
+            Scheduler<LongPressHandleRequest>,
+            Scheduler<ClickExactHandleRequest>,
+            Pointer,
+            Sequence,
+            SequenceSchedulerState<i64, ()>,
+            Repeat,
+            RepeatSchedulerState<i64, &'static str>,
+            Analog,
+        >,
+        MouseLane<
+            CoordsState<Coords>,
+            Timed,
+            Scheduler<LongPressHandleRequest>,
+            Scheduler<ClickExactHandleRequest>,
+            Pointer,
+            Axis,
+            Scheduler<KineticHandleRequest>,
+        >,
+        TouchLane<
+            CoordsState<Coords>,
+            Timed,
+            Scheduler<LongPressHandleRequest>,
+            Scheduler<ClickExactHandleRequest>,
+            Pointer,
+            Gesture,
+            Scheduler<KineticHandleRequest>,
+        >,
+        PenLane<
+            CoordsState<Coords>,
+            Timed,
+            Scheduler<LongPressHandleRequest>,
+            Scheduler<ClickExactHandleRequest>,
+            Pointer,
+            Pen,
+        >,
+    >;
+
+    pub fn new_state() -> State {
+        let timed_policy = TimedPolicy::new(1000, 300, 3);
+        let keyboard = KeyboardLane::new(
+            CoordsState::with_coords((0.0, 0.0)),
+            Timed::new(TimedPolicies::new(timed_policy)),
+            Scheduler::default(),
+            Scheduler::default(),
+            Pointer::default(),
+            Sequence::new(1000),
+            SequenceSchedulerState::default(),
+            Repeat::new(None),
+            RepeatSchedulerState::default(),
+            Analog::default(),
+        );
+        let mouse = MouseLane::new(
+            CoordsState::with_coords((0.0, 0.0)),
+            Timed::new(TimedPolicies::new(timed_policy)),
+            Scheduler::default(),
+            Scheduler::default(),
+            Pointer::default(),
+            Axis::new(1.0),
+            Scheduler::default(),
+        );
+        let touch = TouchLane::new(
+            CoordsState::with_coords((0.0, 0.0)),
+            Timed::new(TimedPolicies::new(timed_policy)),
+            Scheduler::default(),
+            Scheduler::default(),
+            Pointer::default(),
+            Gesture::default(),
+            Scheduler::default(),
+        );
+        let pen = PenLane::new(
+            CoordsState::with_coords((0.0, 0.0)),
+            Timed::new(TimedPolicies::new(timed_policy)),
+            Scheduler::default(),
+            Scheduler::default(),
+            Pointer::default(),
+            Pen::default(),
+        );
+        GlobalState::new(Modifiers::default(), keyboard, mouse, touch, pen)
+    }
+}
+
+#[test]
+fn test_touch_lane() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+
+    use str_state::Mapping;
+
+    let touch_mapping = Mapping::new(HashSet::from([
+        Binding::Press(SwitchBinding::new(
+            "Touch0",
+            Modifiers::new(),
+            (),
+            (),
+            "Touch0Press",
+        )),
+        Binding::Coords(CoordsBinding::new(
+            PointerMoveEventData {
+                switch: "Touch1",
+                kind: PointerMoveEventKind::DragStart,
+            },
+            Modifiers::new(),
+            "Touch1DragStart",
+        )),
+        Binding::Release(SwitchBinding::new(
+            "Touch1",
+            Modifiers::new(),
+            Some(TimedReleaseEventData::new(TimedReleaseEventKind::Click, 1)),
+            Some(PointerChangeEventData::DragEnd),
+            "Touch1DragEnd",
+        )),
+        Binding::Gesture(CoordsBinding::new(
+            GestureEventKind::PinchStart,
+            Modifiers::new(),
+            "PinchStart",
+        )),
+        Binding::Gesture(CoordsBinding::new(
+            GestureEventKind::Pinch,
+            Modifiers::new(),
+            "Pinch",
+        )),
+        Binding::Gesture(CoordsBinding::new(
+            GestureEventKind::PinchEnd,
+            Modifiers::new(),
+            "PinchEnd",
+        )),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: Mapping::default(),
+        mouse: Mapping::default(),
+        touch: touch_mapping,
+        pen: Mapping::default(),
+    });
+
+    let mut global_state = str_state::new_state();
+
+    let build = |bindings: TouchBindings<'_, _, &'static str, (f64, f64)>| {
+        let pointer = bindings
+            .pointer
+            .map(|(bindings, _)| bindings.build(|event| Some(*event)))
+            .unwrap_or_default();
+        let gestures: Vec<_> = bindings
+            .gestures
+            .into_iter()
+            .flat_map(|(bindings, data)| bindings.build(|event| Some((*event, data.scale))))
+            .collect();
+        (pointer, gestures)
+    };
+    global_state
+        .touch
+        .pointer_state
+        .set_policy(DragPolicy::new(50.0, 0.0, None));
+
+    let result = global_state
+        .with_touch_start_event(TouchEvent::new(0, "Touch0", (0.0, 0.0)), &mapping_cache);
+    assert_eq!(build(result.bindings), (vec!["Touch0Press"], vec![]));
+    let result = global_state
+        .with_touch_start_event(TouchEvent::new(10, "Touch1", (100.0, 0.0)), &mapping_cache);
+    assert_eq!(build(result.bindings), (vec![], vec![("PinchStart", 1.0)]));
+
+    let result = global_state
+        .with_touch_move_event(TouchEvent::new(20, "Touch0", (75.0, 0.0)), &mapping_cache);
+    assert_eq!(build(result.bindings), (vec![], vec![("Pinch", 0.25)]));
+    let result = global_state
+        .with_touch_move_event(TouchEvent::new(30, "Touch1", (125.0, 0.0)), &mapping_cache);
+    assert_eq!(build(result.bindings), (vec![], vec![("Pinch", 2.0)]));
+    let result = global_state
+        .with_touch_move_event(TouchEvent::new(40, "Touch1", (175.0, 0.0)), &mapping_cache);
+    assert_eq!(
+        build(result.bindings),
+        (vec!["Touch1DragStart"], vec![("Pinch", 2.0)])
+    );
+
+    let result = global_state
+        .with_touch_end_event(TouchEvent::new(50, "Touch0", (75.0, 0.0)), &mapping_cache);
+    assert_eq!(build(result.bindings), (vec![], vec![("PinchEnd", 1.0)]));
+    let result = global_state
+        .with_touch_end_event(TouchEvent::new(60, "Touch1", (175.0, 0.0)), &mapping_cache);
+    assert_eq!(build(result.bindings), (vec!["Touch1DragEnd"], vec![]));
+}
+
+#[test]
+fn test_primary_touch() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+
+    use str_state::Mapping;
+
+    let coords = |switch, kind, event| {
+        Binding::Coords(CoordsBinding::new(
+            PointerMoveEventData { switch, kind },
+            Modifiers::new(),
+            event,
+        ))
+    };
+    let touch_mapping = Mapping::new(HashSet::from([
+        Binding::Press(SwitchBinding::new(
+            "Touch",
+            Modifiers::new(),
+            (),
+            (),
+            "TouchPress",
+        )),
+        Binding::Press(SwitchBinding::new(
+            "5",
+            Modifiers::new(),
+            (),
+            (),
+            "SecondPress",
+        )),
+        coords("Touch", PointerMoveEventKind::DragStart, "TouchDragStart"),
+        coords("Touch", PointerMoveEventKind::DragCancel, "TouchDragCancel"),
+        coords("5", PointerMoveEventKind::DragStart, "SecondDragStart"),
+        coords("5", PointerMoveEventKind::DragCancel, "SecondDragCancel"),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: Mapping::default(),
+        mouse: Mapping::default(),
+        touch: touch_mapping,
+        pen: Mapping::default(),
+    });
+
+    let mut global_state = str_state::new_state();
+    global_state
+        .touch
+        .pointer_state
+        .set_policy(DragPolicy::new(50.0, 0.0, None));
+    global_state
+        .touch
+        .gesture_state
+        .set_primary_switch(Some("Touch"));
+
+    let build = |bindings: TouchBindings<'_, _, &'static str, (f64, f64)>| {
+        bindings
+            .pointer
+            .map(|(bindings, _)| bindings.build(|event| Some(*event)))
+            .unwrap_or_default()
+    };
+
+    let result =
+        global_state.with_touch_start_event(TouchEvent::new(0, "3", (0.0, 0.0)), &mapping_cache);
+    assert_eq!(build(result.bindings), vec!["TouchPress"]);
+    let _ = global_state.with_touch_end_event(TouchEvent::new(10, "3", (0.0, 0.0)), &mapping_cache);
+
+    let result =
+        global_state.with_touch_start_event(TouchEvent::new(20, "7", (0.0, 0.0)), &mapping_cache);
+    assert_eq!(build(result.bindings), vec!["TouchPress"]);
+    let result =
+        global_state.with_touch_start_event(TouchEvent::new(30, "5", (100.0, 0.0)), &mapping_cache);
+    assert_eq!(build(result.bindings), vec!["SecondPress"]);
+
+    let result =
+        global_state.with_touch_move_event(TouchEvent::new(40, "7", (60.0, 0.0)), &mapping_cache);
+    assert_eq!(build(result.bindings), vec!["TouchDragStart"]);
+    let result =
+        global_state.with_touch_move_event(TouchEvent::new(50, "5", (200.0, 0.0)), &mapping_cache);
+    assert_eq!(build(result.bindings), vec!["SecondDragStart"]);
+
+    let result = global_state.with_drag_cancel_event(&mapping_cache);
+    let mut events: Vec<_> = result
+        .touch
+        .into_iter()
+        .flat_map(|(bindings, coords)| bindings.build(|event| Some((*event, coords))))
+        .collect();
+    events.sort_by(|a, b| a.0.cmp(b.0));
+    assert_eq!(
+        events,
+        vec![
+            ("SecondDragCancel", (200.0, 0.0)),
+            ("TouchDragCancel", (60.0, 0.0))
+        ]
+    );
+
+    // Once the primary touch lifts, touches are only matched by their ids
+    // until all of them are up.
+    let _ =
+        global_state.with_touch_end_event(TouchEvent::new(60, "7", (60.0, 0.0)), &mapping_cache);
+    let result =
+        global_state.with_touch_start_event(TouchEvent::new(70, "8", (0.0, 0.0)), &mapping_cache);
+    assert!(build(result.bindings).is_empty());
+    let _ = global_state.with_touch_end_event(TouchEvent::new(80, "8", (0.0, 0.0)), &mapping_cache);
+    let _ =
+        global_state.with_touch_end_event(TouchEvent::new(90, "5", (200.0, 0.0)), &mapping_cache);
+    let result =
+        global_state.with_touch_start_event(TouchEvent::new(100, "8", (0.0, 0.0)), &mapping_cache);
+    assert_eq!(build(result.bindings), vec!["TouchPress"]);
+}
+
+#[test]
+fn test_gesture_state() {
+    use core::f64::consts::FRAC_PI_2;
+
+    use input_core::*;
+
+    let mut state = GestureState::new();
+    assert_eq!(state.on_press_event(0, (0.0, 0.0)).unwrap(), None);
+    let data = state.on_press_event(1, (10.0, 0.0)).unwrap().unwrap();
+    assert_eq!(data.kind, GestureEventKind::PinchStart);
+    assert!(state.on_press_event(1, (10.0, 0.0)).is_err());
+
+    let events = state.on_move_event(&1, (0.0, 20.0));
+    let kinds: Vec<_> = events.iter().map(|data| data.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            GestureEventKind::Pinch,
+            GestureEventKind::Pan,
+            GestureEventKind::Rotate
+        ]
+    );
+    assert!((events[0].scale - 2.0).abs() < 1e-9);
+    assert_eq!(events[1].translation, (-5.0, 10.0));
+    assert!((events[2].angle - FRAC_PI_2).abs() < 1e-9);
+
+    assert_eq!(state.on_press_event(2, (5.0, 5.0)).unwrap(), None);
+    let events = state.on_move_event(&2, (50.0, 50.0));
+    let pan = events
+        .iter()
+        .find(|data| data.kind == GestureEventKind::Pan)
+        .unwrap();
+    assert!((pan.translation.0 - 15.0).abs() < 1e-9);
+    assert!((pan.translation.1 - 15.0).abs() < 1e-9);
+
+    assert_eq!(state.on_release_event(&0).unwrap(), None);
+    let data = state.on_release_event(&1).unwrap().unwrap();
+    assert_eq!(data.kind, GestureEventKind::PinchEnd);
+    assert!(state.on_release_event(&1).is_err());
+
+    let mut state = GestureState::new().with_policy(GesturePolicy::new(10.0, 0.2, 0.5));
+    let _ = state.on_press_event(0, (0.0, 0.0)).unwrap();
+    let _ = state.on_press_event(1, (10.0, 0.0)).unwrap();
+    assert!(state.on_move_event(&1, (11.0, 0.0)).is_empty());
+
+    let events = state.on_move_event(&1, (13.0, 0.0));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].kind, GestureEventKind::Pinch);
+    assert!((events[0].scale - 1.3).abs() < 1e-9);
+
+    let events = state.on_move_event(&1, (13.5, 0.0));
+    assert_eq!(events.len(), 1);
+    assert!((events[0].scale - 13.5 / 13.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_key_sequence() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    let ctrl = || Modifiers::from(std::sync::Arc::new(["Ctrl"].into_iter().collect()));
+    let chord = |switch, event| {
+        Binding::Sequence(SequenceBinding {
+            prefix: vec![SequenceStep::new("K", ctrl())],
+            switch,
+            modifiers: ctrl(),
+            when: None,
+            priority: 0,
+            propagation: BindingPropagation::Consume,
+            event,
+        })
+    };
+    let plain = |switch, event| Binding::Press(SwitchBinding::new(switch, ctrl(), (), (), event));
+    let keyboard_mapping = Mapping::new(HashSet::from([
+        chord("C", "Comment"),
+        chord("U", "Uncomment"),
+        plain("K", "Kill"),
+        plain("C", "Copy"),
+        Binding::Release(SwitchBinding::new(
+            "C",
+            ctrl(),
+            Some(TimedReleaseEventData::new(TimedReleaseEventKind::Click, 1)),
+            None,
+            "CopyClick",
+        )),
+        Binding::LongPress(SwitchBinding::new(
+            "C",
+            ctrl(),
+            TimedEventData::new(TimedLongPressEventKind::LongPress, 1),
+            (),
+            "CopyHold",
+        )),
+        Binding::Sequence(SequenceBinding {
+            prefix: Vec::new(),
+            switch: "G",
+            modifiers: ctrl(),
+            when: None,
+            priority: 0,
+            propagation: BindingPropagation::Consume,
+            event: "Goto",
+        }),
+        Binding::Sequence(SequenceBinding {
+            prefix: vec![SequenceStep::new("G", ctrl())],
+            switch: "G",
+            modifiers: ctrl(),
+            when: None,
+            priority: 0,
+            propagation: BindingPropagation::Consume,
+            event: "GotoTop",
+        }),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: keyboard_mapping,
+        mouse: Mapping::default(),
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+    let mut global_state = str_state::new_state();
+
+    let press = |global_state: &mut str_state::State, time, switch| {
+        let result =
+            global_state.with_keyboard_press_event(SwitchEvent::new(time, switch), &mapping_cache);
+        let _ = global_state
+            .with_keyboard_release_event(SwitchEvent::new(time, switch), &mapping_cache);
+        let events: Vec<_> = result
+            .bindings
+            .into_iter()
+            .flat_map(|(bindings, _)| bindings.build(|event| Some(*event)))
+            .collect();
+        (result.scheduled, events)
+    };
+
+    let _ = global_state.with_keyboard_press_event(SwitchEvent::new(0, "Ctrl"), &mapping_cache);
+
+    assert_eq!(press(&mut global_state, 10, "K"), (Some(1010), vec![]));
+    assert_eq!(
+        global_state.keyboard_pending_sequence(),
+        [SequenceStep::new("K", ctrl())]
+    );
+    assert_eq!(press(&mut global_state, 20, "C").1, vec!["Comment"]);
+    assert!(global_state.keyboard_pending_sequence().is_empty());
+
+    assert!(press(&mut global_state, 30, "K").1.is_empty());
+    assert!(press(&mut global_state, 40, "X").1.is_empty());
+    assert!(global_state.keyboard_pending_sequence().is_empty());
+    assert!(press(&mut global_state, 50, "U").1.is_empty());
+
+    assert!(press(&mut global_state, 60, "K").1.is_empty());
+    let _ = global_state.with_timeout(1060, &mapping_cache);
+    assert!(global_state.keyboard_pending_sequence().is_empty());
+    assert!(press(&mut global_state, 1070, "U").1.is_empty());
+
+    assert!(press(&mut global_state, 1080, "K").1.is_empty());
+    assert_eq!(press(&mut global_state, 1090, "U").1, vec!["Uncomment"]);
+
+    // Plain bindings fire only outside of a sequence.
+    assert_eq!(press(&mut global_state, 1100, "C").1, vec!["Copy"]);
+    assert!(press(&mut global_state, 1110, "K").1.is_empty());
+    let _ = global_state.with_timeout(2110, &mapping_cache);
+    assert_eq!(press(&mut global_state, 2120, "C").1, vec!["Copy"]);
+
+    // A sequence that a longer one extends waits for the next step.
+    assert!(press(&mut global_state, 2200, "G").1.is_empty());
+    assert_eq!(press(&mut global_state, 2210, "G").1, vec!["GotoTop"]);
+    assert!(press(&mut global_state, 2300, "G").1.is_empty());
+    let result = global_state.with_timeout(3300, &mapping_cache);
+    let events: Vec<_> = result
+        .keyboard_sequence
+        .into_iter()
+        .flat_map(|(bindings, _)| bindings.build(|event| Some(*event)))
+        .collect();
+    assert_eq!(events, vec!["Goto"]);
+    assert!(press(&mut global_state, 3400, "G").1.is_empty());
+    assert_eq!(press(&mut global_state, 3410, "C").1, vec!["Goto", "Copy"]);
+    assert!(global_state.keyboard_pending_sequence().is_empty());
+
+    // A key consumed by a sequence step does not click or long press either.
+    let release = |global_state: &mut str_state::State, time, switch| -> Vec<_> {
+        global_state
+            .with_keyboard_release_event(SwitchEvent::new(time, switch), &mapping_cache)
+            .bindings
+            .into_iter()
+            .flat_map(|(bindings, _)| bindings.build(|event| Some(*event)))
+            .collect()
+    };
+    let timeout = |global_state: &mut str_state::State, time| -> Vec<_> {
+        let result = global_state.with_timeout(time, &mapping_cache);
+        result
+            .keyboard_long_press
+            .into_iter()
+            .chain(result.keyboard_click_exact)
+            .flat_map(|(bindings, _)| bindings.build(|event| Some(*event)))
+            .collect()
+    };
+    let _ = timeout(&mut global_state, 5000);
+    assert!(press(&mut global_state, 5000, "K").1.is_empty());
+    let result =
+        global_state.with_keyboard_press_event(SwitchEvent::new(5010, "C"), &mapping_cache);
+    assert_eq!(result.bindings.len(), 1);
+    assert!(timeout(&mut global_state, 6100).is_empty());
+    assert!(release(&mut global_state, 6200, "C").is_empty());
+    assert!(timeout(&mut global_state, 7000).is_empty());
+
+    let _ = global_state.with_keyboard_press_event(SwitchEvent::new(7000, "C"), &mapping_cache);
+    assert_eq!(release(&mut global_state, 7010, "C"), vec!["CopyClick"]);
+}
+
+#[test]
+fn test_context() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    let expr: ContextExpr = "editorHasSelection && !findInputFocussed".parse().unwrap();
+    assert_eq!(
+        expr,
+        ContextExpr::flag("editorHasSelection").and(!ContextExpr::flag("findInputFocussed"))
+    );
+    assert_eq!(expr.to_string(), "editorHasSelection && !findInputFocussed");
+    let expr: ContextExpr = "(a || b) && mode != 'Node Edit'".parse().unwrap();
+    assert_eq!(expr.to_string().parse::<ContextExpr>(), Ok(expr));
+    assert_eq!(
+        "a && ".parse::<ContextExpr>(),
+        Err(ContextExprParseError::UnexpectedEnd(5))
+    );
+    assert_eq!(
+        "a == 'b".parse::<ContextExpr>(),
+        Err(ContextExprParseError::UnterminatedString(5))
+    );
+
+    let press = |when: &str, event| {
+        Binding::Press(
+            SwitchBinding::new("Delete", Modifiers::new(), (), (), event)
+                .with_when(when.parse().unwrap()),
+        )
+    };
+    let keyboard_mapping = Mapping::new(HashSet::from([
+        press("canvas && mode == NodeEdit", "DeleteText"),
+        press("canvas && mode != NodeEdit", "DeleteNode"),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: keyboard_mapping,
+        mouse: Mapping::default(),
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+    let mut global_state = str_state::new_state();
+
+    let press = |global_state: &mut str_state::State, time| {
+        let result = global_state
+            .with_keyboard_press_event(SwitchEvent::new(time, "Delete"), &mapping_cache);
+        let _ = global_state
+            .with_keyboard_release_event(SwitchEvent::new(time, "Delete"), &mapping_cache);
+        let events: Vec<_> = result
+            .bindings
+            .into_iter()
+            .flat_map(|(bindings, _)| bindings.build(|event| Some(*event)))
+            .collect();
+        events
+    };
+
+    assert!(press(&mut global_state, 0).is_empty());
+    global_state.context.push(ContextLayer::new("canvas"));
+    assert_eq!(press(&mut global_state, 1000), vec!["DeleteNode"]);
+    global_state
+        .context
+        .push(ContextLayer::new("node").with_value("mode", "NodeEdit"));
+    assert_eq!(press(&mut global_state, 2000), vec!["DeleteText"]);
+    let _ = global_state.context.pop();
+    assert_eq!(press(&mut global_state, 3000), vec!["DeleteNode"]);
+}
+
+#[test]
+fn test_binding_priority() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    let modifiers = |switches: &[&'static str]| {
+        Modifiers::from(std::sync::Arc::new(switches.iter().copied().collect()))
+    };
+    let press = |switches: &[&'static str], priority, propagation, event| {
+        Binding::Press(
+            SwitchBinding::new("A", modifiers(switches), (), (), event)
+                .with_priority(priority)
+                .with_propagation(propagation),
+        )
+    };
+    let keyboard_mapping = Mapping::new(HashSet::from([
+        press(&["Ctrl"], 0, BindingPropagation::Consume, "CtrlA"),
+        press(
+            &["Ctrl", "Shift"],
+            0,
+            BindingPropagation::Consume,
+            "CtrlShiftA",
+        ),
+        press(&["Shift"], 0, BindingPropagation::Consume, "ShiftA"),
+        press(&[], 10, BindingPropagation::Passthrough, "Log"),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: keyboard_mapping,
+        mouse: Mapping::default(),
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+    let mut global_state = str_state::new_state();
+
+    let mut time = 0;
+    let mut press = |global_state: &mut str_state::State, disabled: &[&str]| {
+        time += 1000;
+        let result =
+            global_state.with_keyboard_press_event(SwitchEvent::new(time, "A"), &mapping_cache);
+        let _ =
+            global_state.with_keyboard_release_event(SwitchEvent::new(time, "A"), &mapping_cache);
+        let (bindings, _) = result.bindings.into_iter().next().unwrap();
+        let (events, report) = bindings.build_with_report(|event| {
+            if disabled.contains(event) {
+                None
+            } else {
+                Some(*event)
+            }
+        });
+        let outcomes: Vec<_> = report
+            .entries()
+            .iter()
+            .map(|entry| (entry.binding.event, entry.outcome))
+            .collect();
+        (events, outcomes)
+    };
+
+    let _ = global_state.with_keyboard_press_event(SwitchEvent::new(0, "Ctrl"), &mapping_cache);
+    let _ = global_state.with_keyboard_press_event(SwitchEvent::new(0, "Shift"), &mapping_cache);
+
+    assert_eq!(
+        press(&mut global_state, &[]),
+        (
+            vec!["Log", "CtrlShiftA"],
+            vec![
+                ("Log", BindingOutcome::Applied),
+                ("CtrlShiftA", BindingOutcome::Applied),
+                ("CtrlA", BindingOutcome::Shadowed),
+                ("ShiftA", BindingOutcome::Shadowed),
+            ]
+        )
+    );
+    assert_eq!(
+        press(&mut global_state, &["ShiftA", "CtrlShiftA"]),
+        (
+            vec!["Log", "CtrlA"],
+            vec![
+                ("Log", BindingOutcome::Applied),
+                ("CtrlShiftA", BindingOutcome::Disabled),
+                ("CtrlA", BindingOutcome::Applied),
+                ("ShiftA", BindingOutcome::Disabled),
+            ]
+        )
+    );
+    assert_eq!(
+        press(&mut global_state, &["CtrlShiftA"]),
+        (
+            vec!["Log"],
+            vec![
+                ("Log", BindingOutcome::Applied),
+                ("CtrlShiftA", BindingOutcome::Disabled),
+                ("CtrlA", BindingOutcome::Conflicted),
+                ("ShiftA", BindingOutcome::Conflicted),
+            ]
+        )
+    );
+}
+
+#[test]
+fn test_keymap() {
+    use std::collections::HashSet;
+    use std::str::FromStr;
+
+    use input_core::*;
+    use input_more::*;
+
+    #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+    enum Key {
+        Ctrl,
+        Alt,
+        C,
+        D,
+        K,
+        Lmb,
+    }
+
+    impl FromStr for Key {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "Ctrl" => Ok(Self::Ctrl),
+                "Alt" => Ok(Self::Alt),
+                "c" => Ok(Self::C),
+                "d" => Ok(Self::D),
+                "k" => Ok(Self::K),
+                "Lmb" => Ok(Self::Lmb),
+                _ => Err(()),
+            }
+        }
+    }
+
+    type KeyMapping = Mapping<Key, String, Key, String>;
+    type KeyMappingCache = GlobalMappingCache<
+        DeviceMappingCache<Key, String, Key, String>,
+        DeviceMappingCache<Key, String, Key, String>,
+        DeviceMappingCache<Key, String, Key, String>,
+        DeviceMappingCache<Key, String, Key, String>,
+        MappingModifiersCache<Key>,
+    >;
+
+    let modifiers =
+        |switches: &[Key]| Modifiers::from(std::sync::Arc::new(switches.iter().copied().collect()));
+
+    let keymap = r#"[
+        { "key": "Ctrl+Alt+d", "command": "Duplicate", "when": "canvas && !textFocus" },
+        { "key": "Ctrl+k Ctrl+c", "command": "Comment", "priority": 1, "passthrough": true },
+        { "device": "mouse", "key": "Ctrl+Lmb", "on": "click", "clicks": 2, "command": "EditNode" },
+        { "device": "mouse", "key": "Ctrl+Wheel", "on": "axis", "command": "Zoom" }
+    ]"#;
+    let mapping =
+        GlobalMapping::<KeyMapping, KeyMapping, KeyMapping, KeyMapping>::from_keymap(keymap)
+            .unwrap();
+    assert_eq!(
+        mapping.keyboard.bindings,
+        HashSet::from([
+            Binding::Press(
+                SwitchBinding::new(
+                    Key::D,
+                    modifiers(&[Key::Ctrl, Key::Alt]),
+                    (),
+                    (),
+                    "Duplicate".to_owned()
+                )
+                .with_when("canvas && !textFocus".parse().unwrap())
+            ),
+            Binding::Sequence(SequenceBinding {
+                prefix: vec![SequenceStep::new(Key::K, modifiers(&[Key::Ctrl]))],
+                switch: Key::C,
+                modifiers: modifiers(&[Key::Ctrl]),
+                when: None,
+                priority: 1,
+                propagation: BindingPropagation::Passthrough,
+                event: "Comment".to_owned(),
+            }),
+        ])
+    );
+    assert_eq!(
+        mapping.mouse.bindings,
+        HashSet::from([
+            Binding::Release(SwitchBinding::new(
+                Key::Lmb,
+                modifiers(&[Key::Ctrl]),
+                Some(TimedEventData::new(TimedReleaseEventKind::Click, 2)),
+                None,
+                "EditNode".to_owned()
+            )),
+            Binding::Axis(CoordsBinding::new(
+                "Wheel".to_owned(),
+                modifiers(&[Key::Ctrl]),
+                "Zoom".to_owned()
+            )),
+        ])
+    );
+    assert!(mapping.touch.bindings.is_empty());
+
+    let mut mapping_cache = KeyMappingCache::from_keymap(keymap).unwrap();
+    assert!(mapping_cache.keyboard().filter_by_switch(&Key::D).is_some());
+
+    let err = mapping_cache
+        .reload("[\n  { \"key\": \"Ctrl+q\", \"command\": \"Quit\" }\n]")
+        .unwrap_err();
+    assert_eq!((err.line(), err.column()), (2, 40));
+    assert!(err.to_string().contains("Unknown key name \"q\""));
+    assert!(mapping_cache.keyboard().filter_by_switch(&Key::D).is_some());
+
+    let err = mapping_cache
+        .reload("[\n  { \"key\": \"Ctrl+c\", \"when\": \"a &&\", \"command\": \"Copy\" }\n]")
+        .unwrap_err();
+    assert_eq!(err.line(), 2);
+    assert!(err.to_string().contains("Invalid when clause"));
+
+    let err = mapping_cache
+        .reload("[\n  { \"key\": \"c\" \"command\": \"Copy\" }\n]")
+        .unwrap_err();
+    assert_eq!((err.line(), err.column()), (2, 16));
+
+    mapping_cache
+        .reload(r#"[{ "key": "Ctrl+c", "command": "Copy" }]"#)
+        .unwrap();
+    assert!(mapping_cache.keyboard().filter_by_switch(&Key::D).is_none());
+    assert!(mapping_cache.keyboard().filter_by_switch(&Key::C).is_some());
+}
+
+#[test]
+fn test_mapping_analyzer() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    let modifiers = |switches: &[&'static str]| {
+        Modifiers::from(std::sync::Arc::new(switches.iter().copied().collect()))
+    };
+    let press = |switch, switches: &[&'static str], priority, event| {
+        Binding::Press(
+            SwitchBinding::new(switch, modifiers(switches), (), (), event).with_priority(priority),
+        )
+    };
+    let long_press = Binding::LongPress(SwitchBinding::new(
+        "E",
+        Modifiers::new(),
+        TimedEventData::new(TimedLongPressEventKind::LongPress, 1),
+        (),
+        "LongE",
+    ));
+    let click_exact = |switch, kind, event| {
+        Binding::ClickExact(SwitchBinding::new(
+            switch,
+            Modifiers::new(),
+            TimedEventData::new(kind, 1),
+            (),
+            event,
+        ))
+    };
+
+    let mapping = Mapping::new(HashSet::from([
+        press("A", &[], 0, "A1"),
+        press("A", &[], 0, "A2"),
+        press("B", &[], 1, "B"),
+        press("B", &["Ctrl"], 0, "CtrlB"),
+        press("C", &["Ctrl"], 0, "CtrlC"),
+        press("C", &["Shift"], 0, "ShiftC"),
+        press("F", &["Ctrl"], 0, "CtrlF"),
+        press("F", &["Shift"], 0, "ShiftF"),
+        press("F", &["Ctrl", "Shift"], 0, "CtrlShiftF"),
+        press("Ctrl", &[], 0, "CtrlAlone"),
+        long_press,
+        click_exact("E", TimedClickExactEventKind::ClickExact, "ExactE"),
+        click_exact("D", TimedClickExactEventKind::LongClickExact, "LongExactD"),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: mapping.clone(),
+        mouse: Mapping::default(),
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+
+    let report = mapping.analyze(mapping_cache.modifiers());
+    let event = |binding: &Binding<_, _, _, &'static str>| match binding {
+        Binding::Press(binding) => binding.event,
+        Binding::LongPress(binding) => binding.event,
+        Binding::ClickExact(binding) => binding.event,
+        _ => unreachable!(),
+    };
+    let mut issues: Vec<_> = report
+        .issues()
+        .iter()
+        .map(|issue| match *issue {
+            MappingIssue::Duplicate { binding, other } => {
+                ("Duplicate", event(binding), event(other))
+            }
+            MappingIssue::Shadowed { binding, by } => ("Shadowed", event(binding), event(by)),
+            MappingIssue::Ambiguous { binding, other } => {
+                ("Ambiguous", event(binding), event(other))
+            }
+            MappingIssue::ClickExactUnreachable { binding } => {
+                ("ClickExactUnreachable", event(binding), "")
+            }
+            MappingIssue::ModifierAsSwitch { binding, switch } => {
+                ("ModifierAsSwitch", event(binding), *switch)
+            }
+        })
+        .collect();
+    issues.sort_unstable();
+    assert_eq!(
+        issues,
+        [
+            ("Ambiguous", "CtrlC", "ShiftC"),
+            ("ClickExactUnreachable", "LongExactD", ""),
+            ("Duplicate", "A1", "A2"),
+            ("ModifierAsSwitch", "CtrlAlone", "Ctrl"),
+            ("Shadowed", "CtrlB", "B"),
+        ]
+    );
+}
+
+#[test]
+fn test_record_replay() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    type Event = RawEvent<i64, &'static str, (), &'static str, (), &'static str, str_state::Coords>;
+
+    let keyboard_mapping = Mapping::new(HashSet::from([
+        Binding::Press(SwitchBinding::new("A", Modifiers::new(), (), (), "PressA")),
+        Binding::LongPress(SwitchBinding::new(
+            "B",
+            Modifiers::new(),
+            TimedEventData::new(TimedLongPressEventKind::LongPress, 1),
+            (),
+            "LongB",
+        )),
+    ]));
+    let mouse_mapping = Mapping::new(HashSet::from([Binding::Release(SwitchBinding::new(
+        "Lmb",
+        Modifiers::new(),
+        Some(TimedReleaseEventData::new(TimedReleaseEventKind::Click, 1)),
+        None,
+        "ClickLmb",
+    ))]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: keyboard_mapping,
+        mouse: mouse_mapping,
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+
+    let events: Vec<Event> = vec![
+        RawEvent::KeyboardPress(SwitchEvent::new(0, "A")),
+        RawEvent::MouseCoords(CoordsEvent::new(20, (1.0, 1.0))),
+        RawEvent::MousePress(SwitchEvent::new(50, "Lmb")),
+        RawEvent::MouseRelease(SwitchEvent::new(60, "Lmb")),
+        RawEvent::KeyboardRelease(SwitchEvent::new(100, "A")),
+        RawEvent::KeyboardPress(SwitchEvent::new(1000, "B")),
+        RawEvent::KeyboardRelease(SwitchEvent::new(1500, "B")),
+        RawEvent::KeyboardPress(SwitchEvent::new(3000, "B")),
+    ];
+
+    let mut recorder = InputRecorder::new(Vec::new());
+    for event in &events {
+        recorder.record(event).unwrap();
+    }
+    assert_eq!(recorder.num_events(), events.len());
+    let log = String::from_utf8(recorder.into_inner()).unwrap();
+    assert_eq!(
+        log.lines().next(),
+        Some(r#"{"kind":"keyboard_press","time":0,"switch":"A"}"#)
+    );
+
+    let log: &'static str = Box::leak(log.into_boxed_str());
+    let recorded: Vec<Event> = read_input_log(log).unwrap();
+    assert_eq!(recorded, events);
+
+    let replay = |events: Vec<Event>| str_state::new_state().replay(events, &mapping_cache);
+    let replayed = replay(recorded.clone());
+    assert_eq!(
+        replayed,
+        vec![
+            ReplayedEvent::new(0, "PressA"),
+            ReplayedEvent::new(60, "ClickLmb"),
+            ReplayedEvent::new(4000, "LongB"),
+        ]
+    );
+    assert_eq!(replay(recorded), replayed);
+
+    let err = read_input_log::<Event>("\n{\"kind\":\"keyboard_press\",\"time\":0}\n").unwrap_err();
+    assert!(matches!(err, InputLogError::Json { line: 2, .. }));
+}
+
+#[test]
+fn test_drag_policy() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    let release = |timed_data, event| {
+        Binding::Release(SwitchBinding::new(
+            "Lmb",
+            Modifiers::new(),
+            timed_data,
+            None,
+            event,
+        ))
+    };
+    let mouse_mapping = Mapping::new(HashSet::from([
+        release(
+            Some(TimedReleaseEventData::new(TimedReleaseEventKind::Click, 1)),
+            "Click",
+        ),
+        release(None, "Release"),
+        Binding::Coords(CoordsBinding::new(
+            PointerMoveEventData {
+                switch: "Lmb",
+                kind: PointerMoveEventKind::DragStart,
+            },
+            Modifiers::new(),
+            "DragStart",
+        )),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: Mapping::default(),
+        mouse: mouse_mapping,
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+
+    let mut global_state = str_state::new_state();
+    global_state
+        .mouse
+        .pointer_state
+        .set_policy(DragPolicy::new(10.0, 4.0, Some(100)));
+
+    let mut emit = |event| global_state.with_raw_event(event, &mapping_cache);
+    let press = |time| RawEvent::MousePress(SwitchEvent::new(time, "Lmb"));
+    let release = |time| RawEvent::MouseRelease(SwitchEvent::new(time, "Lmb"));
+    let coords = |time, x| RawEvent::MouseCoords(CoordsEvent::new(time, (x, 0.0)));
+    let events = |replayed: Vec<ReplayedEvent<i64, &'static str>>| -> Vec<_> {
+        replayed
+            .into_iter()
+            .filter_map(|event| event.event.into_event())
+            .collect()
+    };
+
+    assert!(events(emit(press(0))).is_empty());
+    assert!(events(emit(coords(50, 12.0))).is_empty());
+    assert!(events(emit(coords(80, 7.0))).is_empty());
+    assert_eq!(events(emit(coords(120, 8.0))), vec!["DragStart"]);
+    assert!(events(emit(release(130))).is_empty());
+
+    assert!(events(emit(press(1000))).is_empty());
+    assert!(events(emit(coords(1010, 20.0))).is_empty());
+    assert!(events(emit(coords(1020, 9.0))).is_empty());
+    assert!(events(emit(coords(1200, 10.0))).is_empty());
+    assert_eq!(events(emit(release(1210))), vec!["Release"]);
+
+    assert!(events(emit(press(3000))).is_empty());
+    assert!(events(emit(coords(3010, 12.0))).is_empty());
+    assert_eq!(events(emit(release(3020))), vec!["Click"]);
+}
+
+#[test]
+fn test_drag_cancel() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    let release = |timed_data, pointer_data, event| {
+        Binding::Release(SwitchBinding::new(
+            "Lmb",
+            Modifiers::new(),
+            timed_data,
+            pointer_data,
+            event,
+        ))
+    };
+    let coords = |kind, event| {
+        Binding::Coords(CoordsBinding::new(
+            PointerMoveEventData {
+                switch: "Lmb",
+                kind,
+            },
+            Modifiers::new(),
+            event,
+        ))
+    };
+    let click = Some(TimedReleaseEventData::new(TimedReleaseEventKind::Click, 1));
+    let mouse_mapping = Mapping::new(HashSet::from([
+        release(click, None, "Click"),
+        release(click, Some(PointerChangeEventData::DragEnd), "DragEnd"),
+        release(None, Some(PointerChangeEventData::DragCancel), "Cancelled"),
+        coords(PointerMoveEventKind::DragStart, "DragStart"),
+        coords(PointerMoveEventKind::DragCancel, "DragCancel"),
+    ]));
+    let keyboard_mapping = Mapping::new(HashSet::from([Binding::Press(SwitchBinding::new(
+        "Escape",
+        Modifiers::new(),
+        (),
+        (),
+        "Escape",
+    ))]))
+    .with_drag_cancel_switches(["Escape"]);
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: keyboard_mapping,
+        mouse: mouse_mapping,
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+
+    let mut global_state = str_state::new_state();
+    let emit = |global_state: &mut str_state::State, event| -> Vec<_> {
+        global_state
+            .with_raw_event(event, &mapping_cache)
+            .into_iter()
+            .filter_map(|event| event.event.into_event())
+            .collect()
+    };
+    let press = |time| RawEvent::MousePress(SwitchEvent::new(time, "Lmb"));
+    let release = |time| RawEvent::MouseRelease(SwitchEvent::new(time, "Lmb"));
+    let coords = |time, x| RawEvent::MouseCoords(CoordsEvent::new(time, (x, 0.0)));
+    let escape = |time| RawEvent::KeyboardPress(SwitchEvent::new(time, "Escape"));
+
+    assert_eq!(emit(&mut global_state, escape(0)), vec!["Escape"]);
+    assert!(emit(&mut global_state, press(10)).is_empty());
+    assert_eq!(emit(&mut global_state, coords(20, 10.0)), vec!["DragStart"]);
+    assert_eq!(
+        emit(&mut global_state, escape(30)),
+        vec!["DragCancel", "Escape"]
+    );
+    assert!(emit(&mut global_state, coords(40, 20.0)).is_empty());
+    assert_eq!(emit(&mut global_state, release(50)), vec!["Cancelled"]);
+
+    assert!(emit(&mut global_state, press(100)).is_empty());
+    assert_eq!(
+        emit(&mut global_state, coords(110, 30.0)),
+        vec!["DragStart"]
+    );
+    let result = global_state.with_drag_cancel_event(&mapping_cache);
+    assert!(result.keyboard.is_empty() && result.touch.is_empty());
+    let events: Vec<_> = result
+        .mouse
+        .into_iter()
+        .flat_map(|(bindings, _)| bindings.build(|event| Some(*event)))
+        .collect();
+    assert_eq!(events, vec!["DragCancel"]);
+    assert!(global_state
+        .with_drag_cancel_event(&mapping_cache)
+        .mouse
+        .is_empty());
+    assert_eq!(emit(&mut global_state, release(120)), vec!["Cancelled"]);
+
+    assert!(emit(&mut global_state, press(150)).is_empty());
+    assert_eq!(
+        emit(&mut global_state, coords(160, 40.0)),
+        vec!["DragStart"]
+    );
+    let result =
+        global_state.with_keyboard_press_event(SwitchEvent::new(170, "Escape"), &mapping_cache);
+    let events: Vec<_> = result
+        .drag_cancel
+        .mouse
+        .into_iter()
+        .flat_map(|(bindings, _)| bindings.build(|event| Some(*event)))
+        .collect();
+    assert_eq!(events, vec!["DragCancel"]);
+    assert_eq!(result.bindings.len(), 1);
+    assert_eq!(emit(&mut global_state, release(180)), vec!["Cancelled"]);
+
+    assert!(emit(&mut global_state, press(200)).is_empty());
+    assert_eq!(emit(&mut global_state, release(210)), vec!["Click"]);
+}
+
+#[test]
+fn test_hit_testing() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::{Coords, Mapping};
+
+    let click = |target: &str, event| {
+        Binding::Release(
+            SwitchBinding::new(
+                "Lmb",
+                Modifiers::new(),
+                Some(TimedReleaseEventData::new(TimedReleaseEventKind::Click, 1)),
+                None,
+                event,
+            )
+            .with_target(target.parse().unwrap()),
+        )
+    };
+    let drag = |target: &str, event| {
+        Binding::Coords(
+            CoordsBinding::new(
+                PointerMoveEventData {
+                    switch: "Lmb",
+                    kind: PointerMoveEventKind::DragStart,
+                },
+                Modifiers::new(),
+                event,
+            )
+            .with_target(target.parse().unwrap()),
+        )
+    };
+    let mouse_mapping = Mapping::new(HashSet::from([
+        click("node", "SelectNode"),
+        click("!node", "UnselectNodes"),
+        drag("node && selected", "MoveNodes"),
+        drag("!node", "BoxSelect"),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: Mapping::default(),
+        mouse: mouse_mapping,
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+
+    // A node spans x in [0, 10), its left half is selected.
+    let hit_tester = |&(x, _): &Coords| {
+        let mut layer = ContextLayer::new("hit");
+        layer.set_flag("node", (0.0..10.0).contains(&x));
+        layer.set_flag("selected", (0.0..5.0).contains(&x));
+        layer
+    };
+    let mut global_state = str_state::new_state().with_hit_tester(hit_tester);
+    let mut emit = |event: RawEvent<_, _, _, _, _, _, _>| -> Vec<_> {
+        let mut events = global_state.with_timeouts_until(event.time(), &mapping_cache);
+        events.extend(global_state.with_raw_event(event, &mapping_cache));
+        events
+            .into_iter()
+            .filter_map(|event| event.event.into_event())
+            .collect()
+    };
+    let press = |time| RawEvent::MousePress(SwitchEvent::new(time, "Lmb"));
+    let release = |time| RawEvent::MouseRelease(SwitchEvent::new(time, "Lmb"));
+    let coords = |time, x| RawEvent::MouseCoords(CoordsEvent::new(time, (x, 0.0)));
+
+    assert!(emit(coords(0, 7.0)).is_empty());
+    assert!(emit(press(10)).is_empty());
+    assert_eq!(emit(release(20)), vec!["SelectNode"]);
+
+    assert!(emit(coords(1000, 50.0)).is_empty());
+    assert!(emit(press(1010)).is_empty());
+    assert_eq!(emit(release(1020)), vec!["UnselectNodes"]);
+
+    // Drags are matched against what was under the pointer at press time.
+    assert!(emit(coords(2000, 2.0)).is_empty());
+    assert!(emit(press(2010)).is_empty());
+    assert_eq!(emit(coords(2020, 40.0)), vec!["MoveNodes"]);
+    assert!(emit(release(2030)).is_empty());
+
+    assert!(emit(coords(3000, 7.0)).is_empty());
+    assert!(emit(press(3010)).is_empty());
+    assert!(emit(coords(3020, 40.0)).is_empty());
+    assert!(emit(release(3030)).is_empty());
+
+    assert!(emit(coords(4000, 50.0)).is_empty());
+    assert!(emit(press(4010)).is_empty());
+    assert_eq!(emit(coords(4020, 90.0)), vec!["BoxSelect"]);
+    assert!(emit(release(4030)).is_empty());
+
+    // Without a hit tester targeted bindings never match.
+    let mut global_state = str_state::new_state();
+    assert!(global_state
+        .replay(
+            [press(0), release(10), press(1000), release(1010)],
+            &mapping_cache
+        )
+        .is_empty());
+}
+
+#[test]
+fn test_modifier_aliases() {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    let modifiers = |switches: &[&'static str]| -> Modifiers<&'static str> {
+        Modifiers::from(Arc::new(switches.iter().copied().collect()))
+    };
+    let press = |switch, switches: &[&'static str], event| {
+        Binding::Press(SwitchBinding::new(
+            switch,
+            modifiers(switches),
+            (),
+            (),
+            event,
+        ))
+    };
+    let aliases = ModifierAliases::new().with_alias("Ctrl", ["LCtrl", "RCtrl"]);
+    assert_eq!(
+        aliases.expand(&modifiers(&["LCtrl", "Shift"])),
+        modifiers(&["Ctrl", "LCtrl", "Shift"])
+    );
+    assert_eq!(
+        aliases.generalize(&modifiers(&["Ctrl", "RCtrl"])),
+        modifiers(&["Ctrl"])
+    );
+
+    let keyboard_mapping = Mapping::new(HashSet::from([
+        press("C", &["Ctrl"], "Copy"),
+        press("C", &["LCtrl"], "CopyLeft"),
+        press("V", &["Ctrl"], "Paste"),
+        Binding::Sequence(SequenceBinding {
+            prefix: vec![SequenceStep::new("K", modifiers(&["Ctrl"]))],
+            switch: "U",
+            modifiers: modifiers(&["Ctrl"]),
+            when: None,
+            priority: 0,
+            propagation: BindingPropagation::Consume,
+            event: "Uncomment",
+        }),
+    ]))
+    .with_modifier_aliases(aliases);
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: keyboard_mapping,
+        mouse: Mapping::default(),
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+    assert!(mapping_cache.modifiers().switches().contains("LCtrl"));
+
+    let mut global_state = str_state::new_state();
+    let mut emit = |event| -> Vec<_> {
+        global_state
+            .with_raw_event(event, &mapping_cache)
+            .into_iter()
+            .filter_map(|event| event.event.into_event())
+            .collect()
+    };
+    let press = |time, switch| RawEvent::KeyboardPress(SwitchEvent::new(time, switch));
+    let release = |time, switch| RawEvent::KeyboardRelease(SwitchEvent::new(time, switch));
+
+    assert!(emit(press(0, "LCtrl")).is_empty());
+    assert_eq!(emit(press(10, "C")), vec!["CopyLeft"]);
+    assert!(emit(release(20, "C")).is_empty());
+    assert!(emit(release(30, "LCtrl")).is_empty());
+
+    assert!(emit(press(100, "RCtrl")).is_empty());
+    assert_eq!(emit(press(110, "C")), vec!["Copy"]);
+    assert!(emit(release(120, "C")).is_empty());
+
+    // Ctrl stays pressed until both sides are released.
+    assert!(emit(press(200, "LCtrl")).is_empty());
+    assert!(emit(release(210, "RCtrl")).is_empty());
+    assert_eq!(emit(press(220, "V")), vec!["Paste"]);
+    assert!(emit(release(230, "V")).is_empty());
+    assert!(emit(release(240, "LCtrl")).is_empty());
+    assert!(emit(press(250, "V")).is_empty());
+    assert!(emit(release(260, "V")).is_empty());
+
+    assert!(emit(press(300, "RCtrl")).is_empty());
+    assert!(emit(press(310, "K")).is_empty());
+    assert!(emit(release(320, "K")).is_empty());
+    assert!(emit(release(330, "RCtrl")).is_empty());
+    assert!(emit(press(340, "LCtrl")).is_empty());
+    assert_eq!(emit(press(350, "U")), vec!["Uncomment"]);
+}
+
+#[test]
+fn test_lock_modifiers() {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    let modifiers = |switches: &[&'static str]| -> Modifiers<&'static str> {
+        Modifiers::from(Arc::new(switches.iter().copied().collect()))
+    };
+    let press = |switch, switches: &[&'static str], event| {
+        Binding::Press(SwitchBinding::new(
+            switch,
+            modifiers(switches),
+            (),
+            (),
+            event,
+        ))
+    };
+    let click = |switch, switches: &[&'static str], event| {
+        Binding::Release(SwitchBinding::new(
+            switch,
+            modifiers(switches),
+            Some(TimedReleaseEventData::new(TimedReleaseEventKind::Click, 1)),
+            None,
+            event,
+        ))
+    };
+    let keyboard_mapping = Mapping::new(HashSet::from([
+        press("A", &[], "A"),
+        press("A", &["Shift"], "ShiftA"),
+        click("B", &[], "ClickB"),
+        click("B", &["Shift"], "ShiftClickB"),
+        Binding::Sequence(SequenceBinding {
+            prefix: vec![SequenceStep::new("G", modifiers(&[]))],
+            switch: "G",
+            modifiers: modifiers(&[]),
+            when: None,
+            priority: 0,
+            propagation: BindingPropagation::Consume,
+            event: "Top",
+        }),
+    ]))
+    .with_lock_modifiers(["CapsLock"])
+    .with_latch_modifiers(["Shift"]);
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: keyboard_mapping,
+        mouse: Mapping::default(),
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+    assert_eq!(
+        mapping_cache.modifiers().kind(&"CapsLock"),
+        ModifierKind::Lock
+    );
+
+    let mut global_state = str_state::new_state();
+    let emit = |global_state: &mut str_state::State, event| -> Vec<_> {
+        global_state
+            .with_raw_event(event, &mapping_cache)
+            .into_iter()
+            .filter_map(|event| event.event.into_event())
+            .collect()
+    };
+    let press = |time, switch| RawEvent::KeyboardPress(SwitchEvent::new(time, switch));
+    let release = |time, switch| RawEvent::KeyboardRelease(SwitchEvent::new(time, switch));
+    let state = &mut global_state;
+
+    // CapsLock stays on after release and does not break other bindings.
+    assert!(emit(state, press(0, "CapsLock")).is_empty());
+    assert!(emit(state, release(10, "CapsLock")).is_empty());
+    assert!(state.modifiers.switches().contains("CapsLock"));
+    assert_eq!(emit(state, press(20, "A")), vec!["A"]);
+    assert!(emit(state, release(30, "A")).is_empty());
+    assert!(emit(state, press(40, "G")).is_empty());
+    assert!(emit(state, release(50, "G")).is_empty());
+    assert_eq!(emit(state, press(60, "G")), vec!["Top"]);
+    assert!(emit(state, release(70, "G")).is_empty());
+
+    // The host turned CapsLock off while the window was unfocused.
+    state.modifiers.set_locked("CapsLock", false);
+    assert!(state.modifiers.switches().is_empty());
+
+    // Sticky Shift applies to the next switch only.
+    assert!(emit(state, press(100, "Shift")).is_empty());
+    assert!(emit(state, release(110, "Shift")).is_empty());
+    assert_eq!(emit(state, press(120, "A")), vec!["ShiftA"]);
+    assert!(emit(state, release(130, "A")).is_empty());
+    assert_eq!(emit(state, press(140, "A")), vec!["A"]);
+    assert!(emit(state, release(150, "A")).is_empty());
+
+    // Tapping a latched Shift again cancels it.
+    assert!(emit(state, press(200, "Shift")).is_empty());
+    assert!(emit(state, release(210, "Shift")).is_empty());
+    assert!(emit(state, press(220, "Shift")).is_empty());
+    assert!(emit(state, release(230, "Shift")).is_empty());
+    assert_eq!(emit(state, press(240, "A")), vec!["A"]);
+    assert!(emit(state, release(250, "A")).is_empty());
+
+    // A held Shift acts as a regular modifier.
+    assert!(emit(state, press(300, "Shift")).is_empty());
+    assert_eq!(emit(state, press(310, "A")), vec!["ShiftA"]);
+    assert!(emit(state, release(320, "A")).is_empty());
+    assert_eq!(emit(state, press(330, "A")), vec!["ShiftA"]);
+    assert!(emit(state, release(340, "A")).is_empty());
+    assert!(emit(state, release(350, "Shift")).is_empty());
+    assert_eq!(emit(state, press(360, "A")), vec!["A"]);
+    assert!(emit(state, release(370, "A")).is_empty());
+
+    // Sticky Shift lasts until the release of the switch that used it.
+    assert!(emit(state, press(400, "Shift")).is_empty());
+    assert!(emit(state, release(410, "Shift")).is_empty());
+    assert!(emit(state, press(420, "B")).is_empty());
+    assert!(state.modifiers.is_latched(&"Shift"));
+    assert_eq!(emit(state, release(430, "B")), vec!["ShiftClickB"]);
+    assert!(!state.modifiers.is_latched(&"Shift"));
+    let _ = state.with_timeouts_until(&2000, &mapping_cache);
+    assert!(emit(state, press(2000, "B")).is_empty());
+    assert_eq!(emit(state, release(2010, "B")), vec!["ClickB"]);
+}
+
+#[test]
+fn test_key_repeat() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    type Event = RawEvent<i64, &'static str, (), &'static str, (), &'static str, str_state::Coords>;
+
+    let binding = |event| SwitchBinding::new("Right", Modifiers::new(), (), (), event);
+    let keyboard_mapping = Mapping::new(HashSet::from([
+        Binding::Press(binding("Nudge")),
+        Binding::Repeat(binding("NudgeRepeat")),
+        Binding::LongPress(SwitchBinding::new(
+            "Right",
+            Modifiers::new(),
+            TimedEventData::new(TimedLongPressEventKind::LongPress, 1),
+            (),
+            "LongNudge",
+        )),
+        Binding::Release(SwitchBinding::new(
+            "Right",
+            Modifiers::new(),
+            Some(TimedReleaseEventData::new(
+                TimedReleaseEventKind::LongClick,
+                1,
+            )),
+            None,
+            "LongClickNudge",
+        )),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: keyboard_mapping,
+        mouse: Mapping::default(),
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+    let press = |time| RawEvent::KeyboardPress(SwitchEvent::new(time, "Right"));
+    let repeat = |time| RawEvent::KeyboardRepeat(SwitchEvent::new(time, "Right"));
+    let release = |time| RawEvent::KeyboardRelease(SwitchEvent::new(time, "Right"));
+
+    // Without repeats, holding the key is a long press.
+    let events: Vec<Event> = vec![press(0), release(1200)];
+    assert_eq!(
+        str_state::new_state().replay(events, &mapping_cache),
+        vec![
+            ReplayedEvent::new(0, "Nudge"),
+            ReplayedEvent::new(1000, "LongNudge"),
+            ReplayedEvent::new(1200, "LongClickNudge"),
+        ]
+    );
+
+    // Once the key repeats, it is neither long pressed nor long clicked.
+    let events: Vec<Event> = vec![
+        press(0),
+        repeat(500),
+        repeat(530),
+        repeat(1100),
+        release(1200),
+    ];
+    assert_eq!(
+        str_state::new_state().replay(events, &mapping_cache),
+        vec![
+            ReplayedEvent::new(0, "Nudge"),
+            ReplayedEvent::new(500, "NudgeRepeat"),
+            ReplayedEvent::new(530, "NudgeRepeat"),
+            ReplayedEvent::new(1100, "NudgeRepeat"),
+        ]
+    );
+
+    // Synthesised repeats speed up and ignore the host repeats.
+    let mut global_state = str_state::new_state();
+    global_state.keyboard.repeat_state.set_policy(Some(
+        RepeatPolicy::new(400, 100).with_acceleration([200, 150]),
+    ));
+    let events: Vec<Event> = vec![press(0), repeat(500), release(980)];
+    assert_eq!(
+        global_state.replay(events, &mapping_cache),
+        vec![
+            ReplayedEvent::new(0, "Nudge"),
+            ReplayedEvent::new(400, "NudgeRepeat"),
+            ReplayedEvent::new(600, "NudgeRepeat"),
+            ReplayedEvent::new(750, "NudgeRepeat"),
+            ReplayedEvent::new(850, "NudgeRepeat"),
+            ReplayedEvent::new(950, "NudgeRepeat"),
+        ]
+    );
+    assert!(global_state.next_scheduled().is_none());
+
+    // A key still held at the end of the log stops repeating there.
+    let mut global_state = str_state::new_state();
+    global_state
+        .keyboard
+        .repeat_state
+        .set_policy(Some(RepeatPolicy::new(400, 100)));
+    let events: Vec<Event> = vec![
+        press(0),
+        RawEvent::KeyboardPress(SwitchEvent::new(650, "Left")),
+    ];
+    assert_eq!(
+        global_state.replay(events, &mapping_cache),
+        vec![
+            ReplayedEvent::new(0, "Nudge"),
+            ReplayedEvent::new(400, "NudgeRepeat"),
+            ReplayedEvent::new(500, "NudgeRepeat"),
+            ReplayedEvent::new(600, "NudgeRepeat"),
+        ]
+    );
+    assert!(global_state.next_scheduled().is_none());
+}
+
+#[test]
+fn test_axis() {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    type Event = RawEvent<i64, &'static str, (), &'static str, (), &'static str, str_state::Coords>;
+
+    let axis = |switches: &[&'static str], event| {
+        Binding::Axis(CoordsBinding::new(
+            (),
+            Modifiers::from(Arc::new(switches.iter().copied().collect())),
+            event,
+        ))
+    };
+    let mouse_mapping = Mapping::new(HashSet::from([
+        axis(&[], "Scroll"),
+        axis(&["Ctrl"], "Zoom"),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: Mapping::default(),
+        mouse: mouse_mapping,
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+    let wheel = |time, delta| RawEvent::MouseAxis(AxisEvent::new(time, (), delta));
+    let notch = |steps: i32| AxisEventData {
+        delta: (0.0, f64::from(steps)),
+        steps: (0, steps),
+    };
+
+    let events: Vec<Event> = vec![
+        wheel(0, (0.0, -1.0)),
+        RawEvent::KeyboardPress(SwitchEvent::new(10, "Ctrl")),
+        wheel(20, (0.0, 1.0)),
+        RawEvent::KeyboardRelease(SwitchEvent::new(30, "Ctrl")),
+        wheel(40, (0.0, 1.0)),
+    ];
+    assert_eq!(
+        str_state::new_state().replay(events, &mapping_cache),
+        vec![
+            ReplayedEvent::axis(0, "Scroll", notch(-1)),
+            ReplayedEvent::axis(20, "Zoom", notch(1)),
+            ReplayedEvent::axis(40, "Scroll", notch(1)),
+        ]
+    );
+
+    // Touchpad deltas add up to whole steps, the raw delta is kept.
+    let mut global_state = str_state::new_state();
+    let mut scroll = |delta| {
+        let result =
+            global_state.with_mouse_axis_event(&AxisEvent::new(0, (), delta), &mapping_cache);
+        let (bindings, data) = result.bindings.unwrap();
+        assert_eq!(bindings.into_inner().len(), 1);
+        data
+    };
+    assert_eq!(
+        scroll((0.25, -0.75)),
+        AxisEventData {
+            delta: (0.25, -0.75),
+            steps: (0, 0),
+        }
+    );
+    assert_eq!(scroll((0.5, -0.5)).steps, (0, -1));
+    assert_eq!(scroll((0.5, 2.5)).steps, (1, 2));
+    // Reversing drops the remainder of the previous direction.
+    assert_eq!(scroll((-0.5, 0.0)).steps, (0, 0));
+    assert_eq!(scroll((-0.5, 0.0)).steps, (-1, 0));
+    assert_eq!(global_state.mouse.axis_state.accumulated(&()), (0.0, 0.5));
+}
+
+#[test]
+fn test_analog() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    type Event = RawEvent<i64, &'static str, (), &'static str, (), &'static str, str_state::Coords>;
+
+    let policy = AnalogPolicy::default()
+        .with_dead_zone(0.25)
+        .with_saturation(0.75)
+        .with_curve(AnalogCurve::Power(2.0));
+    assert_eq!(policy.apply(0.1), 0.0);
+    assert_eq!(policy.apply(-0.5), -0.25);
+    assert_eq!(policy.apply(1.0), 1.0);
+    assert_eq!(policy.with_inverted(true).apply(0.5), -0.25);
+
+    let release = |switch, event| {
+        Binding::Release(SwitchBinding::new(
+            switch,
+            Modifiers::new(),
+            Some(TimedReleaseEventData::new(TimedReleaseEventKind::Click, 1)),
+            None,
+            event,
+        ))
+    };
+    let keyboard_mapping = Mapping::new(HashSet::from([
+        release("StickLeft", "Left"),
+        release("StickRight", "Right"),
+        Binding::LongPress(SwitchBinding::new(
+            "StickRight",
+            Modifiers::new(),
+            TimedEventData::new(TimedLongPressEventKind::LongPress, 1),
+            (),
+            "Run",
+        )),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: keyboard_mapping,
+        mouse: Mapping::default(),
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+
+    let mut global_state = str_state::new_state();
+    global_state.keyboard.analog_state = AnalogState::default().with_axis(
+        (),
+        AnalogAxis::new(AnalogPolicy::default())
+            .with_threshold(AnalogThreshold::new("StickLeft", -0.5, -0.4))
+            .with_threshold(AnalogThreshold::new("StickRight", 0.5, 0.4)),
+    );
+    let stick = |time, value| RawEvent::KeyboardAnalog(AnalogEvent::new(time, (), value));
+
+    // The release threshold keeps a jittery stick pressed.
+    let events: Vec<Event> = vec![
+        stick(0, 0.7),
+        stick(50, 0.45),
+        stick(100, 0.6),
+        stick(150, 0.0),
+        stick(200, -0.8),
+        stick(600, 0.8),
+        stick(2000, 0.05),
+    ];
+    assert_eq!(
+        global_state.replay(events, &mapping_cache),
+        vec![
+            ReplayedEvent::new(150, "Right"),
+            ReplayedEvent::new(600, "Left"),
+            ReplayedEvent::new(1600, "Run"),
+        ]
+    );
+    assert!(!global_state.keyboard.analog_state.is_pressed(&"StickRight"));
+    assert_eq!(global_state.keyboard.analog_state.value(&()), 0.05);
+}
+
+#[test]
+fn test_snapshot() {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    type Event = RawEvent<i64, &'static str, (), &'static str, (), &'static str, str_state::Coords>;
+
+    let modifiers = |switches: &[&'static str]| -> Modifiers<&'static str> {
+        Modifiers::from(Arc::new(switches.iter().copied().collect()))
+    };
+    let click = |switch, num_clicks, event| {
+        Binding::Release(SwitchBinding::new(
+            switch,
+            modifiers(&[]),
+            Some(TimedReleaseEventData::new(
+                TimedReleaseEventKind::Click,
+                num_clicks,
+            )),
+            None,
+            event,
+        ))
+    };
+    let keyboard_mapping = Mapping::new(HashSet::from([
+        Binding::LongPress(
+            SwitchBinding::new(
+                "A",
+                modifiers(&["Shift"]),
+                TimedEventData::new(TimedLongPressEventKind::LongPress, 1),
+                (),
+                "ShiftLongA",
+            )
+            .with_when("editing".parse().unwrap()),
+        ),
+        click("B", 2, "DoubleB"),
+        click("B", 3, "TripleB"),
+        Binding::Sequence(SequenceBinding {
+            prefix: vec![SequenceStep::new("G", modifiers(&[]))],
+            switch: "G",
+            modifiers: modifiers(&[]),
+            when: None,
+            priority: 0,
+            propagation: BindingPropagation::Consume,
+            event: "Top",
+        }),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: keyboard_mapping,
+        mouse: Mapping::default(),
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+    let press = |time, switch| RawEvent::KeyboardPress(SwitchEvent::new(time, switch));
+    let release = |time, switch| RawEvent::KeyboardRelease(SwitchEvent::new(time, switch));
+
+    let mut global_state = str_state::new_state();
+    global_state
+        .context
+        .push(ContextLayer::new("editor").with_flag("editing"));
+    let events: Vec<Event> = vec![
+        press(0, "B"),
+        release(10, "B"),
+        press(20, "B"),
+        release(30, "B"),
+        press(40, "Shift"),
+        press(50, "A"),
+        release(60, "Shift"),
+        press(70, "G"),
+        release(75, "G"),
+    ];
+    for event in events {
+        assert!(global_state
+            .with_timeouts_until(event.time(), &mapping_cache)
+            .is_empty());
+        let _ = global_state.with_raw_event(event, &mapping_cache);
+    }
+
+    // Handle requests of the first B click are dead and left out.
+    let snapshot = global_state.snapshot();
+    assert_eq!(snapshot.keyboard_click_exact_scheduler.requests.len(), 1);
+    let json: &'static str = Box::leak(serde_json::to_string(&snapshot).unwrap().into_boxed_str());
+    let mut restored = str_state::new_state();
+    restored.restore(serde_json::from_str(json).unwrap());
+
+    let events = || -> Vec<Event> {
+        vec![
+            press(80, "G"),
+            press(100, "B"),
+            release(110, "B"),
+            release(1500, "A"),
+        ]
+    };
+    let expected = vec![
+        ReplayedEvent::new(80, "Top"),
+        ReplayedEvent::new(110, "TripleB"),
+        ReplayedEvent::new(1050, "ShiftLongA"),
+    ];
+    assert_eq!(global_state.replay(events(), &mapping_cache), expected);
+    assert_eq!(restored.replay(events(), &mapping_cache), expected);
+}
+
+#[test]
+fn test_focus_lost() {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    let modifiers = |switches: &[&'static str]| -> Modifiers<&'static str> {
+        Modifiers::from(Arc::new(switches.iter().copied().collect()))
+    };
+    let press = |switch, switches: &[&'static str], event| {
+        Binding::Press(SwitchBinding::new(
+            switch,
+            modifiers(switches),
+            (),
+            (),
+            event,
+        ))
+    };
+    let release = |switch, switches: &[&'static str], event| {
+        Binding::Release(SwitchBinding::new(
+            switch,
+            modifiers(switches),
+            None,
+            None,
+            event,
+        ))
+    };
+    let keyboard_mapping = Mapping::new(HashSet::from([
+        press("A", &[], "A"),
+        press("A", &["Ctrl"], "CtrlA"),
+        press("B", &["Shift"], "ShiftB"),
+        release("A", &["Ctrl"], "CtrlReleaseA"),
+    ]))
+    .with_lock_modifiers(["CapsLock"])
+    .with_latch_modifiers(["Shift"]);
+    let mouse_mapping = Mapping::new(HashSet::from([release("Lmb", &[], "ReleaseLmb")]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: keyboard_mapping,
+        mouse: mouse_mapping,
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+
+    let mut global_state = str_state::new_state();
+    let emit = |global_state: &mut str_state::State, event| -> Vec<_> {
+        global_state
+            .with_raw_event(event, &mapping_cache)
+            .into_iter()
+            .filter_map(|event| event.event.into_event())
+            .collect()
+    };
+    let key_press = |time, switch| RawEvent::KeyboardPress(SwitchEvent::new(time, switch));
+    let key_release = |time, switch| RawEvent::KeyboardRelease(SwitchEvent::new(time, switch));
+    let state = &mut global_state;
+
+    assert!(emit(state, key_press(0, "CapsLock")).is_empty());
+    assert!(emit(state, key_release(10, "CapsLock")).is_empty());
+    assert!(emit(state, key_press(20, "Ctrl")).is_empty());
+    assert_eq!(emit(state, key_press(30, "A")), vec!["CtrlA"]);
+    let lmb = RawEvent::MousePress(SwitchEvent::new(40, "Lmb"));
+    assert!(emit(state, lmb).is_empty());
+    let touch = RawEvent::TouchStart(TouchEvent::new(50, "T1", (1.0, 2.0)));
+    assert!(emit(state, touch).is_empty());
+    assert!(state.next_scheduled().is_some());
+
+    // Releases see the modifiers they were pressed with, locks survive.
+    let result = state.with_focus_lost_event(60, SyntheticReleases::Emit, &mapping_cache);
+    let keyboard: Vec<_> = result
+        .keyboard
+        .into_iter()
+        .flat_map(|(bindings, _)| bindings.build(|event| Some(*event)))
+        .collect();
+    assert_eq!(keyboard, vec!["CtrlReleaseA"]);
+    let mouse: Vec<_> = result
+        .mouse
+        .into_iter()
+        .flat_map(|(bindings, _)| bindings.build(|event| Some(*event)))
+        .collect();
+    assert_eq!(mouse, vec!["ReleaseLmb"]);
+    assert_eq!(result.released_keyboard, vec!["A", "Ctrl"]);
+    assert_eq!(result.released_mouse, vec!["Lmb"]);
+    assert_eq!(result.released_touch, vec!["T1"]);
+    assert!(result.released_modifiers.is_empty());
+    assert_eq!(state.modifiers.switches().len(), 1);
+    assert!(state.modifiers.switches().contains("CapsLock"));
+    assert_eq!(state.touch.gesture_state.num_pointers(), 0);
+    assert_eq!(state.next_scheduled(), None);
+
+    state.modifiers.set_locked("CapsLock", false);
+    assert_eq!(emit(state, key_press(100, "A")), vec!["A"]);
+
+    // Suppressed releases are still reported, a latched Shift is dropped.
+    assert!(emit(state, key_press(200, "Shift")).is_empty());
+    assert!(emit(state, key_release(210, "Shift")).is_empty());
+    assert!(state.modifiers.is_latched(&"Shift"));
+    let result = state.with_focus_lost_event(220, SyntheticReleases::Suppress, &mapping_cache);
+    assert!(result.keyboard.is_empty() && result.mouse.is_empty());
+    assert_eq!(result.released_keyboard, vec!["A"]);
+    assert_eq!(result.released_modifiers, vec!["Shift"]);
+    assert!(state.modifiers.switches().is_empty());
+    assert!(emit(state, key_press(300, "B")).is_empty());
+
+    // Hosts report the focus loss in the event stream.
+    let log = r#"{"kind":"focus_lost","time":400,"releases":"emit"}"#;
+    let focus_lost = read_input_log(log).unwrap().pop().unwrap();
+    assert_eq!(
+        focus_lost,
+        RawEvent::FocusLost {
+            time: 400,
+            releases: SyntheticReleases::Emit
+        }
+    );
+    assert!(emit(state, key_press(310, "Ctrl")).is_empty());
+    assert_eq!(emit(state, key_press(320, "A")), vec!["CtrlA"]);
+    assert_eq!(emit(state, focus_lost), vec!["CtrlReleaseA"]);
+    assert!(state.modifiers.switches().is_empty());
+    let log = r#"{"kind":"focus_lost","time":500}"#;
+    let events: Vec<RawEvent<i64, &str, (), &str, (), &str, str_state::Coords>> =
+        read_input_log(log).unwrap();
+    assert_eq!(
+        events,
+        vec![RawEvent::FocusLost {
+            time: 500,
+            releases: SyntheticReleases::Suppress
+        }]
+    );
+}
+
+#[test]
+fn test_recovery() {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    let modifiers = Modifiers::from(Arc::new(Default::default()));
+    let keyboard_mapping = Mapping::new(HashSet::from([
+        Binding::Press(SwitchBinding::new("A", modifiers.clone(), (), (), "A")),
+        Binding::Release(SwitchBinding::new(
+            "A",
+            modifiers.clone(),
+            Some(TimedReleaseEventData::new(TimedReleaseEventKind::Click, 1)),
+            None,
+            "ClickA",
+        )),
+        Binding::LongPress(SwitchBinding::new(
+            "A",
+            modifiers,
+            TimedEventData::new(TimedLongPressEventKind::LongPress, 1),
+            (),
+            "LongA",
+        )),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: keyboard_mapping,
+        mouse: Mapping::default(),
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+
+    let emit = |global_state: &mut str_state::State, event| -> Vec<_> {
+        global_state
+            .with_raw_event(event, &mapping_cache)
+            .into_iter()
+            .filter_map(|event| event.event.into_event())
+            .collect()
+    };
+    let timeouts = |global_state: &mut str_state::State, time| -> Vec<_> {
+        global_state
+            .with_timeouts_until(&time, &mapping_cache)
+            .into_iter()
+            .filter_map(|event| event.event.into_event().map(|ev| (event.time, ev)))
+            .collect()
+    };
+    let press = |time| RawEvent::KeyboardPress(SwitchEvent::new(time, "A"));
+    let release = |time| RawEvent::KeyboardRelease(SwitchEvent::new(time, "A"));
+    let sources = |global_state: &str_state::State| -> Vec<_> {
+        global_state
+            .recovery
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| (diagnostic.source, diagnostic.is_resynced))
+            .collect()
+    };
+
+    // The release of the first press was dropped by the host.
+    let mut strict = str_state::new_state();
+    assert_eq!(emit(&mut strict, press(0)), vec!["A"]);
+    assert_eq!(emit(&mut strict, press(500)), vec!["A"]);
+    assert_eq!(
+        sources(&strict),
+        vec![
+            ("input_core::TimedState::on_press_event", false),
+            ("input_core::PointerState::on_press_event", false),
+        ]
+    );
+    assert_eq!(timeouts(&mut strict, 1000), vec![(1000, "LongA")]);
+    assert!(emit(&mut strict, release(1600)).is_empty());
+    assert!(emit(&mut strict, release(1700)).is_empty());
+    assert_eq!(strict.recovery.take_diagnostics().len(), 4);
+    assert!(strict.recovery.diagnostics().is_empty());
+
+    // Only the latest diagnostics are kept until they are taken.
+    let mut limited = str_state::new_state();
+    limited.recovery = Recovery::default().with_limit(1);
+    let _ = emit(&mut limited, press(0));
+    let _ = emit(&mut limited, press(500));
+    assert_eq!(
+        sources(&limited),
+        vec![("input_core::PointerState::on_press_event", false)]
+    );
+    assert_eq!(limited.recovery.num_dropped(), 1);
+
+    // Resync restarts the long press and drops the duplicate release.
+    let mut resync = str_state::new_state();
+    resync.recovery.policy = RecoveryPolicy::Resync;
+    assert_eq!(emit(&mut resync, press(0)), vec!["A"]);
+    assert_eq!(emit(&mut resync, press(500)), vec!["A"]);
+    assert_eq!(
+        sources(&resync),
+        vec![
+            ("input_core::TimedState::on_press_event", true),
+            ("input_core::PointerState::on_press_event", true),
+        ]
+    );
+    assert!(timeouts(&mut resync, 1400).is_empty());
+    assert_eq!(timeouts(&mut resync, 1500), vec![(1500, "LongA")]);
+    assert!(emit(&mut resync, release(1600)).is_empty());
+    assert!(emit(&mut resync, release(1700)).is_empty());
+    assert_eq!(
+        sources(&resync)[2..],
+        [("input_core::TimedState::on_release_event", true)]
+    );
+
+    let mut ignore = str_state::new_state();
+    ignore.recovery.policy = RecoveryPolicy::Ignore;
+    assert_eq!(emit(&mut ignore, press(0)), vec!["A"]);
+    assert_eq!(emit(&mut ignore, press(500)), vec!["A"]);
+    assert_eq!(timeouts(&mut ignore, 1000), vec![(1000, "LongA")]);
+    assert!(emit(&mut ignore, release(1600)).is_empty());
+    assert!(emit(&mut ignore, release(1700)).is_empty());
+    assert!(ignore.recovery.diagnostics().is_empty());
+}
+
+#[test]
+fn test_coalescing() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    type Coalescer =
+        CoordsCoalescer<i64, &'static str, (), &'static str, (), &'static str, str_state::Coords>;
+
+    let mouse_mapping = Mapping::new(HashSet::from([
+        Binding::Release(SwitchBinding::new(
+            "Lmb",
+            Modifiers::new(),
+            Some(TimedReleaseEventData::new(TimedReleaseEventKind::Click, 1)),
+            Some(PointerChangeEventData::DragEnd),
+            "DragEnd",
+        )),
+        Binding::Coords(CoordsBinding::new(
+            PointerMoveEventData {
+                switch: "Lmb",
+                kind: PointerMoveEventKind::DragStart,
+            },
+            Modifiers::new(),
+            "DragStart",
+        )),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: Mapping::default(),
+        mouse: mouse_mapping,
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+
+    let coords = |time, x| RawEvent::MouseCoords(CoordsEvent::new(time, (x, 0.0)));
+    let mut coalescer = Coalescer::new().with_history(true);
+    for time in 0..4 {
+        coalescer.push(coords(time, time as f64));
+    }
+    coalescer.push(RawEvent::MousePress(SwitchEvent::new(4, "Lmb")));
+    for time in 5..8 {
+        coalescer.push(coords(time, time as f64));
+    }
+    coalescer.push(RawEvent::MouseRelease(SwitchEvent::new(8, "Lmb")));
+    coalescer.push(RawEvent::KeyboardCoords(CoordsEvent::new(9, (0.0, 0.0))));
+
+    let events = coalescer.take();
+    assert!(coalescer.is_empty());
+    assert_eq!(events.len(), 5);
+    match &events[0] {
+        CoalescedEvent::MouseCoords(coords) => {
+            assert_eq!(coords.first, CoordsEvent::new(0, (0.0, 0.0)));
+            assert_eq!(coords.last, CoordsEvent::new(3, (3.0, 0.0)));
+            assert_eq!(
+                coords.history,
+                vec![
+                    CoordsEvent::new(1, (1.0, 0.0)),
+                    CoordsEvent::new(2, (2.0, 0.0)),
+                ]
+            );
+            assert_eq!(coords.num_events, 4);
+        }
+        event => panic!("unexpected event: {event:?}"),
+    }
+    assert!(matches!(events[4], CoalescedEvent::KeyboardCoords(_)));
+
+    // The drag starts once per run and before the release.
+    let mut global_state = str_state::new_state();
+    let replayed: Vec<_> = global_state
+        .with_coalesced_events(events, &mapping_cache)
+        .into_iter()
+        .filter_map(|event| event.event.into_event().map(|ev| (event.time, ev)))
+        .collect();
+    assert_eq!(replayed, vec![(7, "DragStart"), (8, "DragEnd")]);
+    assert_eq!(global_state.mouse.coords_state.coords(), &(7.0, 0.0));
+
+    let mut coalescer = Coalescer::new();
+    for time in 0..3 {
+        coalescer.push(coords(time, time as f64));
+    }
+    match coalescer.take().pop() {
+        Some(CoalescedEvent::MouseCoords(coords)) => assert!(coords.history.is_empty()),
+        event => panic!("unexpected event: {event:?}"),
+    }
+
+    // A run that goes beyond the slop and comes back still starts the drag.
+    let mut coalescer = Coalescer::new();
+    coalescer.push(RawEvent::MousePress(SwitchEvent::new(0, "Lmb")));
+    for (time, x) in [(1, 2.0), (2, 10.0), (3, 4.0), (4, 1.0)] {
+        coalescer.push(coords(time, x));
+    }
+    coalescer.push(RawEvent::MouseRelease(SwitchEvent::new(5, "Lmb")));
+    let events = coalescer.take();
+    match &events[1] {
+        CoalescedEvent::MouseCoords(coords) => {
+            assert_eq!(coords.farthest, Some(CoordsEvent::new(2, (10.0, 0.0))));
+        }
+        event => panic!("unexpected event: {event:?}"),
+    }
+
+    let mut global_state = str_state::new_state();
+    global_state
+        .mouse
+        .pointer_state
+        .set_policy(DragPolicy::new(5.0, 0.0, None));
+    let replayed: Vec<_> = global_state
+        .with_coalesced_events(events, &mapping_cache)
+        .into_iter()
+        .filter_map(|event| event.event.into_event().map(|ev| (event.time, ev)))
+        .collect();
+    assert_eq!(replayed, vec![(2, "DragStart"), (5, "DragEnd")]);
+    assert_eq!(global_state.mouse.coords_state.coords(), &(1.0, 0.0));
+
+    // Touch moves are coalesced per touch and interleaved back by time.
+    let touch = |time, id, x| RawEvent::TouchMove(TouchEvent::new(time, id, (x, 0.0)));
+    let mut coalescer = Coalescer::new();
+    coalescer.push(RawEvent::TouchStart(TouchEvent::new(0, "T1", (0.0, 0.0))));
+    for (time, id, x) in [
+        (1, "T1", 1.0),
+        (2, "T2", 5.0),
+        (3, "T1", 2.0),
+        (4, "T1", 3.0),
+    ] {
+        coalescer.push(touch(time, id, x));
+    }
+    coalescer.push(RawEvent::TouchEnd(TouchEvent::new(5, "T1", (3.0, 0.0))));
+    let events = coalescer.take();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[1].time(), &4);
+    match &events[1] {
+        CoalescedEvent::TouchMove(moves) => {
+            let ids: Vec<_> = moves.touches.iter().map(|(id, _)| *id).collect();
+            assert_eq!(ids, vec!["T1", "T2"]);
+            assert_eq!(moves.touches[0].1.num_events, 3);
+        }
+        event => panic!("unexpected event: {event:?}"),
+    }
+    let raw = events.into_iter().nth(1).unwrap().into_raw();
+    assert_eq!(raw, vec![touch(2, "T2", 5.0), touch(4, "T1", 3.0)],);
+}
+
+#[test]
+fn test_fling() {
+    use std::collections::HashSet;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    let coords = |kind, event| {
+        Binding::Coords(CoordsBinding::new(
+            PointerMoveEventData {
+                switch: "Lmb",
+                kind,
+            },
+            Modifiers::new(),
+            event,
+        ))
+    };
+    let mouse_mapping = Mapping::new(HashSet::from([
+        Binding::Release(SwitchBinding::new(
+            "Lmb",
+            Modifiers::new(),
+            Some(TimedReleaseEventData::new(TimedReleaseEventKind::Click, 1)),
+            Some(PointerChangeEventData::DragEnd),
+            "DragEnd",
+        )),
+        coords(PointerMoveEventKind::Fling, "Fling"),
+        coords(PointerMoveEventKind::Kinetic, "Kinetic"),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: Mapping::default(),
+        mouse: mouse_mapping,
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+
+    let kinetic = KineticPolicy::new(10, 0.5, 0.5);
+    let fling_policy = FlingPolicy::new(50, 1.0).with_kinetic(kinetic);
+    let new_state = || {
+        let mut global_state = str_state::new_state();
+        global_state
+            .mouse
+            .pointer_state
+            .set_fling_policy(Some(fling_policy));
+        global_state
+    };
+    // A slow start followed by 4 units per time unit over the window.
+    let swipe = |start: i64| {
+        let mut events = vec![
+            RawEvent::MousePress(SwitchEvent::new(start, "Lmb")),
+            RawEvent::MouseCoords(CoordsEvent::new(start + 10, (1.0, 0.0))),
+        ];
+        for j in 0..=5 {
+            let coords = (100.0 + 40.0 * j as f64, 0.0);
+            events.push(RawEvent::MouseCoords(CoordsEvent::new(
+                start + 100 + 10 * j,
+                coords,
+            )));
+        }
+        events
+    };
+    let collect = |replayed: Vec<ReplayedEvent<i64, &'static str>>| -> Vec<_> {
+        replayed
+            .into_iter()
+            .filter_map(|event| event.event.into_event().map(|ev| (event.time, ev)))
+            .collect()
+    };
+
+    let mut global_state = new_state();
+    for event in swipe(0) {
+        let _ = global_state.with_raw_event(event, &mapping_cache);
+    }
+    let result =
+        global_state.with_mouse_release_event(SwitchEvent::new(150, "Lmb"), &mapping_cache);
+    assert!(result.bindings.is_some());
+    assert_eq!(result.scheduled, Some(150));
+
+    let result = global_state.with_timeout(150, &mapping_cache);
+    assert_eq!(result.mouse_kinetic.len(), 1);
+    let (_, data) = &result.mouse_kinetic[0];
+    assert_eq!(data.kind, PointerMoveEventKind::Fling);
+    assert_eq!(data.velocity, (4.0, 0.0));
+    assert_eq!(data.delta, (0.0, 0.0));
+
+    // The velocity halves every 10 until it drops below 0.5.
+    let mut deltas = vec![];
+    while let Some(time) = global_state.next_scheduled().cloned() {
+        let result = global_state.with_timeout(time, &mapping_cache);
+        for (_, data) in result.mouse_kinetic {
+            assert_eq!(data.kind, PointerMoveEventKind::Kinetic);
+            deltas.push((time, data.delta.0));
+        }
+    }
+    assert_eq!(
+        deltas,
+        vec![(160, 40.0), (170, 20.0), (180, 10.0), (190, 5.0)]
+    );
+
+    // The fling comes right after the release bindings.
+    let mut global_state = new_state();
+    let mut events = swipe(0);
+    events.push(RawEvent::MouseRelease(SwitchEvent::new(150, "Lmb")));
+    assert_eq!(
+        collect(global_state.replay(events, &mapping_cache)),
+        vec![
+            (150, "DragEnd"),
+            (150, "Fling"),
+            (160, "Kinetic"),
+            (170, "Kinetic"),
+            (180, "Kinetic"),
+            (190, "Kinetic"),
+        ]
+    );
+
+    // Pressing again stops the kinetic updates.
+    let mut events = swipe(1000);
+    events.push(RawEvent::MouseRelease(SwitchEvent::new(1150, "Lmb")));
+    events.push(RawEvent::MousePress(SwitchEvent::new(1165, "Lmb")));
+    assert_eq!(
+        collect(global_state.replay(events, &mapping_cache)),
+        vec![(1150, "DragEnd"), (1150, "Fling"), (1160, "Kinetic")]
+    );
+
+    // A pointer that stopped before the release is not flung.
+    let mut global_state = new_state();
+    let mut events = swipe(0);
+    events.push(RawEvent::MouseCoords(CoordsEvent::new(260, (300.0, 0.0))));
+    events.push(RawEvent::MouseRelease(SwitchEvent::new(260, "Lmb")));
+    assert_eq!(
+        collect(global_state.replay(events, &mapping_cache)),
+        vec![(260, "DragEnd")]
+    );
+}
+
+#[test]
+fn test_pen() {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::Mapping;
+
+    type Event = RawEvent<i64, &'static str, (), &'static str, (), &'static str, str_state::Coords>;
+
+    let press = |switch, modifiers: &[&'static str], event| {
+        Binding::Press(SwitchBinding::new(
+            switch,
+            Modifiers::from(Arc::new(modifiers.iter().copied().collect())),
+            (),
+            (),
+            event,
+        ))
+    };
+    let pen_mapping = Mapping::new(HashSet::from([
+        press("PenTip", &[], "TipPress"),
+        press("Eraser", &["Barrel"], "BarrelErase"),
+        Binding::Coords(CoordsBinding::new(
+            PointerMoveEventData {
+                switch: "PenHard",
+                kind: PointerMoveEventKind::DragStart,
+            },
+            Modifiers::new(),
+            "HardDragStart",
+        )),
+        Binding::Release(SwitchBinding::new(
+            "PenHard",
+            Modifiers::new(),
+            Some(TimedReleaseEventData::new(TimedReleaseEventKind::Click, 1)),
+            Some(PointerChangeEventData::DragEnd),
+            "HardDragEnd",
+        )),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: Mapping::default(),
+        mouse: Mapping::default(),
+        touch: Mapping::default(),
+        pen: pen_mapping,
+    });
+
+    let new_state = || {
+        let mut global_state = str_state::new_state();
+        global_state.pen.pen_state = PenState::default()
+            .with_tool(
+                PenTool::Pen,
+                PenToolSwitches::new("PenTip")
+                    .with_threshold(AnalogThreshold::new("PenHard", 0.6, 0.5)),
+            )
+            .with_tool(PenTool::Eraser, PenToolSwitches::new("Eraser"))
+            .with_barrel("Barrel");
+        global_state
+    };
+    let pen = |time, x, pressure| {
+        RawEvent::Pen(CoordsEvent::new(
+            time,
+            PenCoords::new((x, 0.0)).with_pressure(pressure),
+        ))
+    };
+
+    // Pressing harder starts the drag, the release threshold keeps it going.
+    let mut global_state = new_state();
+    let events: Vec<Event> = vec![
+        pen(0, 0.0, 0.0),
+        pen(10, 0.0, 0.3),
+        pen(20, 10.0, 0.4),
+        pen(30, 10.0, 0.7),
+        pen(40, 30.0, 0.55),
+        pen(50, 30.0, 0.0),
+        RawEvent::Pen(CoordsEvent::new(
+            60,
+            PenCoords::new((30.0, 0.0))
+                .with_pressure(0.5)
+                .with_tool(PenTool::Eraser)
+                .with_barrel_pressed(true),
+        )),
+        pen(70, 30.0, 0.0),
+    ];
+    assert_eq!(
+        global_state.replay(events, &mapping_cache),
+        vec![
+            ReplayedEvent::new(10, "TipPress"),
+            ReplayedEvent::new(40, "HardDragStart"),
+            ReplayedEvent::new(50, "HardDragEnd"),
+            ReplayedEvent::new(60, "BarrelErase"),
+        ]
+    );
+    assert!(global_state.pen.pen_state.pressed().is_empty());
+
+    // Bindings come with the whole sample.
+    let mut global_state = new_state();
+    let sample = PenCoords::new((5.0, 5.0))
+        .with_pressure(0.8)
+        .with_tilt((30.0, -15.0))
+        .with_barrel_pressed(true);
+    let result = global_state.with_pen_event(&CoordsEvent::new(0, sample), &mapping_cache);
+    let bindings: Vec<_> = result
+        .bindings
+        .into_iter()
+        .map(|(bindings, coords)| (bindings.build(|event| Some(*event)), coords))
+        .collect();
+    assert_eq!(bindings, vec![(vec!["TipPress"], sample)]);
+    assert_eq!(
+        global_state.pen.pen_state.pressed(),
+        &["Barrel", "PenTip", "PenHard"]
+    );
+
+    // Focus loss lifts the pen.
+    let result =
+        global_state.with_focus_lost_event(10, SyntheticReleases::Suppress, &mapping_cache);
+    assert_eq!(result.released_pen, vec!["PenHard", "PenTip", "Barrel"]);
+    assert!(global_state.pen.pen_state.pressed().is_empty());
+    assert!(global_state.modifiers.switches().is_empty());
+
+    // A barrel pressed after the contact is released first.
+    let mut global_state = new_state();
+    let sample = PenCoords::new((5.0, 5.0)).with_pressure(0.3);
+    let _ = global_state.with_pen_event(&CoordsEvent::new(0, sample), &mapping_cache);
+    let sample = sample.with_barrel_pressed(true);
+    let _ = global_state.with_pen_event(&CoordsEvent::new(10, sample), &mapping_cache);
+    assert_eq!(global_state.pen.pen_state.pressed(), &["PenTip", "Barrel"]);
+    let result =
+        global_state.with_focus_lost_event(20, SyntheticReleases::Suppress, &mapping_cache);
+    assert_eq!(result.released_pen, vec!["Barrel", "PenTip"]);
+}
+
+#[test]
+fn test_text_input() {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use input_core::*;
+    use input_more::*;
+    use str_state::{new_state, Coords, Mapping};
+
+    let modifiers = Modifiers::from(Arc::new(Default::default()));
+    let keyboard_mapping = Mapping::new(HashSet::from([
+        Binding::Press(SwitchBinding::new("K", modifiers.clone(), (), (), "Kill")),
+        Binding::Release(SwitchBinding::new(
+            "K",
+            modifiers,
+            Some(TimedReleaseEventData::new(TimedReleaseEventKind::Click, 1)),
+            None,
+            "ClickK",
+        )),
+    ]));
+    let mapping_cache = GlobalMappingCache::from_mapping(GlobalMapping {
+        keyboard: keyboard_mapping,
+        mouse: Mapping::default(),
+        touch: Mapping::default(),
+        pen: Mapping::default(),
+    });
+
+    type Event = RawEvent<i64, &'static str, (), &'static str, (), &'static str, Coords>;
+    let emit = |global_state: &mut str_state::State, event: Event| -> Vec<_> {
+        global_state
+            .with_raw_event(event, &mapping_cache)
+            .into_iter()
+            .filter_map(|event| event.event.into_event())
+            .collect()
+    };
+    let press = |time| RawEvent::KeyboardPress(SwitchEvent::new(time, "K"));
+    let release = |time| RawEvent::KeyboardRelease(SwitchEvent::new(time, "K"));
+    let text = |time, input| RawEvent::TextInput(TextInputEvent::new(time, input));
+
+    // Keys typed into a composition do not fire shortcuts.
+    let mut global_state = new_state();
+    assert_eq!(emit(&mut global_state, press(0)), vec!["Kill"]);
+    assert_eq!(emit(&mut global_state, release(50)), vec!["ClickK"]);
+    assert!(emit(&mut global_state, text(100, TextInput::CompositionStart)).is_empty());
+    assert!(emit(&mut global_state, press(110)).is_empty());
+    assert!(emit(&mut global_state, release(150)).is_empty());
+    let result = global_state.with_text_input_event(TextInputEvent::new(
+        160,
+        TextInput::CompositionUpdate("か".to_owned()),
+    ));
+    assert_eq!(
+        result.bindings,
+        Some(TextInputEventData {
+            inserted: None,
+            preedit: Some("か".to_owned()),
+        })
+    );
+    assert_eq!(global_state.text_input_state.composition(), Some("か"));
+    let json = r#"{"kind":"text_input","time":200,"input":{"composition_commit":"か"}}"#;
+    let commit: Event = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        global_state.with_raw_event(commit, &mapping_cache),
+        vec![ReplayedEvent::text_input(
+            200,
+            TextInputEventData {
+                inserted: Some("か".to_owned()),
+                preedit: None,
+            }
+        )]
+    );
+    assert!(!global_state.text_input_state.is_composing());
+    assert!(global_state
+        .with_timeouts_until(&1000, &mapping_cache)
+        .is_empty());
+    assert_eq!(emit(&mut global_state, press(1000)), vec!["Kill"]);
+    assert_eq!(emit(&mut global_state, release(1050)), vec!["ClickK"]);
+
+    // Plain text keeps the composition as it is.
+    let result = global_state
+        .with_text_input_event(TextInputEvent::new(1100, TextInput::Text("a".to_owned())));
+    assert_eq!(
+        result.bindings,
+        Some(TextInputEventData {
+            inserted: Some("a".to_owned()),
+            preedit: None,
+        })
+    );
+
+    // The key that commits the composition is released after the commit.
+    assert!(emit(&mut global_state, text(1200, TextInput::CompositionStart)).is_empty());
+    assert!(emit(&mut global_state, press(1210)).is_empty());
+    let commit = TextInput::CompositionCommit("か".to_owned());
+    assert!(emit(&mut global_state, text(1220, commit)).is_empty());
+    assert!(emit(&mut global_state, release(1260)).is_empty());
+    assert!(global_state
+        .with_timeouts_until(&2000, &mapping_cache)
+        .is_empty());
+    assert_eq!(emit(&mut global_state, press(2000)), vec!["Kill"]);
+    assert_eq!(emit(&mut global_state, release(2050)), vec!["ClickK"]);
+
+    // Updates without a start are dropped or start the composition.
+    let update = TextInputEvent::new(500, TextInput::CompositionUpdate("x".to_owned()));
+    let mut strict = new_state();
+    assert_eq!(strict.with_text_input_event(update.clone()).bindings, None);
+    assert!(!strict.text_input_state.is_composing());
+    assert_eq!(strict.recovery.diagnostics().len(), 1);
+    let mut resync = new_state();
+    resync.recovery.policy = RecoveryPolicy::Resync;
+    let result = resync.with_text_input_event(update);
+    assert_eq!(result.bindings.unwrap().preedit, Some("x".to_owned()));
+    assert_eq!(
+        resync.recovery.diagnostics()[0].source,
+        "input_core::TextInputState::on_input_event"
+    );
+
+    // Focus loss cancels the composition.
+    let _ = resync.with_focus_lost_event(600, SyntheticReleases::Suppress, &mapping_cache);
+    assert!(!resync.text_input_state.is_composing());
+}
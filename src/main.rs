@@ -1,38 +1,339 @@
 #[cfg(test)]
 mod exercise;
 
-pub fn main() {}
-
-// use serde_json::json;
-// use std::io::{self, Read};
-// pub fn main() -> io::Result<()> {
-//     let stdin = io::stdin();
-//     let mut app = App::default();
-
-//     loop {
-//         let mut buffer = String::new();
-//         stdin.read_line(&mut buffer)?;
-//         if buffer == "exit\n" {
-//             break;
-//         }
-
-//         app.push(&buffer.as_str());
-
-//         // let ev = serde_json::from_str(&buffer);
-//         // match ev {
-//         //     Err(err) => {
-//         //         // parse as en error
-//         //         app.events.push(Event::Error(err.to_string()));
-//         //     }
-//         //     Ok(ev) => {
-//         //         app.events.push(ev);
-//         //     }
-//         // }
-
-//         //app.push(buffer.trim());
-
-//         println!("{:?}", app);
-//     }
-
-//     Ok(())
-// }
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use std::{env, fs, process, thread};
+
+use input_core::{
+    AnalogState, AnalogThreshold, AxisState, ClickExactHandleRequest, CoordsState, DragPolicy,
+    FlingPolicy, GestureState, KineticHandleRequest, KineticPolicy, LongPressHandleRequest,
+    Modifiers, NumPossibleClicks, PenState, PenTool, PenToolSwitches, PointerState,
+    RepeatSchedulerState, RepeatState, SequenceSchedulerState, SequenceState, TimedPolicies,
+    TimedPolicy, TimedState,
+};
+use input_more::{
+    DeviceMappingCache, DeviceSchedulerState, GlobalMappingCache, GlobalState, KeyboardLane,
+    MappingModifiersCache, MouseLane, PenLane, RawEvent, ReplayedEvent, SequenceStep, TouchLane,
+};
+
+type TimestampMs = i64;
+type DurationMs = i64;
+type Switch = String;
+type Trigger = String;
+type Coords = (f64, f64);
+type Command = String;
+
+type Event = RawEvent<TimestampMs, Switch, Trigger, Switch, Trigger, Switch, Coords>;
+type AppEvent = ReplayedEvent<TimestampMs, Command>;
+
+type DeviceMapping = DeviceMappingCache<Switch, Trigger, Switch, Command>;
+type MappingCache = GlobalMappingCache<
+    DeviceMapping,
+    DeviceMapping,
+    DeviceMapping,
+    DeviceMapping,
+    MappingModifiersCache<Switch>,
+>;
+
+type Scheduler<Re> = DeviceSchedulerState<TimestampMs, Switch, Switch, Coords, Re>;
+type Timed = TimedState<Switch, DurationMs>;
+type Pointer = PointerState<Switch, Coords, TimestampMs, DurationMs>;
+
+type State = GlobalState<
+    Modifiers<Switch>,
+    KeyboardLane<
+        CoordsState<Coords>,
+        Timed,
+        Scheduler<LongPressHandleRequest>,
+        Scheduler<ClickExactHandleRequest>,
+        Pointer,
+        SequenceState<SequenceStep<Switch, Switch>, DurationMs>,
+        SequenceSchedulerState<TimestampMs, ()>,
+        RepeatState<Switch, DurationMs>,
+        RepeatSchedulerState<TimestampMs, Switch>,
+        AnalogState<Trigger, Switch>,
+    >,
+    MouseLane<
+        CoordsState<Coords>,
+        Timed,
+        Scheduler<LongPressHandleRequest>,
+        Scheduler<ClickExactHandleRequest>,
+        Pointer,
+        AxisState<Trigger>,
+        Scheduler<KineticHandleRequest>,
+    >,
+    TouchLane<
+        CoordsState<Coords>,
+        Timed,
+        Scheduler<LongPressHandleRequest>,
+        Scheduler<ClickExactHandleRequest>,
+        Pointer,
+        GestureState<Switch, Coords>,
+        Scheduler<KineticHandleRequest>,
+    >,
+    PenLane<
+        CoordsState<Coords>,
+        Timed,
+        Scheduler<LongPressHandleRequest>,
+        Scheduler<ClickExactHandleRequest>,
+        Pointer,
+        PenState<Switch>,
+    >,
+>;
+
+const LONG_PRESS_DURATION: DurationMs = 500;
+const CLICK_EXACT_DURATION: DurationMs = 300;
+const MAX_CLICKS: NumPossibleClicks = 3;
+const SEQUENCE_TIMEOUT: DurationMs = 1000;
+const DRAG_DISTANCE: f64 = 4.0;
+const DRAG_HYSTERESIS: f64 = 1.0;
+const AXIS_STEP: f64 = 120.0;
+const FLING_WINDOW: DurationMs = 100;
+// In pixels per millisecond.
+const FLING_MIN_SPEED: f64 = 0.5;
+const KINETIC_INTERVAL: DurationMs = 16;
+const KINETIC_FRICTION: f64 = 0.95;
+const KINETIC_MIN_SPEED: f64 = 0.05;
+const PEN_HARD_PRESS: f64 = 0.6;
+const PEN_HARD_RELEASE: f64 = 0.5;
+
+const USAGE: &str = "usage: sunshine <keymap.json> [--clock virtual|wall]";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ClockKind {
+    Virtual,
+    Wall,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct WallClock {
+    origin: Option<(Instant, TimestampMs)>,
+}
+
+pub fn main() {
+    let (keymap_path, clock) = parse_args(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        process::exit(2);
+    });
+    let keymap = fs::read_to_string(&keymap_path).unwrap_or_else(|err| {
+        eprintln!("Failed to read keymap {:?}: {}", keymap_path, err);
+        process::exit(1);
+    });
+    let mapping = MappingCache::from_keymap(&keymap).unwrap_or_else(|err| {
+        eprintln!("Invalid keymap {:?}: {}", keymap_path, err);
+        process::exit(1);
+    });
+
+    let result = match clock {
+        ClockKind::Virtual => run_virtual(&mapping),
+        ClockKind::Wall => run_wall(&mapping),
+    };
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(String, ClockKind), String> {
+    let mut keymap_path = None;
+    let mut clock = ClockKind::Virtual;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--clock" => {
+                clock = match args.next().as_deref() {
+                    Some("virtual") => ClockKind::Virtual,
+                    Some("wall") => ClockKind::Wall,
+                    Some(other) => return Err(format!("Unknown clock {:?}", other)),
+                    None => return Err("Missing value for --clock".to_owned()),
+                }
+            }
+            _ if keymap_path.is_none() => keymap_path = Some(arg),
+            _ => return Err(format!("Unexpected argument {:?}", arg)),
+        }
+    }
+    let keymap_path = keymap_path.ok_or_else(|| "Missing keymap path".to_owned())?;
+    Ok((keymap_path, clock))
+}
+
+fn new_state() -> State {
+    let timed_policy = TimedPolicy::new(LONG_PRESS_DURATION, CLICK_EXACT_DURATION, MAX_CLICKS);
+    let drag_policy = DragPolicy::new(DRAG_DISTANCE, DRAG_HYSTERESIS, None);
+    let kinetic_policy = KineticPolicy::new(KINETIC_INTERVAL, KINETIC_FRICTION, KINETIC_MIN_SPEED);
+    let fling_policy = FlingPolicy::new(FLING_WINDOW, FLING_MIN_SPEED).with_kinetic(kinetic_policy);
+    let pen_hard = AnalogThreshold::new("PenHard".to_owned(), PEN_HARD_PRESS, PEN_HARD_RELEASE);
+    let pen_state = PenState::default()
+        .with_tool(
+            PenTool::Pen,
+            PenToolSwitches::new("PenTip".to_owned()).with_threshold(pen_hard),
+        )
+        .with_tool(
+            PenTool::Eraser,
+            PenToolSwitches::new("PenEraser".to_owned()),
+        )
+        .with_barrel("PenBarrel".to_owned());
+    let keyboard = KeyboardLane::new(
+        CoordsState::with_coords((0.0, 0.0)),
+        Timed::new(TimedPolicies::new(timed_policy)),
+        Scheduler::default(),
+        Scheduler::default(),
+        Pointer::new(drag_policy),
+        SequenceState::new(SEQUENCE_TIMEOUT),
+        SequenceSchedulerState::default(),
+        // The host sends key repeats itself.
+        RepeatState::new(None),
+        RepeatSchedulerState::default(),
+        AnalogState::default(),
+    );
+    let mouse = MouseLane::new(
+        CoordsState::with_coords((0.0, 0.0)),
+        Timed::new(TimedPolicies::new(timed_policy)),
+        Scheduler::default(),
+        Scheduler::default(),
+        Pointer::new(drag_policy).with_fling_policy(fling_policy),
+        AxisState::new(AXIS_STEP),
+        Scheduler::default(),
+    );
+    let touch = TouchLane::new(
+        CoordsState::with_coords((0.0, 0.0)),
+        Timed::new(TimedPolicies::new(timed_policy)),
+        Scheduler::default(),
+        Scheduler::default(),
+        Pointer::new(drag_policy).with_fling_policy(fling_policy),
+        GestureState::default(),
+        Scheduler::default(),
+    );
+    let pen = PenLane::new(
+        CoordsState::with_coords((0.0, 0.0)),
+        Timed::new(TimedPolicies::new(timed_policy)),
+        Scheduler::default(),
+        Scheduler::default(),
+        Pointer::new(drag_policy),
+        pen_state,
+    );
+    GlobalState::new(Modifiers::default(), keyboard, mouse, touch, pen)
+}
+
+fn parse_event(line_number: usize, line: &str) -> Option<Event> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    match serde_json::from_str(line) {
+        Ok(event) => Some(event),
+        Err(err) => {
+            eprintln!("Invalid event at line {}: {}", line_number, err);
+            None
+        }
+    }
+}
+
+fn write_events(out: &mut impl Write, events: Vec<AppEvent>) -> io::Result<()> {
+    if events.is_empty() {
+        return Ok(());
+    }
+    for event in events {
+        serde_json::to_writer(&mut *out, &event)?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()
+}
+
+fn report_diagnostics(state: &mut State) {
+    for diagnostic in state.recovery.take_diagnostics() {
+        eprintln!("{}: {}", diagnostic.source, diagnostic.message);
+    }
+}
+
+fn run_virtual(mapping: &MappingCache) -> io::Result<()> {
+    let mut state = new_state();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    for (j, line) in io::stdin().lock().lines().enumerate() {
+        let event = match parse_event(j + 1, &line?) {
+            Some(event) => event,
+            None => continue,
+        };
+        write_events(&mut out, state.with_timeouts_until(event.time(), mapping))?;
+        write_events(&mut out, state.with_raw_event(event, mapping))?;
+        report_diagnostics(&mut state);
+    }
+    flush_timeouts(&mut state, mapping, &mut out)
+}
+
+fn run_wall(mapping: &MappingCache) -> io::Result<()> {
+    let mut state = new_state();
+    let mut clock = WallClock::default();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut line_number = 0;
+    loop {
+        let wait = clock
+            .now()
+            .zip(state.next_scheduled())
+            .map(|(now, deadline)| Duration::from_millis((deadline - now).max(0) as u64));
+        let line = match wait {
+            Some(wait) => match receiver.recv_timeout(wait) {
+                Ok(line) => line?,
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(now) = clock.now() {
+                        write_events(&mut out, state.with_timeouts_until(&now, mapping))?;
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match receiver.recv() {
+                Ok(line) => line?,
+                Err(_) => break,
+            },
+        };
+
+        line_number += 1;
+        let event = match parse_event(line_number, &line) {
+            Some(event) => event,
+            None => continue,
+        };
+        clock.sync(*event.time());
+        write_events(&mut out, state.with_timeouts_until(event.time(), mapping))?;
+        write_events(&mut out, state.with_raw_event(event, mapping))?;
+        report_diagnostics(&mut state);
+    }
+    flush_timeouts(&mut state, mapping, &mut out)
+}
+
+fn flush_timeouts(
+    state: &mut State,
+    mapping: &MappingCache,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    state.stop_repeats();
+    while let Some(time) = state.next_scheduled().cloned() {
+        write_events(out, state.with_timeouts_until(&time, mapping))?;
+    }
+    Ok(())
+}
+
+impl WallClock {
+    fn now(&self) -> Option<TimestampMs> {
+        self.origin
+            .map(|(instant, time)| time + instant.elapsed().as_millis() as TimestampMs)
+    }
+
+    fn sync(&mut self, time: TimestampMs) {
+        if self.origin.is_none() {
+            self.origin = Some((Instant::now(), time));
+        }
+    }
+}